@@ -48,6 +48,20 @@ impl ClientUI for Client1_UI {
     fn get_viewport_id(&self) -> u64 {
         self.self_id.into()
     }
+
+    // Forgets the server, so the probe completes when the answer to server_type? stores it again
+    fn send_probe(&mut self, server_id: NodeId) {
+        self.servers.lock().expect("Failed to lock").remove(&server_id);
+        self.cmd_snd
+            .as_ref()
+            .expect("Failed to get value")
+            .send(format!("server_type?->{}", server_id))
+            .expect("Failed to send");
+    }
+
+    fn probe_completed(&self, server_id: NodeId) -> bool {
+        self.servers.lock().expect("Failed to lock").contains_key(&server_id)
+    }
 }
 
 impl Client1_UI {
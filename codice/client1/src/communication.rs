@@ -10,7 +10,7 @@ use crate::logger::logger::write_log;
 //Communication part related to the Client
 impl Client1 {
     pub fn handle_command(&mut self, command: String) -> String{
-    let available_simple_commands = vec!["server_type?","files_list?","client_list?"];
+    let available_simple_commands = vec!["server_type?","files_list?","client_list?","unregister?","messages?"];
         if let Some((cmd,dest)) = command.split_once("->") {
 
             let dest_id = dest.parse::<NodeId>().expect("Failed to parse a correct destination");
@@ -52,9 +52,18 @@ impl Client1 {
                         "Error: command not formatted correctly".to_string()
                     }
                 }
+                cmd if cmd.starts_with("register?(") && cmd.ends_with(")") => {
+                    self.selected_server = dest_id;
+                    self.send_message(dest_id, cmd);
+                    "CLIENT1: OK".to_string()
+                }
                 cmd if cmd.starts_with("message_for?(") => {
                     let dest_id = Self::get_values(cmd.to_string().clone().as_str()).expect("Failed to get values").0;
                     if self.other_client_ids.lock().expect("Failed to lock").contains(&dest_id) {
+                        // An offline client is still registered, the server keeps the message until it's back
+                        if self.offline_client_ids.contains(&dest_id) {
+                            self.msg_snd.send(format!("Client {} is offline, the message will wait on the server", dest_id)).expect("Failed to send message");
+                        }
                         self.send_message(self.selected_server, cmd);
                         "CLIENT1: OK".to_string()
                     } else {
@@ -93,8 +102,12 @@ impl Client1 {
                         Ok(_) =>{
                                 match self.receiver_channel.recv(){
                                     Ok(packet) =>{
-                                        match packet.pack_type{
-                                            PacketType::Nack(nack) =>{
+                                        let nack = match &packet.pack_type{
+                                            PacketType::Nack(nack) => Some(nack.clone()),
+                                            _ => None
+                                        };
+                                        match nack{
+                                            Some(nack) =>{
                                                 // In case I receive a Nack with same session_id I need to send again the message.
                                                 //self.redo_network();
                                                 match Self::bfs_compute_path(&self.network,self.node_id,dest_id){
@@ -107,7 +120,8 @@ impl Client1 {
                                                     None =>{write_log("Error communication.rs: no path to the dest_id, error 1")}
                                                 }
                                             }
-                                            _=> ()
+                                            // Anything else (the Ack or a message pushed by a server) is handled as in the main loop
+                                            None => self.handle_packet(packet)
                                         }
                                     }
                                     Err(e) => {write_log(&format!("Error communication.rs: {}",e))}
@@ -204,6 +218,7 @@ impl Client1 {
                             if !other_cl.contains(e){
                                 other_cl.push(*e);
                             }
+                            self.offline_client_ids.remove(e);
                         }
                         "".to_string()
                         //format!("[{}]", val.iter().map(|node| node.to_string()).collect::<Vec<String>>().join(","))
@@ -222,6 +237,72 @@ impl Client1 {
                     None => "Failed to get message content".to_string()
                 }
             }
+            msg if msg.starts_with("message_delivered!(") && msg.ends_with(")") =>{
+                // Receipt of a message sent to another client
+                match msg.strip_prefix("message_delivered!(").and_then(|s|s.strip_suffix(")")).and_then(|s|s.split_once(",")){
+                    Some((id, content)) => format!("Delivered to {}: {}", id, content),
+                    None => "Failed to get delivery receipt".to_string()
+                }
+            }
+            msg if msg.starts_with("message_undelivered!(") && msg.ends_with(")") =>{
+                // The other client unregistered before the message could reach it
+                match msg.strip_prefix("message_undelivered!(").and_then(|s|s.strip_suffix(")")).and_then(|s|s.split_once(",")){
+                    Some((id, content)) => format!("Not delivered to {}: {}", id, content),
+                    None => "Failed to get delivery receipt".to_string()
+                }
+            }
+            msg if msg.starts_with("message_queued!(") && msg.ends_with(")") =>{
+                // The other client is offline, the server keeps the message until it comes back
+                match msg.strip_prefix("message_queued!(").and_then(|s|s.strip_suffix(")")).and_then(|s|s.split_once(",")){
+                    Some((id, content)) => format!("Queued for {} (offline): {}", id, content),
+                    None => "Failed to get delivery receipt".to_string()
+                }
+            }
+            msg if msg.starts_with("client_joined!(") && msg.ends_with(")") =>{
+                // Another client is online: "client_joined!(id)" or "client_joined!(id,name)"
+                let raw_data = msg.strip_prefix("client_joined!(").and_then(|s|s.strip_suffix(")")).unwrap_or("");
+                match raw_data.split(",").next().and_then(|id|id.parse::<NodeId>().ok()){
+                    Some(id) =>{
+                        let mut other_cl = self.other_client_ids.lock().expect("Failed to lock");
+                        if id != self.node_id && !other_cl.contains(&id){
+                            other_cl.push(id);
+                        }
+                        self.offline_client_ids.remove(&id);
+                        "".to_string()
+                    }
+                    None => "Failed to get the id of the new client".to_string()
+                }
+            }
+            msg if msg.starts_with("client_left!(") && msg.ends_with(")") =>{
+                match msg.strip_prefix("client_left!(").and_then(|s|s.strip_suffix(")")).and_then(|id|id.parse::<NodeId>().ok()){
+                    Some(id) =>{
+                        // Kept in the list: a message for it waits on the server until it's back
+                        self.offline_client_ids.insert(id);
+                        "".to_string()
+                    }
+                    None => "Failed to get the id of the client that left".to_string()
+                }
+            }
+            msg if msg.starts_with("registered!(") && msg.ends_with(")") =>{
+                match msg.strip_prefix("registered!(").and_then(|s|s.strip_suffix(")")).and_then(|id|id.parse::<NodeId>().ok()){
+                    Some(id) => format!("Registered to the server as client {}", id),
+                    None => "Failed to get the registered id".to_string()
+                }
+            }
+            msg if msg.starts_with("unregistered!(") && msg.ends_with(")") =>{
+                match msg.strip_prefix("unregistered!(").and_then(|s|s.strip_suffix(")")).and_then(|id|id.parse::<NodeId>().ok()){
+                    Some(id) => format!("Client {} is no longer registered to the server", id),
+                    None => "Failed to get the unregistered id".to_string()
+                }
+            }
+            msg if msg.starts_with("messages!(") && msg.ends_with(")") =>{
+                // Messages kept by the server while the client was unreachable, each one follows as a message_from!
+                match msg.strip_prefix("messages!(").and_then(|s|s.strip_suffix(")")).and_then(|n|n.parse::<usize>().ok()){
+                    Some(0) => "No messages were waiting on the server".to_string(),
+                    Some(n) => format!("{} messages were waiting on the server", n),
+                    None => "Failed to get the number of waiting messages".to_string()
+                }
+            }
             _ => "Error: return message not correctly formatted".to_string()
         }
     }
@@ -313,7 +394,7 @@ mod test{
         // Initialize dummy client
         let (snd,rcv) = unbounded::<Packet>();
         let (_,rcv_id) = unbounded::<NodeId>();
-        let mut cl = Client1::new(1, HashMap::new(), rcv, rcv_id);
+        let mut cl = Client1::new(1, HashMap::new(), rcv, rcv_id, unbounded().1);
         cl.0.sender_channels.insert(2,snd);
         cl.0.network.insert(1,vec![2]);
         cl.0.other_client_ids.lock().expect("Failed to lock").push(2);
@@ -358,4 +439,24 @@ mod test{
         let test_msg8 = "test_error".to_string();
         //assert_eq!(cl.handle_msg(test_msg8,3,2,0),"Error");
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_offline_client_still_addressable(){
+        let (snd,rcv) = unbounded::<Packet>();
+        let (_,rcv_id) = unbounded::<NodeId>();
+        let mut cl = Client1::new(1, HashMap::new(), unbounded().1, rcv_id, unbounded().1);
+        cl.0.sender_channels.insert(2,snd);
+        cl.0.network.insert(1,vec![2]);
+        cl.0.network.insert(2,vec![1,3]);
+        cl.0.network.insert(3,vec![2]);
+        cl.0.selected_server = 3;
+        cl.0.handle_msg("client_joined!(4)".to_string(),3);
+        cl.0.handle_msg("client_left!(4)".to_string(),3);
+        assert!(cl.0.other_client_ids.lock().expect("Failed to lock").contains(&4));
+
+        // The message goes to the server, that keeps it in the mailbox of the client 4
+        assert_eq!(cl.0.handle_command("message_for?(4,hi)->3".to_string()),"CLIENT1: OK");
+        assert_eq!(rcv.try_iter().count(),1);
+        cl.0.handle_msg("client_list!([1, 4])".to_string(),3);
+        assert!(cl.0.offline_client_ids.is_empty());
+    }
+}
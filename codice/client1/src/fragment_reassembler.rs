@@ -1,5 +1,6 @@
 use base64::{engine::general_purpose, Engine as _};
-use std::collections::{HashMap};
+use common::completed_sessions::CompletedSessions;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::fs::File;
 use std::io::Write;
@@ -12,7 +13,8 @@ use crate::Client1;
 // Struct FragmentReassembler to manage reassembly of fragments
 pub struct FragmentReassembler {
     pub buffer: HashMap<(u64, NodeId), Vec<u8>>, // Map of (session_id, source_id) to message buffer
-    pub processed_fragments: HashMap<(u64, NodeId), u64>, // Number of received fragments per (session_id, source_id)
+    pub processed_fragments: HashMap<(u64, NodeId), HashSet<u64>>, // Indexes of the received fragments per (session_id, source_id)
+    completed: CompletedSessions<(u64, NodeId)>, // Last reassembled (session_id, source_id), to recognise late duplicates
 }
 
 impl FragmentReassembler {
@@ -21,11 +23,24 @@ impl FragmentReassembler {
         Self {
             buffer: HashMap::new(),
             processed_fragments: HashMap::new(),
+            completed: CompletedSessions::default(),
         }
     }
-    // Add a fragment in the buffer and proceed to assemble the message as a Vec is all fragments received
+    // Add a fragment in the buffer and proceed to assemble the message as a Vec is all fragments received.
+    // Fragments can arrive in any order; a duplicate (a retransmission whose ack was only slow) is
+    // ignored and returns Ok(None), so that it is acked again like any fragment of an incomplete message
     pub fn add_fragment(&mut self, session_id: u64, source_id: NodeId, fragment: Fragment) -> Result<Option<Vec<u8>>,String> {
         let key = (session_id, source_id);
+        if fragment.fragment_index >= fragment.total_n_fragments {
+            return Err(format!("Fragment {} of a message of {} fragments", fragment.fragment_index, fragment.total_n_fragments));
+        }
+        if self.completed.contains(&key) {
+            return Ok(None);
+        }
+        let received = self.processed_fragments.entry(key).or_default();
+        if !received.insert(fragment.fragment_index) {
+            return Ok(None);
+        }
 
         // Initialize tracking structures for this (session_id, source_id) pair if needed
         if !self.buffer.contains_key(&key) {
@@ -41,14 +56,14 @@ impl FragmentReassembler {
         let end = start + fragment.length as usize;
         buffer[start..end].copy_from_slice(&fragment.data[..fragment.length as usize]);
 
-        // Update count of received fragments
-        *self.processed_fragments.entry(key).or_insert(0) += 1;
-        if self.processed_fragments.get(&key).expect("Failed to get value").eq(&(fragment.total_n_fragments)) {
+        // Every index has been received
+        if received.len() as u64 == fragment.total_n_fragments {
             // Reassemble the message
             let message = self.buffer.remove(&key).unwrap_or_default();
 
             //Clean tracking structures
             self.processed_fragments.remove(&key);
+            self.completed.insert(key);
 
             //let total_length = ((fragment.total_n_fragments - 1) * 128 + fragment.length as u64) as usize;
             //let message = buffer[..total_length].to_vec();
@@ -138,7 +153,7 @@ mod test{
     fn test_fragment_string_assembled_correctly(){
         let (_,rcv) = unbounded::<Packet>();
         let (_,rcv_id) = unbounded::<NodeId>();
-        let mut client_test = Client1::new(1, HashMap::new(), rcv,rcv_id);
+        let mut client_test = Client1::new(1, HashMap::new(), rcv, rcv_id, unbounded().1);
         let mut fr = FragmentReassembler::new();
         let test_data = &"A".repeat(200);
         let test_result = FragmentReassembler::generate_fragments(test_data);
@@ -150,10 +165,51 @@ mod test{
         assert!(test_data.eq(&res.unwrap()));
     }
     #[test]
+    fn test_fragments_out_of_order_and_duplicated(){
+        let mut fr = FragmentReassembler::new();
+        let test_data = "B".repeat(500);
+        let mut fragments = FragmentReassembler::generate_fragments(&test_data).unwrap();
+        fragments.reverse();
+        let last = fragments.pop().unwrap();
+        for e in fragments.iter(){
+            assert_eq!(fr.add_fragment(1,1,e.clone()), Ok(None));
+            // A retransmitted fragment must not complete the message
+            assert_eq!(fr.add_fragment(1,1,e.clone()), Ok(None));
+        }
+        let message = fr.add_fragment(1,1,last.clone()).unwrap().unwrap();
+        assert_eq!(FragmentReassembler::assemble_string_file(message).unwrap(), test_data);
+        // A duplicate arriving after the message was reassembled doesn't start a new one
+        assert_eq!(fr.add_fragment(1,1,last), Ok(None));
+        assert!(fr.buffer.is_empty());
+    }
+    #[test]
+    fn test_fragments_of_two_paths_reordered(){
+        // A files list spread by the server on two paths, the second one delivers its fragments late and reversed
+        let names: Vec<String> = (0..120).map(|i| format!("reordered_file_{i:03}.txt")).collect();
+        let test_data = format!("files_list!([{}])", names.join(", "));
+        let fragments = FragmentReassembler::generate_fragments(&test_data).unwrap();
+        assert!(fragments.len() > 16);
+        let (first, second): (Vec<Fragment>, Vec<Fragment>) = fragments.into_iter().partition(|f| f.fragment_index % 2 == 0);
+        let mut fr = FragmentReassembler::new();
+        for e in first.iter(){
+            assert_eq!(fr.add_fragment(7, 20, e.clone()), Ok(None));
+        }
+        let mut message = None;
+        for e in second.iter().rev(){
+            assert_eq!(message, None);
+            message = fr.add_fragment(7, 20, e.clone()).unwrap();
+        }
+        let res = FragmentReassembler::assemble_string_file(message.unwrap()).unwrap();
+        assert_eq!(res, test_data);
+        for name in names.iter(){
+            assert!(res.contains(name.as_str()));
+        }
+    }
+    #[test]
     fn test_fragment_txt_assembled_correctly(){
         let (_,rcv_id) = unbounded::<NodeId>();
         let (_,rcv) = unbounded::<Packet>();
-        let mut client_test = Client1::new(1, HashMap::new(), rcv,rcv_id);
+        let mut client_test = Client1::new(1, HashMap::new(), rcv, rcv_id, unbounded().1);
         let test_text_content = fs::read("src/test/file1");
         let test_result = FragmentReassembler::assemble_string_file(test_text_content.unwrap());
         assert_eq!(test_result.unwrap(),"test 123456 advanced_programming");
@@ -163,7 +219,7 @@ mod test{
     fn test_fragment_mediaFile_assembled_correctly(){
         let (_,rcv_id) = unbounded::<NodeId>();
         let (_,rcv) = unbounded::<Packet>();
-        let mut client_test = Client1::new(1, HashMap::new(), rcv,rcv_id);
+        let mut client_test = Client1::new(1, HashMap::new(), rcv, rcv_id, unbounded().1);
         let test_text_content = fs::read("src/test/testMedia.mp3");
         let test_result = FragmentReassembler::assemble_string_file(test_text_content.unwrap());
         match test_result{
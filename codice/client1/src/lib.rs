@@ -30,9 +30,11 @@ mod fragment_reassembler;
 mod logger;
 use crate::client1_ui::Client1_UI;
 use crate::logger::logger::{init_logger, write_log};
-use crossbeam_channel::{select_biased, unbounded, Receiver, Sender};
+use common::introspection::{DiscoveredView, IntrospectionRequest};
+use common::presence::{keepalive_targets, KEEPALIVE_INTERVAL, KEEPALIVE_REQUEST};
+use crossbeam_channel::{select_biased, tick, unbounded, Receiver, Sender};
 use fragment_reassembler::*;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::{env, thread};
 use std::io::Write;
 use std::sync::{Arc, Condvar, Mutex};
@@ -49,11 +51,13 @@ pub struct Client1 {
     sender_channels: HashMap<NodeId, Sender<Packet>>,
     receiver_channel: Receiver<Packet>,
     crashed_drone_rcv: Receiver<NodeId>,
+    introspection_rcv: Receiver<IntrospectionRequest>, // Requests from the controller for the discovered network
     flood_ids: Vec<(u64, NodeId)>,
     network: Graph,
     fragment_reassembler: FragmentReassembler, // Used to handle fragments
     received_files: Vec<String>,               // Path where to save files received
     other_client_ids: Arc<Mutex<Vec<NodeId>>>, // Storage other client IDs
+    offline_client_ids: HashSet<NodeId>, // Registered clients the server reported as offline
     files_names: Arc<Mutex<HashMap<NodeId, Vec<String>>>>, // Storage of file names
     servers: Arc<Mutex<HashMap<NodeId, String>>>, // map of servers ID and relative type
     packet_sent: AckMap,
@@ -61,7 +65,9 @@ pub struct Client1 {
     selected_server: NodeId,
     cmd_rcv: Receiver<String>,
     msg_snd: Sender<String>,
-    redo_discovery: Arc<(Mutex<bool>,Condvar)>
+    redo_discovery: Arc<(Mutex<bool>,Condvar)>,
+    keepalive_ticker: Receiver<Instant>,
+    keepalive_pending: HashSet<NodeId> // Servers whose client_list! answers a keepalive, not the user
 }
 
 impl Client1 {
@@ -70,7 +76,8 @@ impl Client1 {
         node_id: NodeId,
         sender_channels: HashMap<NodeId, Sender<Packet>>,
         receiver_channel: Receiver<Packet>,
-        crashed_drone_rcv: Receiver<NodeId>
+        crashed_drone_rcv: Receiver<NodeId>,
+        introspection_rcv: Receiver<IntrospectionRequest>
     ) -> (Self, Client1_UI) {
         let other_client_ids = Arc::new(Mutex::new(vec![]));
         let files_names = Arc::new(Mutex::new(HashMap::new()));
@@ -95,11 +102,13 @@ impl Client1 {
                 sender_channels,
                 receiver_channel,
                 crashed_drone_rcv,
+                introspection_rcv,
                 flood_ids: vec![],
                 network: Graph::new(),
                 fragment_reassembler: FragmentReassembler::new(),
                 received_files: vec![],
                 other_client_ids,
+                offline_client_ids: HashSet::new(),
                 files_names,
                 servers,
                 packet_sent,
@@ -107,7 +116,9 @@ impl Client1 {
                 selected_server: NodeId::default(),
                 cmd_rcv,
                 msg_snd,
-                redo_discovery: discovery_complete
+                redo_discovery: discovery_complete,
+                keepalive_ticker: tick(KEEPALIVE_INTERVAL),
+                keepalive_pending: HashSet::new()
             },
             client_ui,
         )
@@ -259,11 +270,18 @@ impl Client1 {
             PacketType::MsgFragment(fragment) => {
                 let frag_index = fragment.fragment_index;
                 // Check if a fragment with the same (session_id,src_id) has already been received
-                match self
-                    .fragment_reassembler
-                    .add_fragment(packet.session_id, packet.routing_header.hops[0], fragment)
-                    .expect("Failed to add fragment")
-                {
+                let reassembled = match self.fragment_reassembler.add_fragment(
+                    packet.session_id,
+                    packet.routing_header.hops[0],
+                    fragment,
+                ) {
+                    Ok(reassembled) => reassembled,
+                    Err(e) => {
+                        write_log(&format!("Error: {e}"));
+                        return;
+                    }
+                };
+                match reassembled {
                     Some(message) => {
                         match FragmentReassembler::assemble_string_file(message.clone()) {
                             // Check FragmentReassembler output and behave accordingly
@@ -274,11 +292,21 @@ impl Client1 {
                                 let new_first_hop = new_hops[1];
                                 //write_log(msg.as_str());
                                 //Handle the reconstructed message
+                                // The answer to a keepalive only updates the client list, the user didn't ask for it
+                                let keepalive_answer = msg.starts_with("client_list!(") && self.keepalive_pending.remove(&dest_id);
                                 if msg.starts_with("server_type!(")
-                                    || msg.starts_with("client_list!(")
                                     || msg.starts_with("files_list!(")
+                                    || msg.starts_with("client_joined!(")
+                                    || msg.starts_with("client_left!(")
+                                    || keepalive_answer
                                 {
                                     self.handle_msg(msg, dest_id);
+                                } else if msg.starts_with("client_list!(") {
+                                    // The list is shown from other_client_ids, only an empty one is reported
+                                    let response = self.handle_msg(msg, dest_id);
+                                    if !response.is_empty() {
+                                        self.msg_snd.send(response).expect("Failed to send message");
+                                    }
                                 } else {
                                     self.msg_snd
                                         .clone()
@@ -554,6 +582,28 @@ impl Client1 {
         }
     }
 
+    // Knowledge of the network, sessions waiting for acks and known nodes
+    pub fn discovered_view(&self) -> DiscoveredView {
+        DiscoveredView::from_graph(self.node_id, &self.network).with_known(
+            self.packet_sent
+                .lock()
+                .expect("Failed to lock")
+                .keys()
+                .map(|(session_id, _)| *session_id)
+                .collect::<Vec<_>>(),
+            self.servers
+                .lock()
+                .expect("Failed to lock")
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            self.other_client_ids
+                .lock()
+                .expect("Failed to lock")
+                .clone(),
+        )
+    }
+
     pub fn run(&mut self) {
         init_logger();
         //Initialize network field
@@ -569,7 +619,8 @@ impl Client1 {
                             Ok(packet) => {
                                     self.handle_packet(packet);
                             },
-                            Err(_) => ()
+                            // No node can reach this client anymore, the network was shut down
+                            Err(_) => return
                     }
                 }
                 recv(self.cmd_rcv) -> cmd => {
@@ -592,9 +643,32 @@ impl Client1 {
                         Err(_) => ()
                     }
                 }
+                recv(self.introspection_rcv) -> request => {
+                    match request{
+                        Ok(request) =>{
+                            // The controller may have stopped waiting for the answer
+                            let _ = request.reply.send(self.discovered_view());
+                        }
+                        Err(_) => ()
+                    }
+                }
+                recv(self.keepalive_ticker) -> _ => {
+                    self.send_keepalive();
+                }
             }
         }
     }
+    // Tell the communication servers the client is still online
+    pub fn send_keepalive(&mut self) {
+        let servers = self.servers.lock().expect("Failed to lock").clone();
+        let targets = keepalive_targets(&servers, |server_id| {
+            Self::bfs_compute_path(&self.network, self.node_id, server_id).is_some()
+        });
+        for server_id in targets {
+            self.keepalive_pending.insert(server_id);
+            self.send_message(server_id, KEEPALIVE_REQUEST);
+        }
+    }
 }
 
 // Tests for bfs and network update based on FloodResponses
@@ -606,7 +680,7 @@ mod test {
     fn test_bfs_shortest_path() {
         let (snd, rcv) = unbounded::<Packet>();
         let (_,rcv_id) = unbounded::<NodeId>();
-        let mut cl = Client1::new(1, HashMap::new(), rcv,rcv_id);
+        let mut cl = Client1::new(1, HashMap::new(), rcv, rcv_id, unbounded().1);
         cl.0.sender_channels.insert(19, snd);
         cl.0.network.insert(1, vec![2, 3]);
         cl.0.other_client_ids.lock().expect("Failed to lock").push(2);
@@ -630,7 +704,7 @@ mod test {
     fn test_bfs_no_shortest_path() {
         let (snd, rcv) = unbounded::<Packet>();
         let (_,rcv_id) = unbounded::<NodeId>();
-        let mut cl = Client1::new(1, HashMap::new(), rcv,rcv_id);
+        let mut cl = Client1::new(1, HashMap::new(), rcv, rcv_id, unbounded().1);
         cl.0.sender_channels.insert(2, snd);
         cl.0.network.insert(1, vec![2, 3]);
         cl.0.other_client_ids.lock().expect("Failed to lock").push(2);
@@ -649,7 +723,7 @@ mod test {
     fn test_update_graph() {
         let (snd, rcv) = unbounded::<Packet>();
         let (_,rcv_id) = unbounded::<NodeId>();
-        let mut cl = Client1::new(1, HashMap::new(), rcv,rcv_id);
+        let mut cl = Client1::new(1, HashMap::new(), rcv, rcv_id, unbounded().1);
         cl.0.sender_channels.insert(2, snd);
         cl.0.network.insert(1, vec![2]);
         let mut f_req = FloodRequest::new(1234, 1);
@@ -669,4 +743,50 @@ mod test {
         let test_res = Client1::bfs_compute_path(&cl.0.network, 1, 6).unwrap();
         assert_eq!(test_res, vec![1, 2, 3, 4, 5, 6]);
     }
-}
\ No newline at end of file
+    #[test]
+    fn test_keepalive_only_to_communication_servers() {
+        let (snd, rcv) = unbounded::<Packet>();
+        let (_,rcv_id) = unbounded::<NodeId>();
+        // send_message waits for the answer to each fragment
+        let (ack_snd, ack_rcv) = unbounded::<Packet>();
+        ack_snd.send(Packet::new_ack(SourceRoutingHeader::with_first_hop(vec![3, 2, 1]), 0, 0)).expect("Failed to send");
+        let mut cl = Client1::new(1, HashMap::new(), ack_rcv, rcv_id, unbounded().1);
+        cl.0.sender_channels.insert(2, snd);
+        cl.0.network.insert(1, vec![2]);
+        cl.0.network.insert(2, vec![1, 3, 4]);
+        cl.0.network.insert(3, vec![2]);
+        cl.0.network.insert(4, vec![2]);
+        cl.0.servers.lock().expect("Failed to lock").insert(3, "CommunicationServer".to_string());
+        cl.0.servers.lock().expect("Failed to lock").insert(4, "TextServer".to_string());
+
+        cl.0.send_keepalive();
+        let sent: Vec<Packet> = rcv.try_iter().collect();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].routing_header.hops, vec![1, 2, 3]);
+    }
+    #[test]
+    fn test_pushed_message_during_send_is_handled() {
+        let (snd, rcv) = unbounded::<Packet>();
+        let (_,rcv_id) = unbounded::<NodeId>();
+        // A notification of the server arrives while the keepalive waits for its ack
+        let (push_snd, push_rcv) = unbounded::<Packet>();
+        let fragment = FragmentReassembler::generate_fragments("client_joined!(5)").expect("Failed to create fragments").remove(0);
+        push_snd.send(Packet {
+            routing_header: SourceRoutingHeader::new(vec![3, 2, 1], 2),
+            pack_type: PacketType::MsgFragment(fragment),
+            session_id: 42
+        }).expect("Failed to send");
+        let mut cl = Client1::new(1, HashMap::new(), push_rcv, rcv_id, unbounded().1);
+        cl.0.sender_channels.insert(2, snd);
+        cl.0.network.insert(1, vec![2]);
+        cl.0.network.insert(2, vec![1, 3]);
+        cl.0.network.insert(3, vec![2]);
+        cl.0.servers.lock().expect("Failed to lock").insert(3, "CommunicationServer".to_string());
+
+        cl.0.send_keepalive();
+        assert!(cl.0.other_client_ids.lock().expect("Failed to lock").contains(&5));
+        let sent: Vec<Packet> = rcv.try_iter().collect();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().any(|p| matches!(p.pack_type, PacketType::Ack(_)) && p.session_id == 42));
+    }
+}
@@ -43,6 +43,20 @@ impl ClientUI for Client2_UI {
     fn get_viewport_id(&self) -> u64 {
         self.self_id.into()
     }
+
+    // The server is removed here and added back by the server_type! answer, that's what probe_completed looks for
+    fn send_probe(&mut self, server_id: NodeId) {
+        self.servers.write().expect("Failed to lock servers").remove(&server_id);
+        self.cmd_snd
+            .as_ref()
+            .expect("Failed to get value")
+            .send(format!("server_type?->{}", server_id))
+            .expect("Failed to send");
+    }
+
+    fn probe_completed(&self, server_id: NodeId) -> bool {
+        self.servers.read().expect("Failed to lock servers").contains_key(&server_id)
+    }
 }
 
 impl Client2_UI {
@@ -77,6 +91,9 @@ impl Client2_UI {
             communication_server_commands: vec![
                 "message_for?".to_string(),
                 "client_list?".to_string(),
+                "register?".to_string(),
+                "unregister?".to_string(),
+                "messages?".to_string(),
             ],
             log_messages: Vec::new(),
         }
@@ -204,6 +221,12 @@ impl Client2_UI {
                             self.error = (true, "No clients available.\nCheck for available clients first".to_string());
                         }
                     }
+                    "register?" => {
+                        ui.horizontal(|ui| {
+                            ui.label("Name:");
+                            ui.add(TextEdit::singleline(&mut self.input_text).desired_width(150.0));
+                        });
+                    }
                     "file?" | "media?" => {
                         let label = if self.selected_command == "file?" { "File" } else { "Media" };
                         if !self.files_names.lock().expect("Failed to lock").is_empty() {
@@ -289,8 +312,13 @@ impl Client2_UI {
             .communication_server_commands
             .contains(&self.selected_command)
         {
-            if self.selected_command.eq("client_list?") {
+            if self.selected_command.eq("client_list?")
+                || self.selected_command.eq("unregister?")
+                || self.selected_command.eq("messages?")
+            {
                 Client2_UI::create_simple_command(&self.selected_command, self.selected_server.0)
+            } else if self.selected_command.eq("register?") {
+                Client2_UI::create_complex_command(&self.selected_command, &self.input_text, self.selected_server.0)
             } else {
                 Client2_UI::create_message_for_command(
                     &self.selected_command,
@@ -7,7 +7,9 @@ pub mod client2_ui;
 
 use crate::client2_ui::Client2_UI;
 use crate::repackager::Repackager;
-use crossbeam_channel::{select_biased, unbounded, Receiver, Sender};
+use common::introspection::{DiscoveredView, IntrospectionRequest};
+use common::presence::{keepalive_targets, KEEPALIVE_INTERVAL, KEEPALIVE_REQUEST};
+use crossbeam_channel::{select_biased, tick, unbounded, Receiver, Sender};
 use egui::debug_text::print;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{self, File};
@@ -15,6 +17,7 @@ use std::io;
 use std::io::{BufRead, BufReader};
 use std::io::{Read, Write};
 use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
 use wg_2024::network::*;
 use wg_2024::packet::*;
 
@@ -29,11 +32,15 @@ pub struct Client2 {
     receiver_channel: Receiver<Packet>,
     saved_files: HashSet<String>,
     other_client_ids: Arc<Mutex<Vec<NodeId>>>,
+    offline_client_ids: HashSet<NodeId>, // Registered clients the server reported as offline
     files_names: Arc<Mutex<Vec<String>>>,
     cmd_rcv: Receiver<String>,
     msg_snd: Sender<String>,
     drone_rcv: Receiver<NodeId>,
+    introspection_rcv: Receiver<IntrospectionRequest>,
     fragment_buffers: FileToRecieve,
+    keepalive_ticker: Receiver<Instant>,
+    keepalive_pending: HashSet<NodeId>, // Servers whose client_list! answers a keepalive, not the user
     //reader: BufReader<TcpStream>,
     //writer: TcpStream,
 }
@@ -41,8 +48,6 @@ pub struct Client2 {
 #[derive(Eq, Hash, PartialEq, Debug)]
 pub struct FileToRecieve{
     file_name: String,
-    data: Vec<u8>,
-    session_id: u64,
 }
 
 impl Client2 {
@@ -50,7 +55,8 @@ impl Client2 {
         node_id: NodeId,
         neighbor_senders: HashMap<NodeId, Sender<Packet>>,
         receiver_channel: Receiver<Packet>,
-        drone_rcv: Receiver<NodeId>
+        drone_rcv: Receiver<NodeId>,
+        introspection_rcv: Receiver<IntrospectionRequest>
     ) -> (Self, Client2_UI) {
         let other_client_ids = Arc::new(Mutex::new(vec![]));
         let files_names = Arc::new(Mutex::new(vec![]));
@@ -72,6 +78,7 @@ impl Client2 {
                 node_id,
                 discovered_drones: HashMap::new(),
                 drone_rcv,
+                introspection_rcv,
                 neighbor_senders,
                 network_graph: HashMap::new(),
                 servers,
@@ -80,14 +87,15 @@ impl Client2 {
                 receiver_channel,
                 saved_files: HashSet::new(),
                 other_client_ids,
+                offline_client_ids: HashSet::new(),
                 files_names,
                 cmd_rcv,
                 msg_snd,
                 fragment_buffers: FileToRecieve{
                     file_name: "".to_string(),
-                    data: Vec::new(),
-                    session_id: 0,
                 },
+                keepalive_ticker: tick(KEEPALIVE_INTERVAL),
+                keepalive_pending: HashSet::new(),
                 //reader,
                 //writer,
             },
@@ -225,11 +233,20 @@ impl Client2 {
         match comm {
             cmd if cmd == "server_type?"
                 || cmd == "files_list?"
-                || cmd == "client_list?" =>
+                || cmd == "client_list?"
+                || cmd == "unregister?"
+                || cmd == "messages?" =>
             {
                 //server_type?
                 //files_list?
                 //client_list?
+                //unregister?
+                //messages?
+                self.send_message(server_id, cmd, None);
+                return "CLIENT2: OK".to_string();
+            }
+            cmd if cmd.starts_with("register?(") && cmd.ends_with(")") => {
+                //register?(name)
                 self.send_message(server_id, cmd, None);
                 return "CLIENT2: OK".to_string();
             }
@@ -238,8 +255,6 @@ impl Client2 {
                 let text = cmd.strip_prefix("file?(").and_then(|s| s.strip_suffix(")"));
                 self.fragment_buffers = FileToRecieve{
                     file_name: text.expect("Couldnt get file name").to_string(),
-                    data: Vec::new(),
-                    session_id: 0,
                 };
                 self.send_message(server_id, cmd, None);
                 return "CLIENT2: OK".to_string();
@@ -249,8 +264,6 @@ impl Client2 {
                 let text = cmd.strip_prefix("media?(").and_then(|s| s.strip_suffix(")"));
                 self.fragment_buffers = FileToRecieve{
                     file_name: text.expect("Couldnt get file name").to_string(),
-                    data: Vec::new(),
-                    session_id: 0,
                 };
                 self.send_message(server_id, cmd, None);
                 return "CLIENT2: OK".to_string();
@@ -261,6 +274,15 @@ impl Client2 {
                 let text = cmd
                     .strip_prefix("message_for?(")
                     .and_then(|s| s.strip_suffix(")"));
+                // An offline client is still registered, the server keeps the message until it's back
+                let dest_id = text
+                    .and_then(|s| s.split_once(','))
+                    .and_then(|(id, _)| id.trim().parse::<NodeId>().ok());
+                if let Some(dest_id) = dest_id.filter(|id| self.offline_client_ids.contains(id)) {
+                    self.msg_snd
+                        .send(format!("Client {} is offline, the message will wait on the server", dest_id))
+                        .expect("Failed to send message");
+                }
                 self.send_message(server_id, cmd, None);
                 return "CLIENT2: OK".to_string();
             }
@@ -295,9 +317,13 @@ impl Client2 {
     //Function that handles messages from the server
     pub fn handle_messages(&mut self, message: String, session_id: u64, sender: NodeId) {
         //println!("CLIENT2: CLIENT{}: Received message: {}", self.node_id, message);
-        self.msg_snd
-            .send(message.clone())
-            .expect("Failed to send message");
+        // The answer to a keepalive only updates the client list, the user didn't ask for it
+        let keepalive_answer = message.starts_with("client_list!(") && self.keepalive_pending.remove(&sender);
+        if !keepalive_answer {
+            self.msg_snd
+                .send(message.clone())
+                .expect("Failed to send message");
+        }
 
         if let Some(svtype) = message.strip_prefix("server_type!(").and_then(|s| s.strip_suffix(")")) {
             let mut servers = self.servers.write().expect("Failed to lock servers map");
@@ -343,15 +369,115 @@ impl Client2 {
                         if !self.other_client_ids.lock().expect("Failed to lock client ids").contains(&id) {
                             self.other_client_ids.lock().expect("Failed to lock client ids").push(id);
                         }
+                        self.offline_client_ids.remove(&id);
                     }
                 }
             }
+            msg if msg.starts_with("client_joined!(") && msg.ends_with(")") => {
+                //client_joined!(client_id) or client_joined!(client_id,name)
+                let id = msg
+                    .strip_prefix("client_joined!(")
+                    .and_then(|s| s.strip_suffix(")"))
+                    .and_then(|s| s.split(',').next())
+                    .and_then(|s| s.trim().parse::<NodeId>().ok());
+                if let Some(id) = id.filter(|id| *id != self.node_id) {
+                    let mut ids = self.other_client_ids.lock().expect("Failed to lock client ids");
+                    if !ids.contains(&id) {
+                        ids.push(id);
+                    }
+                    self.offline_client_ids.remove(&id);
+                }
+            }
+            msg if msg.starts_with("client_left!(") && msg.ends_with(")") => {
+                //client_left!(client_id)
+                let id = msg
+                    .strip_prefix("client_left!(")
+                    .and_then(|s| s.strip_suffix(")"))
+                    .and_then(|s| s.trim().parse::<NodeId>().ok());
+                if let Some(id) = id {
+                    // Kept in the list: a message for it waits on the server until it's back
+                    self.offline_client_ids.insert(id);
+                }
+            }
             msg if msg.starts_with("message_from!(") && msg.ends_with(")") => {
                 //message_from!(client_id, message)
                 let txt = msg
                     .strip_prefix("message_from!(")
                     .and_then(|s| s.strip_suffix(")"));
             }
+            msg if msg.starts_with("registered!(") && msg.ends_with(")") => {
+                //registered!(client_id)
+                let id = msg
+                    .strip_prefix("registered!(")
+                    .and_then(|s| s.strip_suffix(")"))
+                    .and_then(|s| s.trim().parse::<NodeId>().ok());
+                let text = match id {
+                    Some(id) => format!("Registered to the server as client {}", id),
+                    None => "Failed to get the registered id".to_string(),
+                };
+                self.msg_snd.send(text).expect("Failed to send message");
+            }
+            msg if msg.starts_with("unregistered!(") && msg.ends_with(")") => {
+                //unregistered!(client_id)
+                let id = msg
+                    .strip_prefix("unregistered!(")
+                    .and_then(|s| s.strip_suffix(")"))
+                    .and_then(|s| s.trim().parse::<NodeId>().ok());
+                let text = match id {
+                    Some(id) => format!("Client {} is no longer registered to the server", id),
+                    None => "Failed to get the unregistered id".to_string(),
+                };
+                self.msg_snd.send(text).expect("Failed to send message");
+            }
+            msg if msg.starts_with("message_delivered!(") && msg.ends_with(")") => {
+                //message_delivered!(client_id, message), receipt of a message sent to another client
+                let receipt = msg
+                    .strip_prefix("message_delivered!(")
+                    .and_then(|s| s.strip_suffix(")"))
+                    .and_then(|s| s.split_once(','));
+                let text = match receipt {
+                    Some((id, content)) => format!("Delivered to {}: {}", id.trim(), content),
+                    None => "Failed to get delivery receipt".to_string(),
+                };
+                self.msg_snd.send(text).expect("Failed to send message");
+            }
+            msg if msg.starts_with("message_undelivered!(") && msg.ends_with(")") => {
+                //message_undelivered!(client_id, message), the other client unregistered before it could get it
+                let receipt = msg
+                    .strip_prefix("message_undelivered!(")
+                    .and_then(|s| s.strip_suffix(")"))
+                    .and_then(|s| s.split_once(','));
+                let text = match receipt {
+                    Some((id, content)) => format!("Not delivered to {}: {}", id.trim(), content),
+                    None => "Failed to get delivery receipt".to_string(),
+                };
+                self.msg_snd.send(text).expect("Failed to send message");
+            }
+            msg if msg.starts_with("message_queued!(") && msg.ends_with(")") => {
+                //message_queued!(client_id, message), the other client is offline and gets it when it is back
+                let receipt = msg
+                    .strip_prefix("message_queued!(")
+                    .and_then(|s| s.strip_suffix(")"))
+                    .and_then(|s| s.split_once(','));
+                let text = match receipt {
+                    Some((id, content)) => format!("Queued for {} (offline): {}", id.trim(), content),
+                    None => "Failed to get delivery receipt".to_string(),
+                };
+                self.msg_snd.send(text).expect("Failed to send message");
+            }
+            msg if msg.starts_with("messages!(") && msg.ends_with(")") => {
+                //messages!(count), the waiting messages follow as message_from!
+                let count = msg
+                    .strip_prefix("messages!(")
+                    .and_then(|s| s.strip_suffix(")"))
+                    .and_then(|s| s.trim().parse::<usize>().ok());
+                let text = match count {
+                    Some(0) => "No messages were waiting on the server".to_string(),
+                    Some(count) => format!("{} messages were waiting on the server", count),
+                    None => "Failed to get the number of waiting messages".to_string(),
+                };
+                self.msg_snd.send(text).expect("Failed to send message");
+            }
 
             _ => {}
         }
@@ -451,50 +577,33 @@ impl Client2 {
     }
 
     pub fn handle_msg_fragment(&mut self, fragment: Fragment, packet: Packet) {
-        if fragment.fragment_index == 0 && fragment.total_n_fragments > 1 {
-            self.fragment_buffers.session_id = packet.session_id;
-        }
-        if fragment.fragment_index < fragment.total_n_fragments && self.fragment_buffers.session_id == packet.session_id {
-            // Calculate position and copy data into the struct's data vector
-            let start = (fragment.fragment_index * 128) as usize;
-            let end = start + fragment.length as usize;
-
-            // Ensure the vector is large enough
-            if self.fragment_buffers.data.len() < end {
-                self.fragment_buffers.data.resize(end, 0);
-            }
-
-            // Copy the fragment data into the correct position
-            self.fragment_buffers.data[start..end].copy_from_slice(&fragment.data[..fragment.length as usize]);
-
-            if fragment.fragment_index != fragment.total_n_fragments - 1 {
-                return;
-            } else {
-                let output_path = format!("C:\\Temp\\Client2\\{}", self.fragment_buffers.file_name);
-
-                // Call assemble_file with the correct parameters
-                match Repackager::assemble_file(self.fragment_buffers.data.clone(), &output_path) {
-                    Ok(msg) => self.msg_snd.send("File saved successfuly to C:\\Temp\\Client2".to_string()).expect("Failed to send message"),
-                    Err(e) => self.msg_snd.send(format!("Error assembling file: {}", e)).expect("Failed to send message"),
-                }
-                self.fragment_buffers = FileToRecieve{
-                    file_name: "".to_string(),
-                    data: Vec::new(),
-                    session_id: 0,
-                };
-                return;
-            }
-        }
-
-
-        let session_id = self.generate_session_id();
-        let src_id = self.node_id as u64;
+        // Fragments of a message share the session id and the source it was sent from,
+        // they can arrive in any order and more than once
+        let session_id = packet.session_id;
+        let src_id = *packet.routing_header.hops.first().expect("No hops found") as u64;
 
         match self
             .repackager
             .process_fragment(session_id, src_id, fragment)
         {
             Ok(Some(reassembled_message)) => {
+                // The file or media requested last, saved instead of shown
+                if !self.fragment_buffers.file_name.is_empty()
+                    && (reassembled_message.starts_with(b"file!(") || reassembled_message.starts_with(b"media!("))
+                {
+                    let output_path = format!("C:\\Temp\\Client2\\{}", self.fragment_buffers.file_name);
+
+                    // Call assemble_file with the correct parameters
+                    match Repackager::assemble_file(reassembled_message, &output_path) {
+                        Ok(msg) => self.msg_snd.send("File saved successfuly to C:\\Temp\\Client2".to_string()).expect("Failed to send message"),
+                        Err(e) => self.msg_snd.send(format!("Error assembling file: {}", e)).expect("Failed to send message"),
+                    }
+                    self.fragment_buffers = FileToRecieve{
+                        file_name: "".to_string(),
+                    };
+                    return;
+                }
+
                 // Process the complete message
                 let msg = Repackager::assemble_string(reassembled_message);
                 if let Ok(message) = msg.clone() {
@@ -563,6 +672,23 @@ impl Client2 {
         rand::random()
     }
 
+    // Discovered graph, sessions waiting to be resent on a NACK and known nodes
+    pub fn discovered_view(&self) -> DiscoveredView {
+        DiscoveredView::from_graph(self.node_id, &self.network_graph).with_known(
+            self.sent_packets.keys().copied(),
+            self.servers
+                .read()
+                .expect("Failed to lock the servers map")
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            self.other_client_ids
+                .lock()
+                .expect("Failed to lock")
+                .clone(),
+        )
+    }
+
     pub fn run(&mut self) {
         self.discover_network();
         let receiver_channel = self.receiver_channel.clone();
@@ -576,7 +702,8 @@ impl Client2 {
                                 Ok(packet) => {
                                         self.handle_packet(packet); //, &msg_snd
                                 },
-                                Err(e) => ()//println!("Err2: {e}")
+                                // No node can reach this client anymore, the network was shut down
+                                Err(_) => return
                         }
                     }
                     recv(self.cmd_rcv) -> cmd => {
@@ -598,9 +725,30 @@ impl Client2 {
                             Err(_) => ()
                         }
                     }
+                    recv(self.introspection_rcv) -> request => {
+                        if let Ok(request) = request {
+                            // The controller may have stopped waiting for the answer
+                            let _ = request.reply.send(self.discovered_view());
+                        }
+                    }
+                    recv(self.keepalive_ticker) -> _ => {
+                        self.send_keepalive();
+                    }
             }
         }
     }
+
+    // Tell the communication servers the client is still online
+    pub fn send_keepalive(&mut self) {
+        let servers = self.servers.read().expect("Failed to lock servers map").clone();
+        let targets = keepalive_targets(&servers, |server_id| {
+            Self::bfs_shortest_path(self.network_graph.clone(), self.node_id, server_id).is_some()
+        });
+        for server_id in targets {
+            self.keepalive_pending.insert(server_id);
+            self.send_message(server_id, KEEPALIVE_REQUEST, None);
+        }
+    }
 }
 
 
@@ -623,6 +771,7 @@ mod tests {
             neighbor_senders,
             packet_rcv.clone(),
             drone_rcv,
+            unbounded().1,
         );
 
         (client, ui, packet_rcv, drone_snd)
@@ -678,4 +827,23 @@ mod tests {
         assert_eq!(header.hops, vec![1, 3, 4]);
         assert_eq!(header.hop_index, 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_fragments_out_of_order_and_duplicated() {
+        let (mut client, _ui, _packet_rcv, _drone_snd) = create_test_client(1);
+        let server_type = "A".repeat(300);
+        let fragments = Repackager::create_fragments(&format!("server_type!({})", server_type), None).unwrap();
+        assert_eq!(fragments.len(), 3);
+
+        let header = SourceRoutingHeader::new(vec![7, 3, 1], 2);
+        let packet = |fragment: &Fragment| Packet::new_fragment(header.clone(), 42, fragment.clone());
+        // Last fragment first, and the middle one twice
+        for index in [2, 1, 1, 0, 1] {
+            client.handle_msg_fragment(fragments[index].clone(), packet(&fragments[index]));
+        }
+
+        let servers = client.servers.read().unwrap();
+        assert_eq!(servers.get(&7), Some(&server_type));
+        assert_eq!(servers.len(), 1);
+    }
+}
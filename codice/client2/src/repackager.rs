@@ -2,7 +2,8 @@
 
 use std::fs::File;
 use base64::{engine::general_purpose, Engine as _};
-use std::collections::{HashMap};
+use common::completed_sessions::CompletedSessions;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::Read;
 use std::io::Write;
@@ -11,7 +12,8 @@ use wg_2024::packet::{Fragment};
 
 pub struct Repackager {
     buffers: HashMap<(u64, u64), Vec<u8>>, // Maps (session_id, src_id) to a buffer
-    processed_packet: HashMap<(u64, u64),u8>,
+    processed_packet: HashMap<(u64, u64), HashSet<u64>>, // Indexes already copied in each buffer
+    completed: CompletedSessions<(u64, u64)>, // Last reassembled (session_id, src_id), their late duplicates are ignored
 }
 
 impl Repackager {
@@ -19,6 +21,7 @@ impl Repackager {
         Repackager {
             buffers: HashMap::new(),
             processed_packet: HashMap::new(),
+            completed: CompletedSessions::default(),
         }
     }
 
@@ -40,22 +43,19 @@ To reassemble fragments into a single packet, a client or server uses the fragme
     pub fn process_fragment(&mut self, session_id: u64, src_id: u64, fragment: Fragment) -> Result<Option<Vec<u8>>, u8> {
         let key = (session_id, src_id);
 
-        // Check if buffer for this session_id and src_id exists, if not, create it
+        if fragment.fragment_index >= fragment.total_n_fragments {
+            return Err(1); // Indicate error for invalid fragment
+        }
+        // A fragment sent again because its ack was lost is already in the buffer
+        if self.completed.contains(&key) || self.processed_packet.get(&key).is_some_and(|indexes| indexes.contains(&fragment.fragment_index)) {
+            return Ok(None);
+        }
+
+        // Check if buffer for this session_id and src_id exists, if not, create it with room for every fragment
         let buffer = self.buffers.entry(key).or_insert_with(|| {
-            Vec::with_capacity((fragment.total_n_fragments * 128) as usize)
+            vec![0; (fragment.total_n_fragments * 128) as usize]
         });
 
-        // Ensure buffer is large enough, I am pretty sure it useless but online is but a great enfasis on this to prevent error . Id
-        // Check the bufffer dim
-        if buffer.len() < (fragment.total_n_fragments * 128) as usize {
-            //println!(
-            //"Resizing buffer: current = {}, required = {}",
-            //buffer.len(),
-            //fragment.total_n_fragments * 128
-            //);
-            buffer.resize((fragment.total_n_fragments * 128) as usize, 0);
-        }
-
         // Copy fragment data into the buffer at the correct offset. Each fragment is of size 128 except the last one
         let start = (fragment.fragment_index * 128) as usize;
         let end = start + fragment.length as usize;
@@ -68,15 +68,20 @@ To reassemble fragments into a single packet, a client or server uses the fragme
 
         //copy the whole fragment in the buffer
         buffer[start..end].copy_from_slice(&fragment.data[..fragment.length as usize]);
+        // The last fragment tells where the message ends, whenever it arrives
+        if fragment.fragment_index == fragment.total_n_fragments - 1 {
+            buffer.truncate(end);
+        }
 
-        // Increment the processed packet counter
-        *self.processed_packet.entry(key).or_insert(0) += 1;
+        // Mark the slot of this fragment as filled
+        self.processed_packet.entry(key).or_default().insert(fragment.fragment_index);
 
-        // Check if all fragments have been received
-        if self.processed_packet[&key] == fragment.total_n_fragments as u8 {
+        // Check if all fragments have been received, whatever order they arrived in
+        if self.processed_packet[&key].len() as u64 == fragment.total_n_fragments {
             // All fragments received, return the reassembled data
             let complete_data = self.buffers.remove(&key).unwrap_or_default();
             self.processed_packet.remove(&key);
+            self.completed.insert(key);
             Ok(Some(complete_data)) // Message reassembled successfully
         } else {
             Ok(None) // Not all fragments recived yet
@@ -1,6 +1,11 @@
 use eframe::egui;
+use wg_2024::network::NodeId;
 
 pub trait ClientUI {
     fn show_ui(&mut self, _frame: &mut eframe::Frame, ui: &mut egui::Ui);
     fn get_viewport_id(&self) -> u64;
+    /// Forgets the type of `server_id` and asks the server for it again
+    fn send_probe(&mut self, server_id: NodeId);
+    /// Whether the server answered the last probe
+    fn probe_completed(&self, server_id: NodeId) -> bool;
 }
@@ -0,0 +1,77 @@
+//! Sessions whose message was already reassembled, so that a late duplicate of
+//! one of their fragments isn't taken for the start of a new message.
+use std::collections::{HashSet, VecDeque};
+use std::hash::Hash;
+
+/// Sessions remembered by default, the oldest are forgotten first
+pub const COMPLETED_SESSIONS_KEPT: usize = 1024;
+
+/// The last sessions completed, up to a fixed number
+#[derive(Debug, Clone)]
+pub struct CompletedSessions<K> {
+    sessions: HashSet<K>,
+    // Same sessions, oldest first
+    order: VecDeque<K>,
+    capacity: usize,
+}
+
+impl<K: Copy + Eq + Hash> Default for CompletedSessions<K> {
+    fn default() -> Self {
+        Self::with_capacity(COMPLETED_SESSIONS_KEPT)
+    }
+}
+
+impl<K: Copy + Eq + Hash> CompletedSessions<K> {
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            sessions: HashSet::new(),
+            order: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    #[must_use]
+    pub fn contains(&self, session: &K) -> bool {
+        self.sessions.contains(session)
+    }
+
+    /// Remembers a session, forgetting the oldest one when full
+    pub fn insert(&mut self, session: K) {
+        if !self.sessions.insert(session) {
+            return;
+        }
+        self.order.push_back(session);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.sessions.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remembers_completed_sessions() {
+        let mut completed = CompletedSessions::default();
+        completed.insert((1, 2));
+        assert!(completed.contains(&(1, 2)));
+        assert!(!completed.contains(&(2, 1)));
+    }
+
+    #[test]
+    fn test_oldest_session_forgotten() {
+        let mut completed = CompletedSessions::with_capacity(2);
+        completed.insert(1);
+        completed.insert(2);
+        // Inserting it again doesn't make it newer or take another slot
+        completed.insert(1);
+        completed.insert(3);
+        assert!(!completed.contains(&1));
+        assert!(completed.contains(&2));
+        assert!(completed.contains(&3));
+    }
+}
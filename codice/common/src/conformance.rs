@@ -0,0 +1,517 @@
+//! Scripted WGL protocol checks run against every drone implementation.
+//!
+//! Each case builds a fresh drone through `get_drone_impl` with id `DRONE_ID`
+//! and two mock neighbours, `PREVIOUS_HOP` and `NEXT_HOP`, then drives it
+//! through its packet and command channels.
+use crate::get_drone_impl::{DRONE_IMPL_NAMES, get_drone_impl, get_drone_impl_name};
+use crossbeam_channel::{Receiver, Sender, select, unbounded};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use wg_2024::controller::{DroneCommand, DroneEvent};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{
+    FRAGMENT_DSIZE, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet,
+    PacketType,
+};
+
+const DRONE_ID: NodeId = 1;
+const PREVIOUS_HOP: NodeId = 2;
+const NEXT_HOP: NodeId = 3;
+const UNKNOWN_NODE: NodeId = 9;
+const SESSION_ID: u64 = 42;
+const FLOOD_ID: u64 = 5;
+const DROP_RATE_SAMPLES: u32 = 200;
+const DROP_RATE: f32 = 0.5;
+const DROP_RATE_TOLERANCE: f32 = 0.15;
+const TIMEOUT: Duration = Duration::from_millis(500);
+const SETTLE_TIME: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConformanceCase {
+    ForwardByHopIndex,
+    ErrorInRouting,
+    DestinationIsDrone,
+    UnexpectedRecipient,
+    FloodRequestDedup,
+    FloodResponseRouting,
+    PacketDropRate,
+    CrashDraining,
+    ShortcutAck,
+    ShortcutNack,
+    ShortcutFloodResponse,
+}
+
+impl ConformanceCase {
+    pub const ALL: [ConformanceCase; 11] = [
+        ConformanceCase::ForwardByHopIndex,
+        ConformanceCase::ErrorInRouting,
+        ConformanceCase::DestinationIsDrone,
+        ConformanceCase::UnexpectedRecipient,
+        ConformanceCase::FloodRequestDedup,
+        ConformanceCase::FloodResponseRouting,
+        ConformanceCase::PacketDropRate,
+        ConformanceCase::CrashDraining,
+        ConformanceCase::ShortcutAck,
+        ConformanceCase::ShortcutNack,
+        ConformanceCase::ShortcutFloodResponse,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            ConformanceCase::ForwardByHopIndex => "forward",
+            ConformanceCase::ErrorInRouting => "err_routing",
+            ConformanceCase::DestinationIsDrone => "dest_drone",
+            ConformanceCase::UnexpectedRecipient => "unexp_recip",
+            ConformanceCase::FloodRequestDedup => "flood_dedup",
+            ConformanceCase::FloodResponseRouting => "flood_resp",
+            ConformanceCase::PacketDropRate => "pdr",
+            ConformanceCase::CrashDraining => "crash",
+            ConformanceCase::ShortcutAck => "sc_ack",
+            ConformanceCase::ShortcutNack => "sc_nack",
+            ConformanceCase::ShortcutFloodResponse => "sc_flood",
+        }
+    }
+}
+
+/// Results of every case for a single drone implementation
+#[derive(Debug, Clone)]
+pub struct ConformanceReport {
+    pub implementation: &'static str,
+    pub results: Vec<(ConformanceCase, Result<(), String>)>,
+}
+
+impl ConformanceReport {
+    #[must_use]
+    pub fn all_passed(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.is_ok())
+    }
+}
+
+/// A drone running on its own thread, wired to mock neighbours and controller
+struct Harness {
+    command_send: Sender<DroneCommand>,
+    packet_send: Sender<Packet>,
+    event_recv: Receiver<DroneEvent>,
+    neighbours: HashMap<NodeId, Receiver<Packet>>,
+    handle: JoinHandle<()>,
+}
+
+impl Harness {
+    fn start(index: u8) -> Self {
+        Self::start_with(index, Vec::new(), Vec::new())
+    }
+
+    /// Starts the drone with `packets` and `commands` already waiting in its channels
+    fn start_with(index: u8, packets: Vec<Packet>, commands: Vec<DroneCommand>) -> Self {
+        let (command_send, command_recv) = unbounded();
+        let (event_send, event_recv) = unbounded();
+        let (packet_send, packet_recv) = unbounded();
+
+        for packet in packets {
+            packet_send
+                .send(packet)
+                .expect("Should be able to queue the packet");
+        }
+        for command in commands {
+            command_send
+                .send(command)
+                .expect("Should be able to queue the command");
+        }
+
+        let mut neighbour_senders = HashMap::new();
+        let mut neighbours = HashMap::new();
+        for neighbour in [PREVIOUS_HOP, NEXT_HOP] {
+            let (send, recv) = unbounded();
+            neighbour_senders.insert(neighbour, send);
+            neighbours.insert(neighbour, recv);
+        }
+
+        let handle = thread::spawn(move || {
+            let mut drone = get_drone_impl(
+                index,
+                DRONE_ID,
+                event_send,
+                command_recv,
+                packet_recv,
+                neighbour_senders,
+                0.0,
+            );
+            drone.run();
+        });
+
+        Self {
+            command_send,
+            packet_send,
+            event_recv,
+            neighbours,
+            handle,
+        }
+    }
+
+    fn send(&self, packet: Packet) -> Result<(), String> {
+        self.packet_send
+            .send(packet)
+            .map_err(|_| "Drone stopped receiving packets".to_string())
+    }
+
+    fn command(&self, command: DroneCommand) -> Result<(), String> {
+        self.command_send
+            .send(command)
+            .map_err(|_| "Drone stopped receiving commands".to_string())?;
+        thread::sleep(SETTLE_TIME);
+        Ok(())
+    }
+
+    fn neighbour(&self, neighbour: NodeId) -> &Receiver<Packet> {
+        self.neighbours
+            .get(&neighbour)
+            .expect("Should only ask for mock neighbours")
+    }
+
+    fn recv_from(&self, neighbour: NodeId) -> Result<Packet, String> {
+        self.neighbour(neighbour)
+            .recv_timeout(TIMEOUT)
+            .map_err(|_| format!("Nothing received by node {neighbour}"))
+    }
+
+    fn expect_silence(&self, neighbour: NodeId) -> Result<(), String> {
+        match self.neighbour(neighbour).recv_timeout(SETTLE_TIME) {
+            Ok(packet) => Err(format!("Unexpected packet to node {neighbour}: {packet}")),
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Waits for an event matching `accept`, skipping any other event
+    fn wait_for_event(&self, accept: impl Fn(&DroneEvent) -> bool) -> Result<DroneEvent, String> {
+        let deadline = Instant::now() + TIMEOUT;
+        while let Ok(event) = self.event_recv.recv_deadline(deadline) {
+            if accept(&event) {
+                return Ok(event);
+            }
+        }
+        Err("Expected controller event was not sent".to_string())
+    }
+
+    fn expect_nack(&self, neighbour: NodeId, expected: &NackType) -> Result<(), String> {
+        let packet = self.recv_from(neighbour)?;
+        match packet.pack_type {
+            PacketType::Nack(ref nack) if nack.nack_type == *expected => {}
+            _ => return Err(format!("Expected {expected:?}, got {packet}")),
+        }
+        if packet.routing_header.destination() != Some(neighbour)
+            || packet.routing_header.current_hop() != Some(neighbour)
+        {
+            return Err(format!("NACK routed wrongly: {:?}", packet.routing_header));
+        }
+        Ok(())
+    }
+
+    /// Crashes the drone, closes its channels and waits for its thread to end
+    fn stop(self) -> Result<(), String> {
+        let _ = self.command_send.send(DroneCommand::Crash);
+        drop(self.packet_send);
+        drop(self.command_send);
+
+        let deadline = Instant::now() + TIMEOUT;
+        while !self.handle.is_finished() {
+            if Instant::now() > deadline {
+                return Err("Drone did not stop after Crash".to_string());
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        Ok(())
+    }
+}
+
+fn fragment(hops: Vec<NodeId>) -> Packet {
+    Packet::new_fragment(
+        SourceRoutingHeader::new(hops, 1),
+        SESSION_ID,
+        Fragment::new(0, 1, [0; FRAGMENT_DSIZE]),
+    )
+}
+
+fn ack(hops: Vec<NodeId>) -> Packet {
+    Packet::new_ack(SourceRoutingHeader::new(hops, 1), SESSION_ID, 0)
+}
+
+fn nack(hops: Vec<NodeId>) -> Packet {
+    Packet::new_nack(
+        SourceRoutingHeader::new(hops, 1),
+        SESSION_ID,
+        Nack {
+            fragment_index: 0,
+            nack_type: NackType::Dropped,
+        },
+    )
+}
+
+fn flood_response(hops: Vec<NodeId>) -> Packet {
+    Packet::new_flood_response(
+        SourceRoutingHeader::new(hops, 1),
+        SESSION_ID,
+        FloodResponse {
+            flood_id: FLOOD_ID,
+            path_trace: vec![
+                (PREVIOUS_HOP, NodeType::Client),
+                (DRONE_ID, NodeType::Drone),
+                (NEXT_HOP, NodeType::Server),
+            ],
+        },
+    )
+}
+
+fn expect_forwarded(packet: &Packet, sent: &Packet) -> Result<(), String> {
+    if packet.pack_type != sent.pack_type || packet.session_id != sent.session_id {
+        return Err(format!("Forwarded a different packet: {packet}"));
+    }
+    if packet.routing_header.hop_index != sent.routing_header.hop_index + 1 {
+        return Err(format!(
+            "hop_index not increased: {}",
+            packet.routing_header.hop_index
+        ));
+    }
+    Ok(())
+}
+
+fn forward_by_hop_index(h: &Harness) -> Result<(), String> {
+    let sent = fragment(vec![PREVIOUS_HOP, DRONE_ID, NEXT_HOP]);
+    h.send(sent.clone())?;
+    expect_forwarded(&h.recv_from(NEXT_HOP)?, &sent)?;
+    h.wait_for_event(|event| matches!(event, DroneEvent::PacketSent(_)))?;
+    Ok(())
+}
+
+fn error_in_routing(h: &Harness) -> Result<(), String> {
+    h.send(fragment(vec![PREVIOUS_HOP, DRONE_ID, UNKNOWN_NODE]))?;
+    h.expect_nack(PREVIOUS_HOP, &NackType::ErrorInRouting(UNKNOWN_NODE))
+}
+
+fn destination_is_drone(h: &Harness) -> Result<(), String> {
+    h.send(fragment(vec![PREVIOUS_HOP, DRONE_ID]))?;
+    h.expect_nack(PREVIOUS_HOP, &NackType::DestinationIsDrone)
+}
+
+fn unexpected_recipient(h: &Harness) -> Result<(), String> {
+    h.send(fragment(vec![PREVIOUS_HOP, UNKNOWN_NODE, NEXT_HOP]))?;
+    h.expect_nack(PREVIOUS_HOP, &NackType::UnexpectedRecipient(DRONE_ID))
+}
+
+fn flood_request_dedup(h: &Harness) -> Result<(), String> {
+    let request = Packet::new_flood_request(
+        SourceRoutingHeader::empty_route(),
+        SESSION_ID,
+        FloodRequest::initialize(FLOOD_ID, PREVIOUS_HOP, NodeType::Client),
+    );
+
+    // First time: forwarded to every neighbour except the sender
+    h.send(request.clone())?;
+    let forwarded = h.recv_from(NEXT_HOP)?;
+    match forwarded.pack_type {
+        PacketType::FloodRequest(ref flood)
+            if flood.flood_id == FLOOD_ID
+                && flood.path_trace.last() == Some(&(DRONE_ID, NodeType::Drone)) => {}
+        _ => return Err(format!("Flood request not forwarded: {forwarded}")),
+    }
+    h.expect_silence(PREVIOUS_HOP)?;
+
+    // Second time: answered with a flood response instead
+    h.send(request)?;
+    let response = h.recv_from(PREVIOUS_HOP)?;
+    match response.pack_type {
+        PacketType::FloodResponse(ref flood)
+            if flood.flood_id == FLOOD_ID
+                && flood.path_trace.last() == Some(&(DRONE_ID, NodeType::Drone)) => {}
+        _ => return Err(format!("Duplicate flood not answered: {response}")),
+    }
+    h.expect_silence(NEXT_HOP)
+}
+
+fn flood_response_routing(h: &Harness) -> Result<(), String> {
+    let sent = flood_response(vec![NEXT_HOP, DRONE_ID, PREVIOUS_HOP]);
+    h.send(sent.clone())?;
+    expect_forwarded(&h.recv_from(PREVIOUS_HOP)?, &sent)
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn packet_drop_rate(h: &Harness) -> Result<(), String> {
+    h.command(DroneCommand::SetPacketDropRate(DROP_RATE))?;
+    for _ in 0..DROP_RATE_SAMPLES {
+        h.send(fragment(vec![PREVIOUS_HOP, DRONE_ID, NEXT_HOP]))?;
+    }
+
+    let (mut forwarded, mut dropped) = (0u32, 0u32);
+    while forwarded + dropped < DROP_RATE_SAMPLES {
+        select! {
+            recv(h.neighbour(NEXT_HOP)) -> _ => forwarded += 1,
+            recv(h.neighbour(PREVIOUS_HOP)) -> packet => match packet.map(|p| p.pack_type) {
+                Ok(PacketType::Nack(Nack { nack_type: NackType::Dropped, .. })) => dropped += 1,
+                other => return Err(format!("Expected Dropped NACK, got {other:?}")),
+            },
+            default(TIMEOUT) => {
+                return Err(format!("Only {} of {DROP_RATE_SAMPLES} fragments handled", forwarded + dropped));
+            }
+        }
+    }
+
+    let mut dropped_events = 0;
+    while h
+        .wait_for_event(|event| matches!(event, DroneEvent::PacketDropped(_)))
+        .is_ok()
+    {
+        dropped_events += 1;
+    }
+    if dropped_events != dropped {
+        return Err(format!(
+            "{dropped} fragments dropped but {dropped_events} PacketDropped events"
+        ));
+    }
+
+    let rate = dropped as f32 / DROP_RATE_SAMPLES as f32;
+    if (rate - DROP_RATE).abs() > DROP_RATE_TOLERANCE {
+        return Err(format!("Observed drop rate {rate:.2}, set {DROP_RATE:.2}"));
+    }
+    Ok(())
+}
+
+/// The packets already queued when the Crash arrives may be handled either as
+/// usual or as a crashing drone would, but none of them may be lost. Only a
+/// fragment sent after the queue was drained has to be refused
+fn crash_draining(index: u8) -> Result<(), String> {
+    let queued_fragment = fragment(vec![PREVIOUS_HOP, DRONE_ID, NEXT_HOP]);
+    let queued_ack = ack(vec![NEXT_HOP, DRONE_ID, PREVIOUS_HOP]);
+    let h = Harness::start_with(
+        index,
+        vec![queued_fragment.clone(), queued_ack.clone()],
+        vec![DroneCommand::Crash],
+    );
+
+    select! {
+        recv(h.neighbour(NEXT_HOP)) -> packet => {
+            let packet = packet.map_err(|_| "Next hop disconnected".to_string())?;
+            expect_forwarded(&packet, &queued_fragment)?;
+        }
+        recv(h.neighbour(PREVIOUS_HOP)) -> packet => match packet.map(|p| p.pack_type) {
+            Ok(PacketType::Nack(Nack { nack_type: NackType::ErrorInRouting(DRONE_ID), .. })) => {}
+            other => return Err(format!("Queued fragment neither forwarded nor refused: {other:?}")),
+        },
+        default(TIMEOUT) => return Err("Fragment queued before the Crash was lost".to_string()),
+    }
+    expect_forwarded(&h.recv_from(PREVIOUS_HOP)?, &queued_ack)?;
+
+    // The queue is empty, so the Crash is the only thing left for the drone to read
+    thread::sleep(SETTLE_TIME);
+    // A drone that stopped as soon as it was drained is fine too
+    if h.send(fragment(vec![PREVIOUS_HOP, DRONE_ID, NEXT_HOP]))
+        .is_ok()
+        && let Err(reason) = h.expect_nack(PREVIOUS_HOP, &NackType::ErrorInRouting(DRONE_ID))
+        && !h.handle.is_finished()
+    {
+        return Err(reason);
+    }
+
+    h.stop()
+}
+
+fn controller_shortcut(h: &Harness, sent: &Packet) -> Result<(), String> {
+    h.send(sent.clone())?;
+    let event = h.wait_for_event(|event| matches!(event, DroneEvent::ControllerShortcut(_)))?;
+    match event {
+        DroneEvent::ControllerShortcut(packet) if packet.pack_type == sent.pack_type => Ok(()),
+        _ => Err(format!("Shortcut carried a different packet: {event:?}")),
+    }
+}
+
+/// Runs a single case against a fresh drone of the implementation at `index`
+///
+/// # Errors
+/// Returns the reason the drone did not behave as the protocol requires
+pub fn run_case(index: u8, case: ConformanceCase) -> Result<(), String> {
+    if case == ConformanceCase::CrashDraining {
+        return crash_draining(index);
+    }
+
+    let h = Harness::start(index);
+    let unreachable = vec![NEXT_HOP, DRONE_ID, UNKNOWN_NODE];
+
+    let result = match case {
+        ConformanceCase::ForwardByHopIndex => forward_by_hop_index(&h),
+        ConformanceCase::ErrorInRouting => error_in_routing(&h),
+        ConformanceCase::DestinationIsDrone => destination_is_drone(&h),
+        ConformanceCase::UnexpectedRecipient => unexpected_recipient(&h),
+        ConformanceCase::FloodRequestDedup => flood_request_dedup(&h),
+        ConformanceCase::FloodResponseRouting => flood_response_routing(&h),
+        ConformanceCase::PacketDropRate => packet_drop_rate(&h),
+        ConformanceCase::CrashDraining => unreachable!("Handled above"),
+        ConformanceCase::ShortcutAck => controller_shortcut(&h, &ack(unreachable)),
+        ConformanceCase::ShortcutNack => controller_shortcut(&h, &nack(unreachable)),
+        ConformanceCase::ShortcutFloodResponse => {
+            controller_shortcut(&h, &flood_response(unreachable))
+        }
+    };
+
+    // A drone that doesn't stop only leaks its thread, it doesn't fail the case
+    let _ = h.stop();
+    result
+}
+
+/// Runs every case against the implementation `get_drone_impl` builds for `index`
+#[must_use]
+pub fn run_conformance(index: u8) -> ConformanceReport {
+    ConformanceReport {
+        implementation: get_drone_impl_name(index),
+        results: ConformanceCase::ALL
+            .iter()
+            .map(|&case| (case, run_case(index, case)))
+            .collect(),
+    }
+}
+
+/// Runs every case against every drone implementation
+///
+/// # Panics
+#[must_use]
+pub fn run_all_conformance() -> Vec<ConformanceReport> {
+    (0..DRONE_IMPL_NAMES.len())
+        .map(|index| {
+            run_conformance(u8::try_from(index).expect("Should always be able to convert"))
+        })
+        .collect()
+}
+
+/// Formats the reports as a pass/fail matrix, one row per implementation,
+/// followed by the reason of every failure
+#[must_use]
+pub fn format_matrix(reports: &[ConformanceReport]) -> String {
+    let mut matrix = format!("{:<16}", "implementation");
+    for case in ConformanceCase::ALL {
+        let _ = write!(matrix, " {:>12}", case.name());
+    }
+    matrix.push('\n');
+
+    for report in reports {
+        let _ = write!(matrix, "{:<16}", report.implementation);
+        for (_, result) in &report.results {
+            let cell = if result.is_ok() { "pass" } else { "FAIL" };
+            let _ = write!(matrix, " {cell:>12}");
+        }
+        matrix.push('\n');
+    }
+
+    for report in reports {
+        for (case, result) in &report.results {
+            if let Err(reason) = result {
+                let _ = writeln!(
+                    matrix,
+                    "{} {}: {reason}",
+                    report.implementation,
+                    case.name()
+                );
+            }
+        }
+    }
+    matrix
+}
@@ -18,6 +18,26 @@ use wg_2024::drone::Drone;
 use wg_2024::network::NodeId;
 use wg_2024::packet::Packet;
 
+/// Names of the drone implementations, in the same order `get_drone_impl` uses them
+pub const DRONE_IMPL_NAMES: [&str; 10] = [
+    "BagelBomber",
+    "LockheedRustin",
+    "NullPointer",
+    "Rustafarian",
+    "Rustastic",
+    "GetDroned",
+    "RustyDrone",
+    "RustDoIt",
+    "RustRoveri",
+    "KrustyDrone",
+];
+
+/// Returns the name of the drone implementation `get_drone_impl` builds for `index`
+#[must_use]
+pub fn get_drone_impl_name(index: u8) -> &'static str {
+    DRONE_IMPL_NAMES[usize::from(index % 10)]
+}
+
 #[must_use]
 /// # Panics
 pub fn get_drone_impl(
@@ -0,0 +1,65 @@
+//! Channel through which the controller reads what a client or server has
+//! discovered about the network.
+use crossbeam_channel::Sender;
+use std::collections::{HashMap, HashSet};
+use wg_2024::network::NodeId;
+
+/// The network as seen by a client or server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscoveredView {
+    pub node_id: NodeId,
+    /// Neighbours of every discovered node, each link is stored in both directions
+    pub graph: HashMap<NodeId, HashSet<NodeId>>,
+    /// Sessions still waiting to be acknowledged
+    pub pending_sessions: Vec<u64>,
+    pub known_servers: Vec<NodeId>,
+    pub known_clients: Vec<NodeId>,
+}
+
+impl DiscoveredView {
+    /// Builds the view from an adjacency list, adding the reverse of every link
+    pub fn from_graph<'a, N>(
+        node_id: NodeId,
+        graph: impl IntoIterator<Item = (&'a NodeId, N)>,
+    ) -> Self
+    where
+        N: IntoIterator<Item = &'a NodeId>,
+    {
+        let mut view = Self {
+            node_id,
+            ..Self::default()
+        };
+        for (node, neighbours) in graph {
+            view.graph.entry(*node).or_default();
+            for neighbour in neighbours {
+                view.graph.entry(*node).or_default().insert(*neighbour);
+                view.graph.entry(*neighbour).or_default().insert(*node);
+            }
+        }
+        view
+    }
+
+    /// Sets the sessions and nodes known by the endpoint, sorted for display
+    #[must_use]
+    pub fn with_known(
+        mut self,
+        pending_sessions: impl IntoIterator<Item = u64>,
+        known_servers: impl IntoIterator<Item = NodeId>,
+        known_clients: impl IntoIterator<Item = NodeId>,
+    ) -> Self {
+        self.pending_sessions = pending_sessions.into_iter().collect();
+        self.pending_sessions.sort_unstable();
+        self.pending_sessions.dedup();
+        self.known_servers = known_servers.into_iter().collect();
+        self.known_servers.sort_unstable();
+        self.known_clients = known_clients.into_iter().collect();
+        self.known_clients.sort_unstable();
+        self
+    }
+}
+
+/// Asks an endpoint for its `DiscoveredView`, which it sends back on `reply`
+#[derive(Debug, Clone)]
+pub struct IntrospectionRequest {
+    pub reply: Sender<DiscoveredView>,
+}
@@ -1,2 +1,6 @@
 pub mod client_ui;
+pub mod completed_sessions;
+pub mod conformance;
 pub mod get_drone_impl;
+pub mod introspection;
+pub mod presence;
@@ -0,0 +1,36 @@
+//! Presence of the clients on the communication servers. A server marks a client
+//! offline once it hasn't heard from it for `CLIENT_TIMEOUT`, so the clients send
+//! a keepalive a few times within that time.
+use std::time::Duration;
+use wg_2024::network::NodeId;
+
+/// A client not heard for this long is offline
+pub const CLIENT_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Keepalives a client sends within one `CLIENT_TIMEOUT`, so that losing one
+/// doesn't make it go offline
+pub const KEEPALIVES_PER_TIMEOUT: u64 = 3;
+
+/// Time between two keepalives of a client
+pub const KEEPALIVE_INTERVAL: Duration =
+    Duration::from_secs(CLIENT_TIMEOUT.as_secs() / KEEPALIVES_PER_TIMEOUT);
+
+/// The keepalive request, its answer only refreshes the list of the other clients
+pub const KEEPALIVE_REQUEST: &str = "client_list?";
+
+/// The servers a keepalive goes to: the communication servers among the known
+/// ones, by id and type, that `reachable` says there is a route to. A server out
+/// of reach is tried again at the next keepalive.
+pub fn keepalive_targets<'a>(
+    servers: impl IntoIterator<Item = (&'a NodeId, &'a String)>,
+    reachable: impl Fn(NodeId) -> bool,
+) -> Vec<NodeId> {
+    let mut targets: Vec<NodeId> = servers
+        .into_iter()
+        .filter(|(_, server_type)| server_type.as_str() == "CommunicationServer")
+        .map(|(id, _)| *id)
+        .filter(|id| reachable(*id))
+        .collect();
+    targets.sort_unstable();
+    targets
+}
@@ -0,0 +1,14 @@
+use common::conformance::{format_matrix, run_all_conformance};
+
+#[test]
+#[ignore = "slow, run with `cargo test --test conformance -- --ignored --nocapture`"]
+fn drone_conformance_matrix() {
+    let reports = run_all_conformance();
+    let matrix = format_matrix(&reports);
+    println!("{matrix}");
+
+    assert!(
+        reports.iter().all(|report| report.all_passed()),
+        "Some drone implementations don't follow the protocol:\n{matrix}"
+    );
+}
@@ -1,4 +1,8 @@
 use serde::Deserialize;
+use server::CostModel;
+use simulation_controller::battery::BatteryConfig;
+use simulation_controller::mobility::{MobilityModel, MobilitySettings, Point};
+use simulation_controller::pdr_profile::PdrProfile;
 use wg_2024::network::NodeId;
 
 #[derive(Debug, Deserialize)]
@@ -6,18 +10,53 @@ pub struct DroneConfig {
     pub id: NodeId,
     pub connected_node_ids: Vec<NodeId>,
     pub pdr: f32,
+    pub pdr_profile: Option<PdrProfile>,
+    pub battery: Option<BatteryConfig>,
+    pub position: Option<Point>,
+    pub velocity: Option<Point>,
+    pub mobility: Option<MobilityModel>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ClientConfig {
     pub id: NodeId,
     pub connected_drone_ids: Vec<NodeId>,
+    pub position: Option<Point>,
+    pub velocity: Option<Point>,
+    pub mobility: Option<MobilityModel>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ServerConfig {
     pub id: NodeId,
     pub connected_drone_ids: Vec<NodeId>,
+    pub window_size: Option<usize>,
+    pub max_paths: Option<usize>,
+    pub cost_model: Option<CostModelConfig>,
+    pub position: Option<Point>,
+    pub velocity: Option<Point>,
+    pub mobility: Option<MobilityModel>,
+}
+
+// How a server turns its delivery statistics into route costs, the missing values keep the defaults
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+pub struct CostModelConfig {
+    pub prior_delivered: Option<f64>,
+    pub prior_dropped: Option<f64>,
+    pub min_probability: Option<f64>,
+    pub hop_penalty: Option<f64>,
+}
+
+impl CostModelConfig {
+    pub fn cost_model(&self) -> CostModel {
+        let default = CostModel::default();
+        CostModel {
+            prior_delivered: self.prior_delivered.unwrap_or(default.prior_delivered),
+            prior_dropped: self.prior_dropped.unwrap_or(default.prior_dropped),
+            min_probability: self.min_probability.unwrap_or(default.min_probability),
+            hop_penalty: self.hop_penalty.unwrap_or(default.hop_penalty),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -25,4 +64,5 @@ pub struct NetworkConfig {
     pub drone: Vec<DroneConfig>,
     pub client: Vec<ClientConfig>,
     pub server: Vec<ServerConfig>,
+    pub mobility: Option<MobilitySettings>,
 }
@@ -6,15 +6,22 @@ use client1::Client1;
 use client2::client2_ui::Client2_UI;
 use client2::Client2;
 use common::client_ui::ClientUI;
-use crossbeam_channel::unbounded;
-use simulation_controller::node_stats::DroneStats;
+use common::introspection::IntrospectionRequest;
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use server::file_system::ServerType;
+use simulation_controller::forwarded_event::{ForwardedEvent, FORWARDED_EVENT_CAPACITY};
+use simulation_controller::mobility::{Mobility, MovingNode};
+use simulation_controller::node_stats::{DroneStats, EndpointStats};
+use simulation_controller::ui_commands::{CommandId, UICommand, UIResponse};
 use simulation_controller::SimulationController;
 use simulation_controller::SimulationControllerUI;
 use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::sync::{Arc, Mutex};
-use std::thread;
+use std::thread::{self, JoinHandle};
+use wg_2024::controller::{DroneCommand, DroneEvent};
 use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
 
 use crate::ui::App;
 use std::fs::File;
@@ -23,6 +30,22 @@ use std::path::Path;
 
 pub struct NetworkInitializer;
 
+/// Channels and UIs of a network whose nodes are already running
+pub struct SpawnedNetwork {
+    pub drone_command_senders: HashMap<NodeId, Sender<DroneCommand>>,
+    pub node_senders: HashMap<NodeId, Sender<Packet>>,
+    pub event_receiver: Receiver<DroneEvent>,
+    pub event_sender: Sender<DroneEvent>,
+    pub crash_event_senders: Vec<Sender<NodeId>>,
+    pub introspection_senders: HashMap<NodeId, Sender<IntrospectionRequest>>,
+    pub client_uis: Vec<Box<dyn ClientUI>>,
+    pub drone_stats: HashMap<NodeId, DroneStats>,
+    pub endpoint_stats: HashMap<NodeId, EndpointStats>,
+    pub interface_hub: server::interface::interface::AllServersUi,
+    // Threads of the drones, clients and servers
+    pub handles: Vec<JoinHandle<()>>,
+}
+
 impl NetworkInitializer {
     pub fn read_config(file_path: &str) -> Result<NetworkConfig, Box<dyn std::error::Error>> {
         let config_str = fs::read_to_string(file_path)?;
@@ -31,16 +54,80 @@ impl NetworkInitializer {
     }
 
     pub fn initialize_network(config: &NetworkConfig) {
+        let SpawnedNetwork {
+            drone_command_senders,
+            node_senders,
+            event_receiver,
+            event_sender,
+            crash_event_senders,
+            introspection_senders,
+            client_uis,
+            drone_stats,
+            endpoint_stats,
+            interface_hub,
+            handles: _,
+        } = Self::spawn_network(config);
+
+        server::interface::interface::start_ui(interface_hub);
+
+        let (ui_command_sender, ui_command_receiver) = unbounded();
+        let (ui_response_sender, ui_response_receiver) = unbounded();
+        let (forwarded_event_sender, forwarded_event_receiver) = bounded(FORWARDED_EVENT_CAPACITY);
+
+        let mut simulation_controller = Self::new_controller(
+            config,
+            drone_command_senders,
+            node_senders,
+            event_receiver,
+            event_sender,
+            crash_event_senders,
+            introspection_senders,
+            ui_command_receiver,
+            ui_response_sender,
+            forwarded_event_sender,
+        );
+
+        thread::spawn(move || simulation_controller.run());
+
+        if let Err(error) = eframe::run_native(
+            "Network simulation",
+            eframe::NativeOptions::default(),
+            Box::new(|cc| {
+                Ok(Box::new(App::new(
+                    cc,
+                    SimulationControllerUI::new(
+                        drone_stats,
+                        endpoint_stats,
+                        ui_command_sender,
+                        ui_response_receiver,
+                        forwarded_event_receiver,
+                        config.client.iter().map(|c| c.id).collect(),
+                        config.server.iter().map(|c| c.id).collect(),
+                    ),
+                    client_uis,
+                )))
+            }),
+        ) {
+            println!("Error: {}", error);
+        }
+    }
+
+    /// Starts every drone, client and server of the configuration in its own thread
+    pub fn spawn_network(config: &NetworkConfig) -> SpawnedNetwork {
         let (event_sender, event_receiver) = unbounded();
         let mut node_senders = HashMap::new();
         let mut node_receivers = HashMap::new();
         let mut drone_command_senders = HashMap::new();
         let mut drone_command_receivers = HashMap::new();
         let mut drone_stats = HashMap::new();
+        let mut endpoint_stats = HashMap::new();
         let mut crash_event_senders = Vec::new();
         let mut crash_event_receivers = HashMap::new();
+        let mut introspection_senders = HashMap::new();
+        let mut introspection_receivers = HashMap::new();
 
         let mut client_uis = Vec::<Box<dyn ClientUI>>::new();
+        let mut handles = Vec::new();
 
         // Initialize drones
         for drone_config in &config.drone {
@@ -62,6 +149,9 @@ impl NetworkInitializer {
             node_receivers.insert(client_config.id, client_recv.clone());
             crash_event_receivers.insert(client_config.id, crash_event_receiver);
             crash_event_senders.push(crash_event_sender);
+            let (introspection_sender, introspection_receiver) = unbounded();
+            introspection_senders.insert(client_config.id, introspection_sender);
+            introspection_receivers.insert(client_config.id, introspection_receiver);
         }
 
         // Initialize servers
@@ -73,6 +163,9 @@ impl NetworkInitializer {
             node_receivers.insert(server_config.id, server_recv.clone());
             crash_event_receivers.insert(server_config.id, crash_event_receiver);
             crash_event_senders.push(crash_event_sender);
+            let (introspection_sender, introspection_receiver) = unbounded();
+            introspection_senders.insert(server_config.id, introspection_sender);
+            introspection_receivers.insert(server_config.id, introspection_receiver);
         }
 
         for (index, drone_config) in config.drone.iter().enumerate() {
@@ -87,16 +180,19 @@ impl NetworkInitializer {
                 );
             }
 
+            let impl_index = u8::try_from(index).expect("Should always be able to convert");
+
             drone_stats.insert(
                 drone_config.id,
                 DroneStats::new(
                     HashSet::from_iter(drone_config.connected_node_ids.clone()),
                     drone_config.pdr,
+                    common::get_drone_impl::get_drone_impl_name(impl_index).to_string(),
                 ),
             );
 
             let mut drone = common::get_drone_impl::get_drone_impl(
-                u8::try_from(index).expect("Should always be able to convert"),
+                impl_index,
                 drone_config.id,
                 event_sender.clone(),
                 drone_command_receivers
@@ -111,7 +207,7 @@ impl NetworkInitializer {
                 drone_config.pdr,
             );
 
-            thread::spawn(move || drone.run());
+            handles.push(thread::spawn(move || drone.run()));
         }
 
         for (index, client_config) in config.client.iter().enumerate() {
@@ -131,6 +227,15 @@ impl NetworkInitializer {
                 .expect("Should always be able to get client receiver")
                 .clone();
 
+            let kind = if index % 2 == 0 { "Client1" } else { "Client2" };
+            endpoint_stats.insert(
+                client_config.id,
+                EndpointStats::new(
+                    HashSet::from_iter(client_config.connected_drone_ids.clone()),
+                    kind.to_string(),
+                ),
+            );
+
             if index % 2 == 0 {
                 let (mut client, client_ui) = Client1::new(
                     client_config.id,
@@ -140,9 +245,13 @@ impl NetworkInitializer {
                         .get(&client_config.id)
                         .expect("Should always be able to get crash event receiver")
                         .clone(),
+                    introspection_receivers
+                        .get(&client_config.id)
+                        .expect("Should always be able to get introspection receiver")
+                        .clone(),
                 );
                 client_uis.push(Box::new(client_ui));
-                thread::spawn(move || client.run());
+                handles.push(thread::spawn(move || client.run()));
             } else {
                 let (mut client, client_ui) = Client2::new(
                     client_config.id,
@@ -152,9 +261,13 @@ impl NetworkInitializer {
                         .get(&client_config.id)
                         .expect("Should always be able to get crash event receiver")
                         .clone(),
+                    introspection_receivers
+                        .get(&client_config.id)
+                        .expect("Should always be able to get introspection receiver")
+                        .clone(),
                 );
                 client_uis.push(Box::new(client_ui));
-                thread::spawn(move || client.run());
+                handles.push(thread::spawn(move || client.run()));
             }
         }
 
@@ -179,8 +292,17 @@ impl NetworkInitializer {
                 .get(&server_config.id)
                 .expect("Should always be able to get server receiver");
 
-            let mut server = match index % 3 {
-                1 => server::Server::new(
+            let server_type = Self::server_type(index);
+            endpoint_stats.insert(
+                server_config.id,
+                EndpointStats::new(
+                    HashSet::from_iter(server_config.connected_drone_ids.clone()),
+                    server_type.to_string(),
+                ),
+            );
+
+            let mut server = match server_type {
+                ServerType::CommunicationServer => server::Server::new(
                     server_config.id,
                     packet_receiver.clone(),
                     neighbor_senders,
@@ -188,11 +310,15 @@ impl NetworkInitializer {
                         .get(&server_config.id)
                         .expect("Should always be able to get crash event receiver")
                         .clone(),
+                    introspection_receivers
+                        .get(&server_config.id)
+                        .expect("Should always be able to get introspection receiver")
+                        .clone(),
                     Box::new(server::file_system::ChatServer::new()),
                     None,
                     InterfaceHub.clone(),
                 ),
-                0 => {
+                ServerType::MediaServer => {
                     let base_path = if cfg!(target_os = "windows") {
                         "C:\\Temp\\ServerMedia"
                     } else {
@@ -206,15 +332,19 @@ impl NetworkInitializer {
                             .get(&server_config.id)
                             .expect("Should always be able to get crash event receiver")
                             .clone(),
+                        introspection_receivers
+                            .get(&server_config.id)
+                            .expect("Should always be able to get introspection receiver")
+                            .clone(),
                         Box::new(server::file_system::ContentServer::new(
                             base_path,
-                            server::file_system::ServerType::MediaServer,
+                            ServerType::MediaServer,
                         )),
                         Some(base_path.to_string()),
                         InterfaceHub.clone(),
                     )
                 }
-                _ => {
+                ServerType::TextServer => {
                     let base_path = if cfg!(target_os = "windows") {
                         "C:\\Temp\\ServerTxt"
                     } else {
@@ -229,32 +359,66 @@ impl NetworkInitializer {
                             .get(&server_config.id)
                             .expect("Should always be able to get crash event receiver")
                             .clone(),
+                        introspection_receivers
+                            .get(&server_config.id)
+                            .expect("Should always be able to get introspection receiver")
+                            .clone(),
                         Box::new(server::file_system::ContentServer::new(
                             base_path,
-                            server::file_system::ServerType::TextServer,
+                            ServerType::TextServer,
                         )),
                         Some(base_path.to_string()),
                         InterfaceHub.clone(),
                     )
                 }
             };
+            if let Some(window_size) = server_config.window_size {
+                server.set_window_size(window_size);
+            }
+            if let Some(max_paths) = server_config.max_paths {
+                server.set_max_paths(max_paths);
+            }
+            if let Some(cost_model) = &server_config.cost_model {
+                server.set_cost_model(cost_model.cost_model());
+            }
 
-            thread::spawn(move || server.run());
+            handles.push(thread::spawn(move || server.run()));
         }
 
-        server::interface::interface::start_ui(InterfaceHub);
-
-        let network_topology = Self::get_network_topology(config);
-        let (ui_command_sender, ui_command_receiver) = unbounded();
-        let (ui_response_sender, ui_response_receiver) = unbounded();
-        let (forwarded_event_sender, forwarded_event_receiver) = unbounded();
+        SpawnedNetwork {
+            drone_command_senders,
+            node_senders,
+            event_receiver,
+            event_sender,
+            crash_event_senders,
+            introspection_senders,
+            client_uis,
+            drone_stats,
+            endpoint_stats,
+            interface_hub: InterfaceHub,
+            handles,
+        }
+    }
 
-        let mut simulation_controller = SimulationController::new(
+    /// Creates the controller of a spawned network
+    pub fn new_controller(
+        config: &NetworkConfig,
+        drone_command_senders: HashMap<NodeId, Sender<DroneCommand>>,
+        node_senders: HashMap<NodeId, Sender<Packet>>,
+        event_receiver: Receiver<DroneEvent>,
+        event_sender: Sender<DroneEvent>,
+        crash_event_senders: Vec<Sender<NodeId>>,
+        introspection_senders: HashMap<NodeId, Sender<IntrospectionRequest>>,
+        ui_command_receiver: Receiver<(CommandId, UICommand)>,
+        ui_response_sender: Sender<(CommandId, UIResponse)>,
+        forwarded_event_sender: Sender<ForwardedEvent>,
+    ) -> SimulationController {
+        SimulationController::new(
             drone_command_senders,
             node_senders,
             event_receiver,
             event_sender,
-            network_topology,
+            Self::get_network_topology(config),
             config.drone.iter().map(|c| c.id).collect(),
             config.client.iter().map(|c| c.id).collect(),
             config.server.iter().map(|c| c.id).collect(),
@@ -263,29 +427,27 @@ impl NetworkInitializer {
             ui_response_sender,
             forwarded_event_sender,
             crash_event_senders,
-        );
-
-        thread::spawn(move || simulation_controller.run());
+            introspection_senders,
+            config
+                .drone
+                .iter()
+                .filter_map(|c| c.pdr_profile.map(|profile| (c.id, profile)))
+                .collect(),
+            config
+                .drone
+                .iter()
+                .filter_map(|c| c.battery.map(|battery| (c.id, battery)))
+                .collect(),
+            Self::get_mobility(config),
+        )
+    }
 
-        if let Err(error) = eframe::run_native(
-            "Network simulation",
-            eframe::NativeOptions::default(),
-            Box::new(|cc| {
-                Ok(Box::new(App::new(
-                    cc,
-                    SimulationControllerUI::new(
-                        drone_stats,
-                        ui_command_sender,
-                        ui_response_receiver,
-                        forwarded_event_receiver,
-                        config.client.iter().map(|c| c.id).collect(),
-                        config.server.iter().map(|c| c.id).collect(),
-                    ),
-                    client_uis,
-                )))
-            }),
-        ) {
-            println!("Error: {}", error);
+    /// Kind of the server at `index` in the configuration, the kinds take turns
+    fn server_type(index: usize) -> ServerType {
+        match index % 3 {
+            1 => ServerType::CommunicationServer,
+            0 => ServerType::MediaServer,
+            _ => ServerType::TextServer,
         }
     }
 
@@ -338,6 +500,36 @@ impl NetworkInitializer {
         topology
     }
 
+    /// Simulated motion of the nodes with a position, if the network has mobility settings
+    fn get_mobility(config: &NetworkConfig) -> Option<Mobility> {
+        let settings = config.mobility?;
+        let nodes = config
+            .drone
+            .iter()
+            .map(|d| (d.id, d.position, d.velocity, &d.mobility))
+            .chain(
+                config
+                    .client
+                    .iter()
+                    .map(|c| (c.id, c.position, c.velocity, &c.mobility)),
+            )
+            .chain(
+                config
+                    .server
+                    .iter()
+                    .map(|s| (s.id, s.position, s.velocity, &s.mobility)),
+            )
+            .filter_map(|(node_id, position, velocity, model)| {
+                Some((
+                    node_id,
+                    MovingNode::new(position?, velocity.unwrap_or_default(), model.clone()),
+                ))
+            })
+            .collect();
+
+        Some(Mobility::new(settings, nodes))
+    }
+
     pub fn run(file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
         let config = Self::read_config(file_path)?;
         validate_config(&config)?;
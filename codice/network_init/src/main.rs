@@ -1,12 +1,21 @@
 #![allow(clippy::too_many_lines)]
 mod config;
 mod initializer;
+mod resilience;
 mod ui;
 mod validation;
 
 use initializer::NetworkInitializer;
 
 fn main() {
+    // Crash every drone in turn instead of starting the simulation
+    if std::env::args().any(|arg| arg == "--resilience") {
+        if let Err(e) = resilience::run("network_config.toml") {
+            eprintln!("Error running the resilience sweep: {e}");
+        }
+        return;
+    }
+
     // Initialize the network
     if let Err(e) = NetworkInitializer::run("network_config.toml") {
         eprintln!("Error initializing network: {e}");
@@ -0,0 +1,271 @@
+use super::config::NetworkConfig;
+use super::initializer::{NetworkInitializer, SpawnedNetwork};
+use super::validation::validate_config;
+use common::client_ui::ClientUI;
+use crossbeam_channel::{bounded, unbounded};
+use simulation_controller::forwarded_event::FORWARDED_EVENT_CAPACITY;
+use simulation_controller::ui_commands::{CommandSender, UICommand, UIResponse};
+use std::collections::HashMap;
+use std::fmt;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+
+/// How long the clients have to discover the network before the crash
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the clients have to reach every server after the crash
+const RECOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the threads of a copy have to stop once it is torn down
+const TEARDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Outcome of a probe from a client to a server after a crash
+#[derive(Debug, Clone)]
+pub struct ProbeResult {
+    pub client: NodeId,
+    pub server: NodeId,
+    // The server could be reached before the crash
+    pub reachable_before: bool,
+    // Time from the crash command to the answer, `None` if it never arrived
+    pub recovery: Option<Duration>,
+}
+
+/// Outcome of crashing a single drone
+#[derive(Debug, Clone)]
+pub enum CrashResult {
+    /// The controller refused the crash
+    Refused(String),
+    Crashed(Vec<ProbeResult>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResilienceReport {
+    pub crashes: Vec<(NodeId, CrashResult)>,
+    // Threads still running after their copy was torn down
+    pub stuck_threads: usize,
+}
+
+impl ResilienceReport {
+    /// Crashes where every server reachable before the crash answered again
+    pub fn recovered(&self) -> usize {
+        self.crashes
+            .iter()
+            .filter(|(_, result)| match result {
+                CrashResult::Crashed(probes) => probes
+                    .iter()
+                    .all(|probe| !probe.reachable_before || probe.recovery.is_some()),
+                CrashResult::Refused(_) => false,
+            })
+            .count()
+    }
+
+    pub fn allowed(&self) -> usize {
+        self.crashes
+            .iter()
+            .filter(|(_, result)| matches!(result, CrashResult::Crashed(_)))
+            .count()
+    }
+}
+
+impl fmt::Display for ResilienceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (drone_id, result) in &self.crashes {
+            match result {
+                CrashResult::Refused(reason) => {
+                    writeln!(f, "Drone {}: crash refused, {}", drone_id, reason)?;
+                }
+                CrashResult::Crashed(probes) => {
+                    writeln!(f, "Drone {}: crashed", drone_id)?;
+                    for probe in probes {
+                        write!(f, "  client {} -> server {}: ", probe.client, probe.server)?;
+                        match (probe.reachable_before, probe.recovery) {
+                            (false, _) => writeln!(f, "unreachable before the crash")?,
+                            (true, Some(recovery)) => {
+                                writeln!(f, "recovered in {:.2} s", recovery.as_secs_f64())?
+                            }
+                            (true, None) => writeln!(f, "not completed")?,
+                        }
+                    }
+                }
+            }
+        }
+        if self.stuck_threads > 0 {
+            writeln!(
+                f,
+                "{} threads did not stop after their trial",
+                self.stuck_threads
+            )?;
+        }
+        write!(
+            f,
+            "{} of {} allowed crashes fully recovered",
+            self.recovered(),
+            self.allowed()
+        )
+    }
+}
+
+pub struct ResilienceSweep;
+
+impl ResilienceSweep {
+    /// Crashes every drone in turn, each one in a fresh copy of the network, and
+    /// checks that every client can still reach every server.
+    /// Every copy is torn down before the next one starts.
+    pub fn run(config: &NetworkConfig) -> ResilienceReport {
+        let mut report = ResilienceReport::default();
+
+        for drone_config in &config.drone {
+            let (result, stuck_threads) = Self::crash_in_copy(config, drone_config.id);
+            report.crashes.push((drone_config.id, result));
+            report.stuck_threads += stuck_threads;
+        }
+
+        report
+    }
+
+    /// Returns the outcome of the crash and how many threads of the copy did not stop
+    fn crash_in_copy(config: &NetworkConfig, drone_id: NodeId) -> (CrashResult, usize) {
+        let SpawnedNetwork {
+            drone_command_senders,
+            node_senders,
+            event_receiver,
+            event_sender,
+            crash_event_senders,
+            introspection_senders,
+            mut client_uis,
+            interface_hub: _,
+            drone_stats: _,
+            endpoint_stats: _,
+            mut handles,
+        } = NetworkInitializer::spawn_network(config);
+
+        let (ui_command_sender, ui_command_receiver) = unbounded();
+        let (ui_response_sender, ui_response_receiver) = unbounded();
+        let (forwarded_event_sender, forwarded_event_receiver) = bounded(FORWARDED_EVENT_CAPACITY);
+
+        let mut simulation_controller = NetworkInitializer::new_controller(
+            config,
+            drone_command_senders,
+            node_senders,
+            event_receiver,
+            event_sender,
+            crash_event_senders,
+            introspection_senders,
+            ui_command_receiver,
+            ui_response_sender,
+            forwarded_event_sender,
+        );
+        handles.push(thread::spawn(move || simulation_controller.run()));
+        // Nothing shows the events, they are drained so that the channel doesn't stay full
+        handles.push(thread::spawn(move || {
+            forwarded_event_receiver.iter().for_each(drop)
+        }));
+
+        let servers = config.server.iter().map(|s| s.id).collect::<Vec<_>>();
+
+        // Wait for the clients to discover the network
+        let before = Self::probe(&mut client_uis, &servers, Instant::now(), DISCOVERY_TIMEOUT)
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+
+        let ui_command_sender = CommandSender::new(ui_command_sender);
+        let crashed_at = Instant::now();
+        ui_command_sender
+            .send(UICommand::CrashDrone(drone_id))
+            .expect("Should be able to send the command");
+        let result = match ui_response_receiver.recv_timeout(RECOVERY_TIMEOUT) {
+            Ok((_, UIResponse::Success(_))) => {
+                let after = Self::probe(&mut client_uis, &servers, crashed_at, RECOVERY_TIMEOUT);
+                CrashResult::Crashed(
+                    after
+                        .into_iter()
+                        .map(|((client, server), recovery)| ProbeResult {
+                            client,
+                            server,
+                            reachable_before: before[&(client, server)].is_some(),
+                            recovery,
+                        })
+                        .collect(),
+                )
+            }
+            Ok((_, UIResponse::Falure(reason))) => CrashResult::Refused(reason),
+            Err(_) => CrashResult::Refused("the controller stopped answering".to_string()),
+        };
+
+        // Without its UI the controller crashes every drone and stops, then the
+        // clients and servers stop once no drone is left. The other channels are
+        // kept until then, the threads still use them.
+        drop(ui_command_sender);
+        let stuck_threads = Self::join_all(handles, TEARDOWN_TIMEOUT);
+        drop((client_uis, ui_response_receiver));
+
+        (result, stuck_threads)
+    }
+
+    /// Joins the threads that stop within `timeout` and returns how many did not
+    fn join_all(handles: Vec<JoinHandle<()>>, timeout: Duration) -> usize {
+        let start = Instant::now();
+        let mut running = handles;
+        while !running.is_empty() && start.elapsed() < timeout {
+            let (finished, still_running): (Vec<_>, Vec<_>) =
+                running.into_iter().partition(JoinHandle::is_finished);
+            for handle in finished {
+                // A node that panicked is stopped as well
+                let _ = handle.join();
+            }
+            running = still_running;
+            thread::sleep(POLL_INTERVAL);
+        }
+        running.len()
+    }
+
+    /// Sends a probe from every client to every server and waits for the answers.
+    /// Returns how long after `since` every answer arrived, `None` for the ones
+    /// that timed out.
+    fn probe(
+        client_uis: &mut [Box<dyn ClientUI>],
+        servers: &[NodeId],
+        since: Instant,
+        timeout: Duration,
+    ) -> Vec<((NodeId, NodeId), Option<Duration>)> {
+        let start = Instant::now();
+        let mut results = Vec::new();
+        for client_ui in client_uis.iter_mut() {
+            for server in servers {
+                client_ui.send_probe(*server);
+            }
+        }
+
+        let mut pending = HashMap::new();
+        for (index, client_ui) in client_uis.iter().enumerate() {
+            let client = NodeId::try_from(client_ui.get_viewport_id())
+                .expect("Should always be able to convert");
+            for server in servers {
+                pending.insert((client, *server), index);
+            }
+        }
+
+        while !pending.is_empty() && start.elapsed() < timeout {
+            pending.retain(|(client, server), index| {
+                if client_uis[*index].probe_completed(*server) {
+                    results.push(((*client, *server), Some(since.elapsed())));
+                    false
+                } else {
+                    true
+                }
+            });
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        results.extend(pending.into_keys().map(|pair| (pair, None)));
+        results.sort_by_key(|(pair, _)| *pair);
+        results
+    }
+}
+
+pub fn run(file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let config = NetworkInitializer::read_config(file_path)?;
+    validate_config(&config)?;
+    println!("{}", ResilienceSweep::run(&config));
+    Ok(())
+}
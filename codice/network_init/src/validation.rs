@@ -25,6 +25,16 @@ pub fn validate_config(config: &NetworkConfig) -> Result<(), String> {
         if drone.pdr < 0.0 || drone.pdr > 1.0 {
             return Err(format!("Drone {} has invalid PDR: {}", drone.id, drone.pdr));
         }
+        if let Some(profile) = &drone.pdr_profile {
+            if let Err(e) = profile.validate() {
+                return Err(format!("Drone {}: {}", drone.id, e));
+            }
+        }
+        if let Some(battery) = &drone.battery {
+            if let Err(e) = battery.validate() {
+                return Err(format!("Drone {}: {}", drone.id, e));
+            }
+        }
     }
 
     // Validate clients
@@ -69,12 +79,70 @@ pub fn validate_config(config: &NetworkConfig) -> Result<(), String> {
         {
             return Err(format!("Server {} has duplicate connections", server.id));
         }
+        if server.window_size == Some(0) {
+            return Err(format!("Server {} has a window size of 0", server.id));
+        }
+        if server.max_paths == Some(0) {
+            return Err(format!("Server {} must use at least 1 path", server.id));
+        }
+        if let Some(cost_model) = &server.cost_model {
+            let cost_model = cost_model.cost_model();
+            if cost_model.prior_delivered < 0.0 || cost_model.prior_dropped < 0.0 {
+                return Err(format!("Server {} has a negative prior", server.id));
+            }
+            if cost_model.min_probability <= 0.0 || cost_model.min_probability > 1.0 {
+                return Err(format!("Server {} must have a minimum probability in (0, 1]", server.id));
+            }
+            if cost_model.hop_penalty < 0.0 {
+                return Err(format!("Server {} has a negative hop penalty", server.id));
+            }
+        }
     }
 
     if let Err(e) = is_graph_connected(config) {
         return Err(e);
     }
 
+    validate_mobility(config)
+}
+
+fn validate_mobility(config: &NetworkConfig) -> Result<(), String> {
+    if let Some(settings) = &config.mobility {
+        settings.validate()?;
+    }
+
+    let nodes = config
+        .drone
+        .iter()
+        .map(|d| (d.id, d.position, d.velocity, &d.mobility))
+        .chain(
+            config
+                .client
+                .iter()
+                .map(|c| (c.id, c.position, c.velocity, &c.mobility)),
+        )
+        .chain(
+            config
+                .server
+                .iter()
+                .map(|s| (s.id, s.position, s.velocity, &s.mobility)),
+        );
+    for (node_id, position, velocity, model) in nodes {
+        if position.is_none() && (velocity.is_some() || model.is_some()) {
+            return Err(format!("Node {} moves but has no position", node_id));
+        }
+        if position.is_some() && config.mobility.is_none() {
+            return Err(format!(
+                "Node {} has a position but the network has no mobility settings",
+                node_id
+            ));
+        }
+        if let Some(model) = model {
+            if let Err(e) = model.validate() {
+                return Err(format!("Node {}: {}", node_id, e));
+            }
+        }
+    }
     Ok(())
 }
 
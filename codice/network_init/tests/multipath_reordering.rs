@@ -0,0 +1,181 @@
+use client1::Client1;
+use common::introspection::{DiscoveredView, IntrospectionRequest};
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use server::file_system::{ContentServer, ServerType};
+use server::Server;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{Fragment, NodeType, Packet, PacketType, FRAGMENT_DSIZE};
+
+const CLIENT: NodeId = 10;
+const SERVER: NodeId = 20;
+const FIRST_DRONE: NodeId = 1;
+const SECOND_DRONE: NodeId = 2;
+// Enough files for a files_list! of more than MULTIPATH_MIN_FRAGMENTS fragments
+const FILES: usize = 120;
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// A drone that never drops anything
+fn run_relay(id: NodeId, packets: Receiver<Packet>, neighbours: HashMap<NodeId, Sender<Packet>>) {
+    let mut seen_floods = HashSet::new();
+    for mut packet in packets {
+        if let PacketType::FloodRequest(request) = &packet.pack_type {
+            let previous = request.path_trace.last().map(|(node_id, _)| *node_id);
+            let request = request.get_incremented(id, NodeType::Drone);
+            let others = neighbours
+                .keys()
+                .filter(|neighbour| Some(**neighbour) != previous)
+                .collect::<Vec<_>>();
+            if !seen_floods.insert((request.flood_id, request.initiator_id)) || others.is_empty() {
+                let response = request.generate_response(packet.session_id);
+                if let Some(previous) = previous {
+                    let _ = neighbours[&previous].send(response);
+                }
+            } else {
+                for neighbour in others {
+                    let forwarded = Packet::new_flood_request(
+                        SourceRoutingHeader::empty_route(),
+                        packet.session_id,
+                        request.clone(),
+                    );
+                    let _ = neighbours[neighbour].send(forwarded);
+                }
+            }
+            continue;
+        }
+
+        packet.routing_header.increase_hop_index();
+        let Some(next_hop) = packet.routing_header.current_hop() else {
+            continue;
+        };
+        if let Some(neighbour) = neighbours.get(&next_hop) {
+            let _ = neighbour.send(packet);
+        }
+    }
+}
+
+fn discovered_view(introspection: &Sender<IntrospectionRequest>) -> DiscoveredView {
+    let (reply, view) = unbounded();
+    introspection
+        .send(IntrospectionRequest { reply })
+        .expect("The node should be running");
+    view.recv_timeout(TIMEOUT).expect("The node should answer")
+}
+
+/// Waits until `introspection` sees the client behind both drones
+fn wait_for_both_paths(introspection: &Sender<IntrospectionRequest>) {
+    let start = Instant::now();
+    while start.elapsed() < TIMEOUT {
+        let view = discovered_view(introspection);
+        if view.graph.get(&CLIENT).is_some_and(|neighbours| {
+            neighbours.contains(&FIRST_DRONE) && neighbours.contains(&SECOND_DRONE)
+        }) {
+            return;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    panic!("The discovery should find both drones");
+}
+
+// Smoke test of a files list spread over two drones, the reordering itself is
+// covered by the unit tests of the send window and of the reassemblers
+#[test]
+fn files_list_spread_on_two_paths_arrives() {
+    let dir = std::env::temp_dir().join(format!("multipath_reordering_{}", std::process::id()));
+    fs::create_dir_all(&dir).expect("Should create the server directory");
+    let file_names = (0..FILES)
+        .map(|index| format!("reordered_file_{index:03}.txt"))
+        .collect::<Vec<_>>();
+    for file_name in &file_names {
+        fs::write(dir.join(file_name), "").expect("Should create the file");
+    }
+
+    let mut packet_senders = HashMap::new();
+    let mut packet_receivers = HashMap::new();
+    for node_id in [CLIENT, SERVER, FIRST_DRONE, SECOND_DRONE] {
+        let (sender, receiver) = unbounded();
+        packet_senders.insert(node_id, sender);
+        packet_receivers.insert(node_id, receiver);
+    }
+    let neighbours = |node_ids: &[NodeId]| {
+        node_ids
+            .iter()
+            .map(|node_id| (*node_id, packet_senders[node_id].clone()))
+            .collect::<HashMap<_, _>>()
+    };
+
+    for drone_id in [FIRST_DRONE, SECOND_DRONE] {
+        let packets = packet_receivers[&drone_id].clone();
+        let drone_neighbours = neighbours(&[CLIENT, SERVER]);
+        thread::spawn(move || run_relay(drone_id, packets, drone_neighbours));
+    }
+
+    let (_server_crash_sender, server_crash_receiver) = unbounded();
+    let (server_introspection, server_introspection_receiver) = unbounded();
+    let mut server = Server::new(
+        SERVER,
+        packet_receivers[&SERVER].clone(),
+        neighbours(&[FIRST_DRONE, SECOND_DRONE]),
+        server_crash_receiver,
+        server_introspection_receiver,
+        Box::new(ContentServer::new(
+            dir.to_str().expect("The path should be valid"),
+            ServerType::TextServer,
+        )),
+        Some(dir.to_string_lossy().into_owned()),
+        Arc::new(Mutex::new(Vec::new())),
+    );
+    server.set_max_paths(2);
+    thread::spawn(move || server.run());
+
+    let (_client_crash_sender, client_crash_receiver) = unbounded();
+    let (_client_introspection, client_introspection_receiver) = unbounded();
+    let (mut client, client_ui) = Client1::new(
+        CLIENT,
+        neighbours(&[FIRST_DRONE, SECOND_DRONE]),
+        packet_receivers[&CLIENT].clone(),
+        client_crash_receiver,
+        client_introspection_receiver,
+    );
+    thread::spawn(move || client.run());
+
+    wait_for_both_paths(&server_introspection);
+
+    // The request the client would send through the first drone
+    let request = "files_list?";
+    let mut data = [0; FRAGMENT_DSIZE];
+    data[..request.len()].copy_from_slice(request.as_bytes());
+    let mut fragment = Fragment::new(0, 1, data);
+    fragment.length = u8::try_from(request.len()).expect("The request is short");
+    packet_senders[&SERVER]
+        .send(Packet::new_fragment(
+            SourceRoutingHeader::new(vec![CLIENT, FIRST_DRONE, SERVER], 2),
+            7,
+            fragment,
+        ))
+        .expect("The server should be running");
+
+    let start = Instant::now();
+    let mut files = client_ui.retrieve_content("Files", SERVER);
+    while files.is_empty() && start.elapsed() < TIMEOUT {
+        thread::sleep(POLL_INTERVAL);
+        files = client_ui.retrieve_content("Files", SERVER);
+    }
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(
+        !files.is_empty(),
+        "The client should rebuild the files list"
+    );
+    for file_name in &file_names {
+        assert!(
+            files.contains(file_name),
+            "{file_name} is missing from {files}"
+        );
+    }
+}
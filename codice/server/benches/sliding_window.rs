@@ -0,0 +1,107 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use server::sliding_window::SendWindow;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::time::{Duration, Instant};
+use wg_2024::packet::{Fragment, FRAGMENT_DSIZE};
+
+// A media file of 32 KB
+const FRAGMENTS: u64 = 256;
+// Drones between the server and the client, each one drops fragments with the same PDR
+const HOPS: u32 = 3;
+const HOP_DELAY: Duration = Duration::from_millis(5);
+// Random extra delay of every hop, replies can arrive out of order
+const JITTER: Duration = Duration::from_millis(1);
+// Time the server needs to put one fragment on its link
+const SEND_TIME: Duration = Duration::from_micros(500);
+const PDRS: [f32; 4] = [0.0, 0.05, 0.2, 0.4];
+// A window of 1 is the old stop-and-wait
+const WINDOW_SIZES: [usize; 4] = [1, 4, 16, 64];
+
+// Path between the server and the client, with the replies on their way back
+struct SimulatedPath<'a> {
+    pdr: f32,
+    rng: &'a mut StdRng,
+    // When the server can send the next fragment
+    link_free: Instant,
+    // (arrival time, fragment index, whether it is an Ack)
+    replies: BinaryHeap<Reverse<(Instant, u64, bool)>>,
+}
+
+impl SimulatedPath<'_> {
+    /// Every fragment dropped by a drone comes back as a Nack from that drone
+    fn send(&mut self, fragment_index: u64, now: Instant) {
+        self.link_free = self.link_free.max(now) + SEND_TIME;
+        let (hops, acked) = match (1..=HOPS).find(|_| self.rng.gen::<f32>() < self.pdr) {
+            Some(hop) => (hop, false),
+            None => (HOPS + 1, true),
+        };
+        let delay = (0..hops * 2)
+            .map(|_| HOP_DELAY + JITTER.mul_f32(self.rng.gen()))
+            .sum::<Duration>();
+        self.replies
+            .push(Reverse((self.link_free + delay, fragment_index, acked)));
+    }
+}
+
+/// How long the server takes to send a file, in simulated time and not in the time the
+/// simulation runs. Fragments are sent again when their Nack arrives, like in the real network.
+fn transfer_time(window_size: usize, pdr: f32, rng: &mut StdRng) -> Duration {
+    let fragments = (0..FRAGMENTS)
+        .map(|i| Fragment::new(i, FRAGMENTS, [0; FRAGMENT_DSIZE]))
+        .collect();
+    let mut window = SendWindow::new(fragments, window_size);
+    let start = Instant::now();
+    let mut path = SimulatedPath {
+        pdr,
+        rng,
+        link_free: start,
+        replies: BinaryHeap::new(),
+    };
+
+    for fragment in window.next_to_send() {
+        path.send(fragment.fragment_index, start);
+    }
+    let mut now = start;
+    while !window.is_complete() {
+        let Some(Reverse((arrival, fragment_index, acked))) = path.replies.pop() else {
+            panic!("The transfer stopped with fragments not acked");
+        };
+        now = arrival;
+        if acked {
+            window.ack(fragment_index);
+            for fragment in window.next_to_send() {
+                path.send(fragment.fragment_index, now);
+            }
+        } else {
+            path.send(fragment_index, now);
+        }
+    }
+    now - start
+}
+
+fn compare_windows(c: &mut Criterion) {
+    for pdr in PDRS {
+        let mut group = c.benchmark_group(format!("transfer of {FRAGMENTS} fragments, pdr {pdr}"));
+        for window_size in WINDOW_SIZES {
+            group.bench_with_input(
+                BenchmarkId::new("window", window_size),
+                &window_size,
+                |b, window_size| {
+                    let mut rng = StdRng::seed_from_u64(42);
+                    b.iter_custom(|iters| {
+                        (0..iters)
+                            .map(|_| transfer_time(*window_size, pdr, &mut rng))
+                            .sum()
+                    });
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, compare_windows);
+criterion_main!(benches);
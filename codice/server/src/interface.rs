@@ -17,7 +17,7 @@ pub mod interface {
         io::{stdout, Result},
         sync::{Arc, Mutex},
         thread,
-        time::Duration,
+        time::{Duration, Instant},
     };
     use std::collections::{BTreeSet, HashMap, HashSet};
     use std::ops::Mul;
@@ -27,6 +27,7 @@ pub mod interface {
     use ratatui::widgets::GraphType;
     use wg_2024::network::NodeId;
     use wg_2024::packet::NodeType;
+    use crate::message::discovery::REFRESH_INTERVAL;
     /* ──────────────────────────────────────────────────────────
            ───────  DATI CONDIVISI TRA TUTTI I THREAD / SERVER  ─────
            ────────────────────────────────────────────────────────── */
@@ -43,9 +44,18 @@ pub mod interface {
         pub graph : Arc<Mutex<HashMap<NodeId, (HashSet<NodeId>, NodeType)>>>,
         pub selected_index: Arc<AtomicUsize>,   // <── cambiato!
         pub selected_chat_index: Arc<AtomicUsize>,
+        pub transport: Arc<Mutex<TransportStats>>,     // timeouts and retransmissions of the fragments sent
+        pub edge_last_seen: Arc<Mutex<HashMap<(NodeId, NodeId), Instant>>>,   // last flood that confirmed each edge, lower id first
 
     }
 
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct TransportStats {
+        pub timeouts: usize,
+        pub retries: usize,
+        pub abandoned_sessions: usize,
+    }
+
     /* ────────────────────────────────────────────────────────── */
     enum Mode {
         Chat,
@@ -59,6 +69,8 @@ pub mod interface {
     type Chatlist = Arc<Mutex<HashMap<BTreeSet<NodeId>,Vec<(NodeId,String)>>>>;
 
     type FileList = Arc<Mutex<HashMap<String,usize>>>;
+    type Transport = Arc<Mutex<TransportStats>>;
+    type EdgeTimes = Arc<Mutex<HashMap<(NodeId, NodeId), Instant>>>;
 
     /* ──────────────────────────────────────────────────────────
        ───────────── FUNZIONI CHIAMATE DAI SERVER ───────────────
@@ -144,6 +156,39 @@ pub mod interface {
     }
 
 
+    pub fn update_transport_stats(stats : &Transport, change : impl FnOnce(&mut TransportStats))
+    {
+        if let Ok(mut locked) = stats.lock() {
+            change(&mut locked);
+        }
+    }
+
+
+    pub fn update_edge_freshness(edges : &EdgeTimes, last_seen : &HashMap<(NodeId, NodeId), Instant>)
+    {
+        if let Ok(mut locked) = edges.lock() {
+            locked.clone_from(last_seen);
+        }
+    }
+
+    pub fn remove_interface_link(grafo: &Graph, a: NodeId, b: NodeId)
+    {
+        if let Ok(mut grafo) = grafo.lock() {
+            if let Some((vicini, _)) = grafo.get_mut(&a) {
+                vicini.remove(&b);
+            }
+            if let Some((vicini, _)) = grafo.get_mut(&b) {
+                vicini.remove(&a);
+            }
+        }
+    }
+
+    // How long ago the edge was confirmed, None if it never was
+    fn edge_age(edges: &HashMap<(NodeId, NodeId), Instant>, a: NodeId, b: NodeId) -> Option<Duration> {
+        edges.get(&(a.min(b), a.max(b))).map(|seen| seen.elapsed())
+    }
+
+
     pub fn recive_flood_interface(
         grafo: &Graph,
         path_trace: Vec<(NodeId, NodeType)>,
@@ -376,13 +421,20 @@ pub mod interface {
                                 ));
                             }
 
-                            // 2. ARCHI
+                            // 2. ARCHI, colored by how recently a flood confirmed them
+                            let edge_times = srv.edge_last_seen.lock().map(|e| e.clone()).unwrap_or_default();
                             let mut seen = HashSet::new();
                             for (a, (vicini, _)) in grafo.iter() {
                                 for b in vicini {
                                     let (min, max) = if a < b { (a, b) } else { (b, a) };
                                     if seen.insert((min, max)) {
-                                        dot.push_str(&format!("    {} -- {};\n", min, max));
+                                        let style = match edge_age(&edge_times, *min, *max) {
+                                            // An edge seen since the last flood is drawn as fresh
+                                            Some(age) if age < REFRESH_INTERVAL => "color=black",
+                                            Some(_) => "color=darkorange, style=dashed",
+                                            None => "color=gray, style=dotted",
+                                        };
+                                        dot.push_str(&format!("    {} -- {} [{}];\n", min, max, style));
                                     }
                                 }
                             }
@@ -499,11 +551,19 @@ pub mod interface {
                                 }
                             ));
 
+                            if let Ok(transport) = srv.transport.lock() {
+                                info.push_str(&format!(
+                                    "Timeouts: {}  |  Retransmissions: {}  |  Abandoned sessions: {}\n",
+                                    transport.timeouts, transport.retries, transport.abandoned_sessions
+                                ));
+                            }
+
                             // Topologia
-                            info.push_str("\nGraph topology:\n");
+                            info.push_str("\nGraph topology (seconds since each edge was confirmed):\n");
                             let mut ids: Vec<_> = grafo.keys().cloned().collect();
                             ids.sort();
 
+                            let edge_times = srv.edge_last_seen.lock().map(|e| e.clone()).unwrap_or_default();
                             for id in ids {
                                 let mut vicini: Vec<_> = grafo.get(&id).expect("can access topology graph").0.iter().cloned().collect();
                                 vicini.sort();
@@ -515,7 +575,10 @@ pub mod interface {
                                     } else {
                                         vicini
                                             .iter()
-                                            .map(|x| x.to_string())
+                                            .map(|x| match edge_age(&edge_times, id, *x) {
+                                                Some(age) => format!("{} ({}s)", x, age.as_secs()),
+                                                None => format!("{} (?)", x),
+                                            })
                                             .collect::<Vec<_>>()
                                             .join(", ")
                                     }
@@ -7,7 +7,7 @@ mod logger;
 
 
 use crate::interface::interface::*;
-use crossbeam_channel::{select_biased, Receiver, Sender};
+use crossbeam_channel::{select_biased, tick, Receiver, Sender};
 use rand::{thread_rng, Rng, SeedableRng};
 use std::collections::HashMap;
 use std::ops::Index;
@@ -15,29 +15,39 @@ use wg_2024::network::*;
 
 use crate::message::file_system::ServerTrait;
 use crate::message::packaging::Repackager;
+use crate::message::retransmission::{RetransmissionTimers, TimeoutEvent, RETRANSMISSION_TICK};
+use crate::message::sliding_window::{SendWindow, DEFAULT_WINDOW_SIZE};
+use crate::message::discovery::{DiscoveryManager, DISCOVERY_TICK};
 use message::net_work as NewWork;
 use wg_2024::drone::Drone;
 use wg_2024::network::NodeId;
 use wg_2024::packet::PacketType::{Ack as AckType,  MsgFragment};
 use wg_2024::packet::{Ack, FloodRequest, FloodResponse, Fragment, NackType, NodeType, Packet, PacketType};
-use NewWork::bfs_shortest_path;
-use std::{sync::{Arc, Mutex}, thread, time::Duration};
+use NewWork::{most_reliable_path, nacked_sessions, node_disjoint_paths, Failure, PathSet, ReliabilityStats, DEFAULT_MAX_PATHS, MULTIPATH_MIN_FRAGMENTS};
+use std::{sync::{Arc, Mutex}, thread, time::{Duration, Instant}};
 use std::sync::atomic::AtomicUsize;
 use ratatui::style::Color;
 
 
 pub use message::file_system;
+pub use message::{discovery, retransmission, sliding_window};
+pub use message::net_work::CostModel;
 use crate::file_system::ServerType;
+use common::introspection::{DiscoveredView, IntrospectionRequest};
 //use crate::logger::logger::init_logger;
 //use crate::logger::logger::write_log;
 use rand::seq::SliceRandom;
 
+//The sessions opened by the server start here, far from the ids the clients use
+const PUSH_SESSION_START: u64 = u64::MAX / 2;
+
 pub struct  Server
 {
     id: NodeId,
     packet_recv: Receiver<Packet>,                  //packet receiver
     packet_send: HashMap<NodeId, Sender<Packet>>,   //directly connected neighbour (drone  for send the packet)  
     simulation_reciver: Receiver<NodeId>,
+    introspection_reciver: Receiver<IntrospectionRequest>,  //the controller asks here what the server discovered
     server_type: Box<dyn ServerTrait>,              //Server type 
     path : Option<String>,
 
@@ -45,8 +55,21 @@ pub struct  Server
     // extra field
     myInterface : ServerUiState,
     graph: HashMap<NodeId, Vec<NodeId>>,            //I need this for bfs
+    node_types: HashMap<NodeId, NodeType>,          //type of every node seen in a path trace
     package_handler: Repackager,                    // Fragment and reassemble file
-    paket_ack_manger: HashMap<(NodeId, u64), Vec<Fragment>>,        // Keep track of the ack 
+    paket_ack_manger: HashMap<(NodeId, u64), SendWindow>,        // Keep track of the ack 
+    window_size: usize,                             // fragments of a session sent without waiting for the ack
+    fragment_routes: HashMap<(NodeId, u64, u64), Vec<NodeId>>,     // route of every fragment not acked yet: (destination, session, fragment)
+    reliability: ReliabilityStats,                  // acks and drops seen for every drone and link
+    cost_model: CostModel,
+    session_paths: HashMap<(NodeId, u64), PathSet>,  // disjoint paths of the large sessions in the paket_ack_manger
+    max_paths: usize,
+    retransmission: RetransmissionTimers,           // timers of the fragments not acked yet
+    retransmission_ticker: Receiver<Instant>,
+    discovery: DiscoveryManager,                    // flood rounds and age of the edges of the graph
+    discovery_ticker: Receiver<Instant>,
+    deliveries: HashMap<(NodeId, u64), u64>,        // sessions carrying something the server type wants to know the fate of
+    next_push_session: u64,                         // session id of the messages the server sends on its own
 
 }
 
@@ -58,6 +81,7 @@ impl Server{
         packet_recv: Receiver<Packet>,
         packet_send : HashMap<NodeId, Sender<Packet>>,
         simulation_reciver : Receiver<NodeId>,
+        introspection_reciver : Receiver<IntrospectionRequest>,
         server_type: Box< dyn ServerTrait>,
         path : Option<String>,
         interface_hub : AllServersUi,
@@ -87,6 +111,8 @@ impl Server{
 
             selected_index: Arc::new(AtomicUsize::new(0)),
             selected_chat_index: Arc::new(AtomicUsize::new(0)),
+            transport: Arc::new(Mutex::new(TransportStats::default())),
+            edge_last_seen: Arc::new(Mutex::new(HashMap::new())),
 
         };
 /*
@@ -106,6 +132,7 @@ impl Server{
             packet_recv: packet_recv,
             packet_send: packet_send,   //directly connected neighbour.  
             simulation_reciver : simulation_reciver,
+            introspection_reciver : introspection_reciver,
             server_type: server_type,
 
             path: path,                 //path where the file are stored
@@ -113,8 +140,21 @@ impl Server{
 
             // extra field
             graph: graph,            //I nees this for bfs
+            node_types: HashMap::new(),
             package_handler: Repackager::new(),
             paket_ack_manger: HashMap::new(),
+            window_size: DEFAULT_WINDOW_SIZE,
+            fragment_routes: HashMap::new(),
+            reliability: ReliabilityStats::default(),
+            cost_model: CostModel::default(),
+            session_paths: HashMap::new(),
+            max_paths: DEFAULT_MAX_PATHS,
+            retransmission: RetransmissionTimers::default(),
+            retransmission_ticker: tick(RETRANSMISSION_TICK),
+            discovery: DiscoveryManager::default(),
+            discovery_ticker: tick(DISCOVERY_TICK),
+            deliveries: HashMap::new(),
+            next_push_session: PUSH_SESSION_START,
 
         }
     }
@@ -179,8 +219,9 @@ Start by sending a flood request to all the neighbour to fill up the graph
                         Ok(packet) => {
                            self.handle_packet(packet);
                         },
-                        Err(e) => {
-                            eprintln!("Error packet reception of server {}: {}", self.id, e)
+                        Err(_) => {
+                            //no node can reach the server anymore, the network was shut down
+                            return;
                         }
                     }
                 }
@@ -189,11 +230,10 @@ Start by sending a flood request to all the neighbour to fill up the graph
                     
                     match node {
                         Ok(node) => {
-                            NewWork::remove_neighbor(&mut self.graph,node);  
-                            
                             self.packet_send.remove(&node);
                             add_message(&self.myInterface.messages, "Server", "It seems that one drone has fallen, trying to send again the packet through another way",
                                     Color::White, Color::Blue);
+                            self.repair_routes(Failure::Node(node));
 
                         }
                         Err(e) => {
@@ -204,6 +244,23 @@ Start by sending a flood request to all the neighbour to fill up the graph
                     
 
                 }
+
+                recv(self.retransmission_ticker) -> _ => {
+                    self.check_timeouts();
+                }
+
+                recv(self.discovery_ticker) -> _ => {
+                    self.refresh_discovery();
+                    self.server_type.check_presence(Instant::now());
+                    self.send_outgoing();
+                }
+
+                recv(self.introspection_reciver) -> request => {
+                    if let Ok(request) = request {
+                        //the controller may have stopped waiting, nothing to do then
+                        let _ = request.reply.send(self.discovered_view());
+                    }
+                }
             }
         }
     }
@@ -283,6 +340,7 @@ Start by sending a flood request to all the neighbour to fill up the graph
 
                         //Process the request
                         let result =self.server_type.process_request(message.unwrap_or("Error while processing".to_string()),source_id as u32,&mut flag);
+                        let delivery_id = self.server_type.take_delivery_id();
 
                         if flag==1
                         {
@@ -339,10 +397,17 @@ Start by sending a flood request to all the neighbour to fill up the graph
                                 //------
 
                                 //It's the structure used to control the that the packet send to the client are received correctly 
-                                self.paket_ack_manger.insert((source_id, packet.session_id), value.clone());
+                                let mut window = SendWindow::new(value, self.window_size);
 
-                                //It starts sending the first fragment to the client.
-                                response.pack_type = MsgFragment(value.index(0).clone());
+                                //It starts sending the first window of fragments to the client.
+                                let first_fragments = window.next_to_send();
+                                if window.len() >= MULTIPATH_MIN_FRAGMENTS {
+                                    self.spread_session((source_id, packet.session_id));
+                                }
+                                self.paket_ack_manger.insert((source_id, packet.session_id), window);
+                                if let Some(delivery_id) = delivery_id {
+                                    self.deliveries.insert((source_id, packet.session_id), delivery_id);
+                                }
 
 
 
@@ -361,13 +426,19 @@ Start by sending a flood request to all the neighbour to fill up the graph
 
 
 
-                                self.send_valid_packet(source_id, response);
+                                self.send_fragments(source_id, packet.session_id, first_fragments);
                             }
                             Err(x) => {
                                 add_message(&self.myInterface.messages, "Server", &format!("ERRORE: {}",x), Color::White, Color::Red);
+                                if let Some(delivery_id) = delivery_id {
+                                    self.server_type.delivery_report(delivery_id, false);
+                                }
                             }
                         }
 
+                        //the request could have unlocked messages for other clients (mailbox, receipts)
+                        self.send_outgoing();
+
                     }
                     _=> {
                         add_message(&self.myInterface.messages, "Server", "Error I was unable to process the packet. It could be because the fragment to long (I refuse to process)", Color::White, Color::Red); }
@@ -380,43 +451,89 @@ Start by sending a flood request to all the neighbour to fill up the graph
 
             PacketType::Nack(msg) => {
 
+                //the Nack is sent back by the drone that found the problem, so it's the first hop
+                let mut repaired = Vec::new();
+                let nack_hops = match msg.nack_type {
+                    //the drone that got a packet meant for someone else is not on my route
+                    NackType::UnexpectedRecipient(_) => packet.routing_header.hops.get(1..).unwrap_or_default(),
+                    _ => &packet.routing_header.hops[..],
+                };
+                let sessions = nacked_sessions(self.paket_ack_manger.keys().cloned(), &self.fragment_routes, nack_hops, packet.session_id, msg.fragment_index);
                 match msg.nack_type {
                     NackType::ErrorInRouting(Nodeid) => {   //contains id or not neighbor
-                        //if we ended up here it means that the drone tryed to pass the packet that is not one of his neighbour thus (since my routing is correct) 
-                        // the drone not found is dropped
-
-                        add_message(&self.myInterface.messages, "Server", "It seems that one drone has fallen, trying to send again the packet through another way",
+                        //the drone that sent the Nack has no link to Nodeid anymore (crash or removed link)
+                        add_message(&self.myInterface.messages, "Server",
+                                    &format!("Drone {} can't reach {}, removing the link and looking for another way", source_id, Nodeid),
                                     Color::White, Color::Blue);
 
-                        NewWork::remove_neighbor(&mut self.graph,Nodeid);
+                        repaired = self.repair_routes(Failure::Link(source_id, Nodeid));
                     }
                     NackType::DestinationIsDrone => {
-                        //what can I do in this case. It 's impossible ??
+                        //my route ended on a drone, so what I know about the network is wrong: I ask it again
+                        add_message(&self.myInterface.messages, "Server",
+                                    &format!("The route of session {} ended on drone {}, flooding the network again", packet.session_id, source_id),
+                                    Color::White, Color::Yellow);
+                        self.node_types.insert(source_id, NodeType::Drone);
+                        self.refresh_topology();
                     }
                     NackType::Dropped => {
-
-                        add_message(&self.myInterface.messages, "Server","I received a NACK (packet dropped)", Color::White, Color::Blue);
+                        //the link works, the drone just lost the fragment: it's enough to send it again, maybe on a more reliable route
+                        add_message(&self.myInterface.messages, "Server", &format!("I received a NACK (packet dropped by {})", source_id), Color::White, Color::Blue);
+                        let route = sessions.first()
+                            .and_then(|(destination, session_id)| self.fragment_routes.get(&(*destination, *session_id, msg.fragment_index)))
+                            .cloned()
+                            //the Nack came back on the route of the fragment, up to the drone that dropped it
+                            .unwrap_or_else(|| packet.routing_header.hops.iter().rev().cloned().collect());
+                        self.reliability.record_dropped(&route, source_id);
                     }
 
                     NackType::UnexpectedRecipient(Nodeid) => {
-                        //no idea 
+                        //the hop before Nodeid delivered to it instead of the next hop of my route, so that link is not what I think
+                        let previous_hop = packet.routing_header.hops.get(1).cloned();
+                        let expected_hop = sessions.first()
+                            .and_then(|(destination, _)| self.route_to(*destination))
+                            .and_then(|route| {
+                                let position = route.hops.iter().position(|hop| Some(*hop) == previous_hop)?;
+                                route.hops.get(position + 1).cloned()
+                            });
+
+                        match (previous_hop, expected_hop) {
+                            (Some(previous_hop), Some(expected_hop)) => {
+                                add_message(&self.myInterface.messages, "Server",
+                                            &format!("Drone {} received a packet meant for {}, removing the link {} - {}", Nodeid, expected_hop, previous_hop, expected_hop),
+                                            Color::White, Color::Blue);
+                                repaired = self.repair_routes(Failure::Link(previous_hop, expected_hop));
+                            }
+                            _ => {
+                                add_message(&self.myInterface.messages, "Server",
+                                            &format!("Drone {} received a packet that was not for it, flooding the network again", Nodeid),
+                                            Color::White, Color::Yellow);
+                                self.refresh_topology();
+                            }
+                        }
                     }
 
                 }
                 let mut found =  false;
+                let mut to_resend = Vec::new();
 
                 //try to find the packet in the packet ack manager
-                for ((source_id, session_id), vec) in self.paket_ack_manger.iter() {
-                    if *session_id == packet.session_id {
-
-                        let mut new_response = response.clone();
-                        new_response.pack_type = MsgFragment(vec[msg.fragment_index as usize].clone());
-                        self.send_valid_packet(*source_id, new_response);
-                        found = true;
-
-                        add_message(&self.myInterface.messages, "Server",&format!("I sent another time a message to {}",source_id), Color::White, Color::Blue);
-
+                for (source_id, session_id) in &sessions {
+                    let Some(fragment) = self.paket_ack_manger.get(&(*source_id, *session_id))
+                        .and_then(|window| window.fragment(msg.fragment_index)) else {
+                        continue;
+                    };
+                    found = true;
+                    if repaired.contains(&(*source_id, *session_id)) {
+                        //the route repair already sent it again
+                        continue;
                     }
+                    to_resend.push((*source_id, *session_id, fragment.clone()));
+                }
+
+                for (source_id, session_id, fragment) in to_resend {
+                    self.send_fragments(source_id, session_id, vec![fragment]);
+                    add_message(&self.myInterface.messages, "Server",&format!("I sent another time a message to {}",source_id), Color::White, Color::Blue);
                 }
 
                 if !found {
@@ -435,37 +552,41 @@ Start by sending a flood request to all the neighbour to fill up the graph
 
 
 
-            PacketType::Ack(msg) => {   //Send the next packet 
-                let result =self.paket_ack_manger.get(&(source_id, packet.session_id)); //Get the correct session
+            PacketType::Ack(msg) => {   //Send the fragments that entered the window
+                self.server_type.heard_from(source_id as u32, Instant::now());
+                let result =self.paket_ack_manger.get_mut(&(source_id, packet.session_id)); //Get the correct session
                 match result {
                     None => {
                         add_message(&self.myInterface.messages, "Server", " Received an Ack but I can't trace back the number to any packet ", Color::White, Color::Red);
 
                     }
-                    Some(ack_value) => {
+                    Some(window) => {
                         /*
                         The ack manager is a structure that use for a ky the source_id and the session id
-                        it has a vector containing all the packet that need to be sends
+                        it has a window over all the packet that need to be sends
                          */
-                        //I try to get the next packet that is needs to be sent 
-                        if let Some(pos) = ack_value.iter().position(|f| f.fragment_index == msg.fragment_index + 1) {
-
-                            //Send the next packet
-                            response.pack_type = MsgFragment(ack_value[pos].clone());
-                            add_message(&self.myInterface.messages, "Server", &format!("Received a ack from source id: {} and Session id: {}",source_id,packet.session_id, ), Color::White, Color::White);
-
-                            self.send_valid_packet(source_id, response);
-                            add_message(&self.myInterface.messages, "Server", &format!("Sent packet number : {} / {} to client: {}",pos ,ack_value.len(),source_id), Color::White, Color::White);
+                        if !window.ack(msg.fragment_index) {
+                            add_message(&self.myInterface.messages, "Server", &format!("Ack of fragment {} already received or never sent.", msg.fragment_index), Color::White, Color::Yellow);
+                            return;
+                        }
+                        self.retransmission.acked((source_id, packet.session_id), msg.fragment_index, Instant::now());
+                        if let Some(route) = self.fragment_routes.remove(&(source_id, packet.session_id, msg.fragment_index)) {
+                            self.reliability.record_delivered(&route);
+                        }
+                        add_message(&self.myInterface.messages, "Server", &format!("Received a ack from source id: {} and Session id: {}",source_id,packet.session_id, ), Color::White, Color::White);
 
+                        //Check if all the packets are arrived correctly
+                        if window.is_complete() {
+                            add_message(&self.myInterface.messages, "Server", &format!("All ack received, removing session - (Source id: {}, Session id: {})",source_id,packet.session_id), Color::White, Color::White);
+                            self.close_session((source_id, packet.session_id), true);
                         }
-                        else {  //Check if all the packets are arrived correctly 
-                            if  msg.fragment_index as usize  == ack_value.len()-1
-                            {
-                                add_message(&self.myInterface.messages, "Server", &format!("All ack received, removing session - (Source id: {}, Session id: {})",source_id,packet.session_id), Color::White, Color::White);
-                                self.paket_ack_manger.remove(&(source_id, packet.session_id));
-                            }
-                            else {
-                                add_message(&self.myInterface.messages, "Server", "Index of Nack not found.", Color::White, Color::Red);
+                        else {
+                            let acked = window.acked_count();
+                            let total = window.len();
+                            let next_fragments = window.next_to_send();
+                            if !next_fragments.is_empty() {
+                                self.send_fragments(source_id, packet.session_id, next_fragments);
+                                add_message(&self.myInterface.messages, "Server", &format!("Acked packets : {} / {} from client: {}",acked ,total,source_id), Color::White, Color::White);
                             }
                         }
 
@@ -476,7 +597,9 @@ Start by sending a flood request to all the neighbour to fill up the graph
 
             PacketType::FloodResponse(path) => {
                 add_message(&self.myInterface.messages, "Server", "Received FloodResponse", Color::White, Color::White);
+                self.node_types.extend(path.path_trace.iter().cloned());
                 recive_flood_interface(&self.myInterface.graph,path.path_trace.clone());
+                self.confirm_path_trace(&path.path_trace);
                 NewWork::recive_flood_response(&mut self.graph, path.path_trace);            //It's not the job of the server to propagate the message is not a drone
             }
 
@@ -491,6 +614,7 @@ Start by sending a flood request to all the neighbour to fill up the graph
                     {
                         previous_neighbour = last.0;
                         flood_packet.path_trace.push((self.id,NodeType::Server)); //TODO fixed here
+                        self.node_types.extend(flood_packet.path_trace.iter().cloned());
                         //The Server is note a drone so when he receives a flood request he can send back a flood response with no problem
                         let new_hops = flood_packet.path_trace
                             .iter()
@@ -504,6 +628,7 @@ Start by sending a flood request to all the neighbour to fill up the graph
                             path_trace: flood_packet.path_trace.clone()
                         };
                         let response = Packet::new_flood_response(srh,packet.session_id,flood_resp);
+                        self.confirm_path_trace(&flood_packet.path_trace);
                         NewWork::recive_flood_response(&mut self.graph, flood_packet.path_trace);
 
                         //for (id,sendr) in &self.packet_send{    //send flood response to all his neibourgh
@@ -533,18 +658,303 @@ Start by sending a flood request to all the neighbour to fill up the graph
 
     }
 
-    fn sendflod_request(& self)
-    {
+    /*
+    Removes the failed node or link from the graph and sends again, on the new route, the fragments
+    not acked yet of every session whose route went through it.
+    When a route fails the network is flooded again. If a destination can't be reached anymore the
+    retransmission timers send the fragments once the new flood responses rebuild a route.
+    Gives back the sessions that were sent again.
+     */
+    fn repair_routes(&mut self, failure: Failure) -> Vec<(NodeId, u64)> {
+        let affected: Vec<(NodeId, u64)> = self.paket_ack_manger.keys()
+            .filter(|(destination, session_id)| {
+                self.route_to(*destination)
+                    .map_or(true, |route| failure.breaks(&route.hops))
+                    || self.fragment_routes.iter().any(|((d, s, _), route)| {
+                        d == destination && s == session_id && failure.breaks(route)
+                    })
+            })
+            .cloned()
+            .collect();
+        self.remove_broken_paths(&failure);
+        match failure {
+            Failure::Node(node) => {
+                for neighbor in self.graph.get(&node).cloned().unwrap_or_default() {
+                    remove_interface_link(&self.myInterface.graph, node, neighbor);
+                }
+                self.discovery.forget_node(node);
+            }
+            Failure::Link(a, b) => {
+                remove_interface_link(&self.myInterface.graph, a, b);
+                self.discovery.forget_link(a, b);
+            }
+        }
+        failure.apply(&mut self.graph);
+        update_edge_freshness(&self.myInterface.edge_last_seen, self.discovery.last_seen());
+
+        let mut repaired = Vec::new();
+        let mut unreachable = Vec::new();
+        for (destination, session_id) in affected {
+            if self.route_to(destination).is_none() {
+                unreachable.push(destination);
+                continue;
+            }
+            //only the fragments that were on a broken route, the others are still on their way
+            let fragments: Vec<Fragment> = match self.paket_ack_manger.get(&(destination, session_id)) {
+                Some(window) => window.in_flight_fragments().into_iter()
+                    .filter(|fragment| {
+                        self.fragment_routes.get(&(destination, session_id, fragment.fragment_index))
+                            .map_or(true, |route| failure.breaks(route))
+                    })
+                    .collect(),
+                None => continue,
+            };
+            if fragments.is_empty() {
+                continue;
+            }
+            add_message(&self.myInterface.messages, "Server",
+                        &format!("New route to client {}, sending again {} fragments of session {}", destination, fragments.len(), session_id),
+                        Color::White, Color::Blue);
+            self.send_fragments(destination, session_id, fragments);
+            repaired.push((destination, session_id));
+        }
+
+        if !unreachable.is_empty() {
+            add_message(&self.myInterface.messages, "Server",
+                        &format!("No route left to {:?}, flooding the network again", unreachable),
+                        Color::White, Color::Yellow);
+        }
+        if !repaired.is_empty() || !unreachable.is_empty() {
+            //a route failed, what I know about the network may be old
+            self.refresh_topology();
+        }
+        repaired
+    }
 
+    //Floods the network after a failure, unless a flood just started
+    fn refresh_topology(&mut self) {
+        if self.discovery.can_flood(Instant::now()) {
+            self.sendflod_request();
+        }
+    }
+
+    //Removes the edges no flood confirmed for a while and starts the periodic flood when it's time
+    fn refresh_discovery(&mut self) {
+        let now = Instant::now();
+        for (a, b) in self.discovery.expired_edges(now) {
+            add_message(&self.myInterface.messages, "Server",
+                        &format!("The link {} - {} was not seen for a while, removing it", a, b),
+                        Color::White, Color::Yellow);
+            NewWork::remove_link(&mut self.graph, a, b);
+            remove_interface_link(&self.myInterface.graph, a, b);
+            self.remove_broken_paths(&Failure::Link(a, b));
+        }
+        if self.discovery.should_refresh(now) {
+            add_message(&self.myInterface.messages, "Server", "Refreshing the topology", Color::White, Color::Blue);
+            self.sendflod_request();
+        }
+        update_edge_freshness(&self.myInterface.edge_last_seen, self.discovery.last_seen());
+    }
+
+    fn confirm_path_trace(&mut self, path_trace: &[(NodeId, NodeType)]) {
+        let path: Vec<NodeId> = path_trace.iter().map(|(id, _)| *id).collect();
+        self.discovery.confirm_path(&path, Instant::now());
+        update_edge_freshness(&self.myInterface.edge_last_seen, self.discovery.last_seen());
+    }
+
+    //Fragments per session sent before waiting for an ack. 1 is the old stop-and-wait
+    pub fn set_window_size(&mut self, window_size: usize) {
+        self.window_size = window_size.max(1);
+    }
+
+    //Sends fragments of a session and starts their retransmission timers
+    fn send_fragments(&mut self, destination: NodeId, session_id: u64, fragments: Vec<Fragment>) {
+        for fragment in fragments {
+            let fragment_index = fragment.fragment_index;
+            self.retransmission.sent((destination, session_id), fragment_index, Instant::now());
+            let packet = Packet::new_fragment(SourceRoutingHeader::empty_route(), session_id, fragment);
+            //a large session takes turns on its paths, the others use the best route
+            let path = self.session_paths.get_mut(&(destination, session_id))
+                .and_then(|paths| paths.pick(&self.reliability, &self.cost_model));
+            let sent = match path {
+                Some(hops) => self.send_on_route(SourceRoutingHeader::with_first_hop(hops), packet),
+                None => self.send_packet(destination, packet),
+            };
+            //the route is remembered to know which drones delivered or lost the fragment
+            match sent {
+                Some(route) => {self.fragment_routes.insert((destination, session_id, fragment_index), route);}
+                None => {self.fragment_routes.remove(&(destination, session_id, fragment_index));}
+            }
+        }
+    }
+
+    //Forgets everything about a session that ended (delivered) or was abandoned
+    fn close_session(&mut self, session: (NodeId, u64), delivered: bool) {
+        self.paket_ack_manger.remove(&session);
+        self.session_paths.remove(&session);
+        self.retransmission.remove_session(session);
+        self.fragment_routes.retain(|(destination, session_id, _), _| (*destination, *session_id) != session);
+        if let Some(delivery_id) = self.deliveries.remove(&session) {
+            self.server_type.delivery_report(delivery_id, delivered);
+            self.send_outgoing();
+        }
+    }
+
+    //Sends the messages the server type wants to push, each one in a new session of the server
+    fn send_outgoing(&mut self) {
+        for outgoing in self.server_type.take_outgoing() {
+            let destination = outgoing.destination as NodeId;
+            let fragments = match Repackager::create_fragments(&outgoing.message, None) {
+                Ok(fragments) => fragments,
+                Err(error) => {
+                    add_message(&self.myInterface.messages, "Server", &format!("Unable to send a message to {}: {}", destination, error), Color::White, Color::Red);
+                    if let Some(delivery_id) = outgoing.delivery_id {
+                        self.server_type.delivery_report(delivery_id, false);
+                    }
+                    continue;
+                }
+            };
+            let session_id = self.next_push_session;
+            self.next_push_session += 1;
+            add_message(&self.myInterface.messages, "Server", &format!("Pushing {} to client {}", outgoing.message, destination), Color::White, Color::Blue);
+
+            let mut window = SendWindow::new(fragments, self.window_size);
+            let first_fragments = window.next_to_send();
+            if window.len() >= MULTIPATH_MIN_FRAGMENTS {
+                self.spread_session((destination, session_id));
+            }
+            self.paket_ack_manger.insert((destination, session_id), window);
+            if let Some(delivery_id) = outgoing.delivery_id {
+                self.deliveries.insert((destination, session_id), delivery_id);
+            }
+            self.send_fragments(destination, session_id, first_fragments);
+        }
+    }
+
+    //Route to a node: the most reliable one given the acks and nacks seen so far, the shortest without them
+    fn route_to(&self, destination: NodeId) -> Option<SourceRoutingHeader> {
+        most_reliable_path(&self.graph, &self.node_types, &self.reliability, &self.cost_model, self.id, destination)
+    }
+
+    //Spreads a large session on up to max_paths disjoint paths, if there is more than one
+    fn spread_session(&mut self, session: (NodeId, u64)) {
+        if self.max_paths < 2 {
+            return;
+        }
+        let paths = node_disjoint_paths(&self.graph, &self.node_types, &self.reliability, &self.cost_model, self.id, session.0, self.max_paths);
+        if paths.len() < 2 {
+            return;
+        }
+        add_message(&self.myInterface.messages, "Server",
+                    &format!("Spreading session {} to client {} on {} disjoint paths", session.1, session.0, paths.len()),
+                    Color::White, Color::Blue);
+        self.session_paths.insert(session, PathSet::new(paths.into_iter().map(|path| path.hops).collect()));
+    }
+
+    //Takes the paths through a failure out of every session, a session left without paths goes back to the best route
+    fn remove_broken_paths(&mut self, failure: &Failure) {
+        for ((destination, session_id), paths) in self.session_paths.iter_mut() {
+            let removed = paths.remove_broken(failure);
+            if removed > 0 {
+                add_message(&self.myInterface.messages, "Server",
+                            &format!("Removed {} broken paths of session {} to client {}, {} left", removed, session_id, destination, paths.len()),
+                            Color::White, Color::Yellow);
+            }
+        }
+        self.session_paths.retain(|_, paths| !paths.is_empty());
+    }
+
+    //Disjoint paths used for the sessions with at least MULTIPATH_MIN_FRAGMENTS fragments, 1 disables multipath
+    pub fn set_max_paths(&mut self, max_paths: usize) {
+        self.max_paths = max_paths.max(1);
+    }
+
+    //How delivery statistics are turned into route costs
+    pub fn set_cost_model(&mut self, cost_model: CostModel) {
+        self.cost_model = cost_model;
+    }
+
+    //Sends again the fragments whose ack did not arrive in time and drops the sessions that gave up
+    fn check_timeouts(&mut self) {
+        for event in self.retransmission.poll(Instant::now()) {
+            match event {
+                TimeoutEvent::Retransmit { session: (destination, session_id), fragment_index, attempt } => {
+                    let fragment = self.paket_ack_manger.get(&(destination, session_id))
+                        .and_then(|window| window.fragment(fragment_index))
+                        .cloned();
+                    match fragment {
+                        None => {
+                            //the session was closed in the meantime
+                            self.retransmission.remove_session((destination, session_id));
+                        }
+                        Some(fragment) => {
+                            add_message(&self.myInterface.messages, "Server",
+                                        &format!("Timeout of fragment {} (session {}, client {}), retry number {}", fragment_index, session_id, destination, attempt),
+                                        Color::White, Color::Yellow);
+                            update_transport_stats(&self.myInterface.transport, |stats| {
+                                stats.timeouts += 1;
+                                stats.retries += 1;
+                            });
+                            if let Some(route) = self.fragment_routes.get(&(destination, session_id, fragment_index)) {
+                                self.reliability.record_timeout(route);
+                            }
+                            self.send_fragments(destination, session_id, vec![fragment]);
+                        }
+                    }
+                }
+                TimeoutEvent::Abandoned { session: (destination, session_id), fragment_index } => {
+                    add_message(&self.myInterface.messages, "Server",
+                                &format!("Fragment {} never acked, abandoning session {} of client {}", fragment_index, session_id, destination),
+                                Color::White, Color::Red);
+                    update_transport_stats(&self.myInterface.transport, |stats| {
+                        stats.timeouts += 1;
+                        stats.abandoned_sessions += 1;
+                    });
+                    if let Some(route) = self.fragment_routes.get(&(destination, session_id, fragment_index)) {
+                        self.reliability.record_timeout(route);
+                    }
+                    self.close_session((destination, session_id), false);
+                }
+                TimeoutEvent::Expired { session: (destination, session_id) } => {
+                    add_message(&self.myInterface.messages, "Server",
+                                &format!("Session {} of client {} expired, removing it", session_id, destination),
+                                Color::White, Color::Red);
+                    update_transport_stats(&self.myInterface.transport, |stats| stats.abandoned_sessions += 1);
+                    self.close_session((destination, session_id), false);
+                }
+            }
+        }
+    }
+
+    //What the server discovered about the network, for the simulation controller
+    fn discovered_view(&self) -> DiscoveredView {
+        let known_nodes = |node_type: NodeType| {
+            self.node_types
+                .iter()
+                .filter(move |(id, kind)| **kind == node_type && **id != self.id)
+                .map(|(id, _)| *id)
+        };
+
+        DiscoveredView::from_graph(self.id, &self.graph).with_known(
+            self.paket_ack_manger.keys().map(|(_, session_id)| *session_id),
+            known_nodes(NodeType::Server),
+            known_nodes(NodeType::Client),
+        )
+    }
+
+    fn sendflod_request(&mut self)
+    {
+        //the same flood_id for all the neighbours, so the drones flood this round only once
+        let flood_id = self.discovery.start_round(Instant::now());
 
 
         //Send flood request to all his neighbour
         for (node,sender) in self.packet_send.iter() {
-            add_message(&self.myInterface.messages, "Server", "Sending flood request to node ", Color::White, Color::Blue);
+            add_message(&self.myInterface.messages, "Server", &format!("Sending flood request {} to node {}", flood_id, node), Color::White, Color::Blue);
 
 
             let request = FloodRequest {
-                flood_id : rand::random(),
+                flood_id,
                 initiator_id: self.id,
                 path_trace: vec![(self.id, NodeType::Server)],
             };
@@ -574,33 +984,40 @@ Start by sending a flood request to all the neighbour to fill up the graph
               .expect("Failed to send message to simulation controller");
       }*/
 
-    fn send_packet(& self, dest_id: NodeId, mut packet: Packet) {
+    //Gives back the hops of the route used, if the packet was sent
+    fn send_packet(& self, dest_id: NodeId, mut packet: Packet) -> Option<Vec<NodeId>> {
 
         // let sender = self.packet_send.get(&dest_id);
 
-        match NewWork::bfs_shortest_path(&self.graph, self.id, dest_id) {
-            Some(path) => {
-                add_message(&self.myInterface.messages, "Server", &format!("path: {}",path ), Color::White, Color::White);
-
-                packet.routing_header = path;
-                let c =self.packet_send.get(&packet.routing_header.hops[1]);    //take the first node to which you need to send the messages
-                match c {
-                    None => {
-                        add_message(&self.myInterface.messages, "Server", "I was not able to find a routing header to the destination!!!!", Color::White, Color::Red);
-
-                    }
-                    Some(x) => {x.send(packet);}
-                }
-            },
+        match self.route_to(dest_id) {
+            Some(path) => self.send_on_route(path, packet),
             None => {
                 add_message(&self.myInterface.messages, "Server", "Error not found a valid path to follow", Color::White, Color::Red);
-
+                None
             }
 
         }
 
     }
 
+    fn send_on_route(& self, path: SourceRoutingHeader, mut packet: Packet) -> Option<Vec<NodeId>> {
+        add_message(&self.myInterface.messages, "Server", &format!("path: {}",path ), Color::White, Color::White);
+
+        packet.routing_header = path;
+        let c =self.packet_send.get(packet.routing_header.hops.get(1)?);    //take the first node to which you need to send the messages
+        match c {
+            None => {
+                add_message(&self.myInterface.messages, "Server", "I was not able to find a routing header to the destination!!!!", Color::White, Color::Red);
+                None
+            }
+            Some(x) => {
+                let hops = packet.routing_header.hops.clone();
+                x.send(packet);
+                Some(hops)
+            }
+        }
+    }
+
     fn send_valid_packet(& self, dest_id: NodeId, packet: Packet) {
         self.send_packet(dest_id, packet.clone());
         // self.send_packet_sent_event(packet);
@@ -6,7 +6,22 @@ The principal function are :
 NETWOR:
     bfs_shortest_path (Tree graph , start_ID , goal_ID)-> gives back the shortest path from start to goal
     remove_neighbor (Tree graph , node_ID, neighbour_ID ) -> remove a neighbour from a node. It's never used
+    remove_link (Tree graph , node_ID, neighbour_ID ) -> remove the link between two nodes
+    most_reliable_path (Tree graph , node types , stats , cost model , start_ID , goal_ID) -> the path most likely to deliver, BFS when there are no stats. Only drones are hops in the middle
+    node_disjoint_paths (Tree graph , node types , stats , cost model , start_ID , goal_ID, k) -> up to k paths with no drone in common
+    PathSet -> the paths a large transfer is spread on, weighted by their delivery probability
+    Failure -> a crashed node or a broken link, with the paths it breaks
     recive_flood_response  this add to the graph the the id of the flood response
+RETRANSMISSION:
+    RetransmissionTimers -> one timer for every fragment not acked yet, with exponential backoff and session expiry
+SLIDING WINDOW:
+    SendWindow -> the fragments of a response, sent a window at a time and acked one by one (selective repeat)
+DISCOVERY:
+    DiscoveryManager -> flood rounds with one flood_id, periodic refresh and aging of the edges not seen anymore
+CHAT SERVER:
+    mailboxes -> messages a client missed, delivered when it contacts the server again, with a receipt to the sender
+    presence -> register?(name) / unregister?, a client is online until CLIENT_TIMEOUT without hearing from it,
+                the others get client_joined! / client_left!
 
 
 
@@ -19,10 +34,14 @@ use crate::message::file_system::{ChatServer, ContentServer};
 
 
 pub mod net_work {
-    use std::collections::{HashMap, VecDeque};
+    use std::cmp::Ordering;
+    use std::collections::{BinaryHeap, HashMap, VecDeque};
     use wg_2024::network::{NodeId, SourceRoutingHeader};
     use wg_2024::packet::{Fragment, NodeType};
 
+    pub const DEFAULT_MAX_PATHS: usize = 3;         //disjoint paths a large transfer is spread on
+    pub const MULTIPATH_MIN_FRAGMENTS: usize = 16;  //smaller responses use only the best path
+
     //It gives back the shortest path possible  BFS  complexity O(V+E) , shutout to Montresor.
     pub fn bfs_shortest_path(
         graph: &HashMap<NodeId, Vec<NodeId>>,
@@ -66,6 +85,344 @@ pub mod net_work {
         None // No path found !BIG PROBLEM!
     }
 
+    // Acks and drops seen for a drone or a link. Timeouts count as a share of a drop
+    #[derive(Debug, Clone, Copy, Default, PartialEq)]
+    pub struct DeliveryStats {
+        pub delivered: f64,
+        pub dropped: f64,
+    }
+
+    // How the statistics become the cost of a route
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct CostModel {
+        pub prior_delivered: f64,   // deliveries assumed before any data, so one drop doesn't make a drone useless
+        pub prior_dropped: f64,     // drops assumed before any data
+        pub min_probability: f64,   // a hop is never considered worse than this
+        pub hop_penalty: f64,       // extra cost of every hop, with the same reliability the shorter route wins
+    }
+
+    impl Default for CostModel {
+        fn default() -> Self {
+            CostModel {
+                prior_delivered: 2.0,
+                prior_dropped: 0.0,
+                min_probability: 0.01,
+                hop_penalty: 0.01,
+            }
+        }
+    }
+
+    impl CostModel {
+        pub fn delivery_probability(&self, stats: Option<&DeliveryStats>) -> f64 {
+            let stats = stats.cloned().unwrap_or_default();
+            let delivered = stats.delivered + self.prior_delivered;
+            let total = delivered + stats.dropped + self.prior_dropped;
+            if total <= 0.0 {
+                return 1.0;
+            }
+            (delivered / total).clamp(self.min_probability, 1.0)
+        }
+    }
+
+    // Delivery statistics of every drone and link the server sent fragments through
+    #[derive(Debug, Clone, Default)]
+    pub struct ReliabilityStats {
+        nodes: HashMap<NodeId, DeliveryStats>,
+        links: HashMap<(NodeId, NodeId), DeliveryStats>,   // lower id first
+    }
+
+    impl ReliabilityStats {
+        pub fn is_empty(&self) -> bool {
+            self.nodes.is_empty() && self.links.is_empty()
+        }
+
+        pub fn node(&self, node: NodeId) -> Option<&DeliveryStats> {
+            self.nodes.get(&node)
+        }
+
+        pub fn link(&self, a: NodeId, b: NodeId) -> Option<&DeliveryStats> {
+            self.links.get(&(a.min(b), a.max(b)))
+        }
+
+        //The fragment was acked: every drone and link of the route delivered it
+        pub fn record_delivered(&mut self, route: &[NodeId]) {
+            for hop in route.windows(2) {
+                self.link_mut(hop[0], hop[1]).delivered += 1.0;
+            }
+            for node in Self::drones(route) {
+                self.nodes.entry(*node).or_default().delivered += 1.0;
+            }
+        }
+
+        //The drone `at` sent back a Dropped Nack: the part of the route before it worked
+        pub fn record_dropped(&mut self, route: &[NodeId], at: NodeId) {
+            let Some(position) = route.iter().position(|node| *node == at) else {
+                return;
+            };
+            for hop in route[..=position].windows(2) {
+                self.link_mut(hop[0], hop[1]).delivered += 1.0;
+            }
+            for node in route.iter().take(position).skip(1) {
+                self.nodes.entry(*node).or_default().delivered += 1.0;
+            }
+            self.nodes.entry(at).or_default().dropped += 1.0;
+        }
+
+        //No answer at all: the drop is shared between all the drones of the route
+        pub fn record_timeout(&mut self, route: &[NodeId]) {
+            let drones = Self::drones(route);
+            if drones.is_empty() {
+                return;
+            }
+            let share = 1.0 / drones.len() as f64;
+            for node in drones {
+                self.nodes.entry(*node).or_default().dropped += share;
+            }
+        }
+
+        //Probability that a fragment goes from the first to the last node of the route
+        pub fn route_probability(&self, route: &[NodeId], model: &CostModel) -> f64 {
+            let links: f64 = route.windows(2)
+                .map(|hop| model.delivery_probability(self.link(hop[0], hop[1])))
+                .product();
+            let nodes: f64 = Self::drones(route).iter()
+                .map(|node| model.delivery_probability(self.node(*node)))
+                .product();
+            links * nodes
+        }
+
+        fn link_mut(&mut self, a: NodeId, b: NodeId) -> &mut DeliveryStats {
+            self.links.entry((a.min(b), a.max(b))).or_default()
+        }
+
+        //The nodes between the two ends of the route
+        fn drones(route: &[NodeId]) -> &[NodeId] {
+            if route.len() < 2 {
+                return &[];
+            }
+            &route[1..route.len() - 1]
+        }
+    }
+
+    // Node of the Dijkstra queue, the lowest cost comes out first
+    struct QueueEntry {
+        cost: f64,
+        node: NodeId,
+    }
+
+    impl PartialEq for QueueEntry {
+        fn eq(&self, other: &Self) -> bool {
+            self.cmp(other) == Ordering::Equal
+        }
+    }
+
+    impl Eq for QueueEntry {}
+
+    impl PartialOrd for QueueEntry {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for QueueEntry {
+        fn cmp(&self, other: &Self) -> Ordering {
+            other.cost.total_cmp(&self.cost).then_with(|| other.node.cmp(&self.node))
+        }
+    }
+
+    //The graph where only drones have neighbours: clients and servers don't forward packets, so a route can only end on them
+    fn through_drones(
+        graph: &HashMap<NodeId, Vec<NodeId>>,
+        node_types: &HashMap<NodeId, NodeType>,
+        start: NodeId,
+    ) -> HashMap<NodeId, Vec<NodeId>> {
+        graph.iter()
+            .map(|(&node, neighbors)| {
+                if node == start || node_types.get(&node) == Some(&NodeType::Drone) {
+                    (node, neighbors.clone())
+                } else {
+                    (node, Vec::new())
+                }
+            })
+            .collect()
+    }
+
+    /*
+    Gives back the route with the highest delivery probability (Dijkstra on -ln(probability) plus the hop penalty).
+    Without any statistic it's the same as the BFS, so it just uses it.
+    Only the nodes known to be drones are expanded, the others can only be the goal.
+     */
+    pub fn most_reliable_path(
+        graph: &HashMap<NodeId, Vec<NodeId>>,
+        node_types: &HashMap<NodeId, NodeType>,
+        stats: &ReliabilityStats,
+        model: &CostModel,
+        start: NodeId,
+        goal: NodeId,
+    ) -> Option<SourceRoutingHeader> {
+        let graph = &through_drones(graph, node_types, start);
+        if stats.is_empty() {
+            return bfs_shortest_path(graph, start, goal);
+        }
+
+        let hop_cost = |from: NodeId, to: NodeId| {
+            let mut probability = model.delivery_probability(stats.link(from, to));
+            if to != goal {
+                probability *= model.delivery_probability(stats.node(to));
+            }
+            -probability.ln() + model.hop_penalty
+        };
+
+        let mut costs = HashMap::from([(start, 0.0)]);
+        let mut parent = HashMap::new();
+        let mut queue = BinaryHeap::from([QueueEntry { cost: 0.0, node: start }]);
+
+        while let Some(QueueEntry { cost, node }) = queue.pop() {
+            if node == goal {
+                let mut path = vec![goal];
+                let mut current = goal;
+                while let Some(&p) = parent.get(&current) {
+                    path.push(p);
+                    current = p;
+                }
+                path.reverse();
+                return Some(SourceRoutingHeader {
+                    hop_index: 1,
+                    hops: path,
+                });
+            }
+            if cost > costs.get(&node).cloned().unwrap_or(f64::INFINITY) {
+                continue;   // an old entry, the node was already reached for less
+            }
+
+            for &neighbor in graph.get(&node).into_iter().flatten() {
+                let new_cost = cost + hop_cost(node, neighbor);
+                if new_cost < costs.get(&neighbor).cloned().unwrap_or(f64::INFINITY) {
+                    costs.insert(neighbor, new_cost);
+                    parent.insert(neighbor, node);
+                    queue.push(QueueEntry { cost: new_cost, node: neighbor });
+                }
+            }
+        }
+
+        None
+    }
+
+    /*
+    Up to k paths that share no node apart from the two ends: the most reliable one, then the most
+    reliable one without the nodes already used, and so on.
+     */
+    pub fn node_disjoint_paths(
+        graph: &HashMap<NodeId, Vec<NodeId>>,
+        node_types: &HashMap<NodeId, NodeType>,
+        stats: &ReliabilityStats,
+        model: &CostModel,
+        start: NodeId,
+        goal: NodeId,
+        k: usize,
+    ) -> Vec<SourceRoutingHeader> {
+        let mut graph = graph.clone();
+        let mut paths = Vec::new();
+        while paths.len() < k {
+            let Some(path) = most_reliable_path(&graph, node_types, stats, model, start, goal) else {
+                break;
+            };
+            let inner = &path.hops[1..path.hops.len() - 1];
+            if inner.is_empty() {
+                remove_link(&mut graph, start, goal);
+            }
+            for node in inner {
+                remove_neighbor(&mut graph, *node);
+            }
+            paths.push(path);
+        }
+        paths
+    }
+
+    /*
+    The sessions a Nack is about. The session id is chosen by the client, so two clients can use the same one:
+    the Nack came back along the route of the fragment (nack_hops, from the node that sent it to me) and only the
+    sessions whose fragment went out on that route are kept. Without a recorded route the session id is enough
+    only when a single client uses it.
+     */
+    pub fn nacked_sessions(
+        open_sessions: impl IntoIterator<Item = (NodeId, u64)>,
+        fragment_routes: &HashMap<(NodeId, u64, u64), Vec<NodeId>>,
+        nack_hops: &[NodeId],
+        session_id: u64,
+        fragment_index: u64,
+    ) -> Vec<(NodeId, u64)> {
+        let back: Vec<NodeId> = nack_hops.iter().rev().cloned().collect();
+        let same_id: Vec<(NodeId, u64)> = open_sessions.into_iter()
+            .filter(|(_, session)| *session == session_id)
+            .collect();
+        let mut on_route: Vec<(NodeId, u64)> = same_id.iter()
+            .filter(|(destination, session)| {
+                fragment_routes.get(&(*destination, *session, fragment_index))
+                    .map_or(false, |route| route.starts_with(&back))
+            })
+            .cloned()
+            .collect();
+        if on_route.is_empty() && same_id.len() == 1 {
+            return same_id;
+        }
+        on_route.sort();
+        on_route
+    }
+
+    // Paths a large transfer is spread on, each one gets fragments in proportion to its delivery probability
+    #[derive(Debug, Clone, Default)]
+    pub struct PathSet {
+        paths: Vec<Vec<NodeId>>,
+        sent: Vec<u32>,     // fragments sent on each path
+    }
+
+    impl PathSet {
+        pub fn new(paths: Vec<Vec<NodeId>>) -> Self {
+            PathSet {
+                sent: vec![0; paths.len()],
+                paths,
+            }
+        }
+
+        //The path that is most behind its share of the fragments
+        pub fn pick(&mut self, stats: &ReliabilityStats, model: &CostModel) -> Option<Vec<NodeId>> {
+            let chosen = (0..self.paths.len()).min_by(|a, b| {
+                let load = |i: usize| (self.sent[i] + 1) as f64 / stats.route_probability(&self.paths[i], model);
+                load(*a).total_cmp(&load(*b))
+            })?;
+            self.sent[chosen] += 1;
+            Some(self.paths[chosen].clone())
+        }
+
+        //Removes the paths that go through the failure, gives back how many were removed
+        pub fn remove_broken(&mut self, failure: &Failure) -> usize {
+            let before = self.paths.len();
+            let mut i = 0;
+            while i < self.paths.len() {
+                if failure.breaks(&self.paths[i]) {
+                    self.paths.remove(i);
+                    self.sent.remove(i);
+                } else {
+                    i += 1;
+                }
+            }
+            before - self.paths.len()
+        }
+
+        pub fn paths(&self) -> &[Vec<NodeId>] {
+            &self.paths
+        }
+
+        pub fn len(&self) -> usize {
+            self.paths.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.paths.is_empty()
+        }
+    }
+
     // Remove a neighbour from a node in case of a crash
     pub fn remove_neighbor(
         graph: &mut HashMap<NodeId, Vec<NodeId>>,
@@ -78,6 +435,46 @@ pub mod net_work {
         }
     }
 
+    // Remove only the link between two nodes, used when a drone says it can't reach a neighbour
+    pub fn remove_link(
+        graph: &mut HashMap<NodeId, Vec<NodeId>>,
+        node: NodeId,
+        neighbor: NodeId,
+    ) {
+        if let Some(neighbors) = graph.get_mut(&node) {
+            neighbors.retain(|&n| n != neighbor);
+        }
+        if let Some(neighbors) = graph.get_mut(&neighbor) {
+            neighbors.retain(|&n| n != node);
+        }
+    }
+
+    // Part of the network that stopped working, found through a Nack or a crash
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum Failure {
+        Node(NodeId),
+        Link(NodeId, NodeId),
+    }
+
+    impl Failure {
+        // Whether a path goes through the failed node or link (in any direction)
+        pub fn breaks(&self, path: &[NodeId]) -> bool {
+            match *self {
+                Failure::Node(node) => path.contains(&node),
+                Failure::Link(a, b) => path
+                    .windows(2)
+                    .any(|hop| (hop[0] == a && hop[1] == b) || (hop[0] == b && hop[1] == a)),
+            }
+        }
+
+        pub fn apply(&self, graph: &mut HashMap<NodeId, Vec<NodeId>>) {
+            match *self {
+                Failure::Node(node) => remove_neighbor(graph, node),
+                Failure::Link(a, b) => remove_link(graph, a, b),
+            }
+        }
+    }
+
     //this function start creating the graph with the flood response that it receive
     pub fn recive_flood_response(
         graph: &mut HashMap<NodeId, Vec<NodeId>>,
@@ -126,9 +523,13 @@ pub mod net_work {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::bfs_shortest_path;
+    use crate::NewWork::bfs_shortest_path;
     use crate::NewWork::recive_flood_response;
     use crate::NewWork::remove_neighbor;
+    use crate::NewWork::{remove_link, Failure};
+    use crate::NewWork::{most_reliable_path, CostModel, ReliabilityStats};
+    use crate::NewWork::{node_disjoint_paths, PathSet};
+    use crate::NewWork::nacked_sessions;
     use std::collections::HashMap;
     use wg_2024::network::{NodeId, SourceRoutingHeader};
     use wg_2024::packet::NodeType;
@@ -161,6 +562,176 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_routes_only_through_drones() {
+        // 1 - 2 - 7 - 3 - 5 is the shortest but 7 is a client, 1 - 2 - 4 - 6 - 3 - 5 has only drones
+        let mut graph: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        graph.insert(1, vec![2]);
+        graph.insert(2, vec![1, 7, 4]);
+        graph.insert(7, vec![2, 3]);
+        graph.insert(4, vec![2, 6]);
+        graph.insert(6, vec![4, 3]);
+        graph.insert(3, vec![7, 6, 5]);
+        graph.insert(5, vec![3]);
+        let mut types: HashMap<NodeId, NodeType> = [2, 3, 4, 6].into_iter().map(|id| (id, Drone)).collect();
+        types.insert(1, NodeType::Server);
+        types.insert(5, NodeType::Client);
+        types.insert(7, NodeType::Client);
+        let model = CostModel::default();
+
+        assert_eq!(bfs_shortest_path(&graph, 1, 5).unwrap().hops, vec![1, 2, 7, 3, 5]);
+        let mut stats = ReliabilityStats::default();
+        assert_eq!(most_reliable_path(&graph, &types, &stats, &model, 1, 5).unwrap().hops, vec![1, 2, 4, 6, 3, 5]);
+        stats.record_delivered(&[1, 2, 7, 3, 5]);
+        assert_eq!(most_reliable_path(&graph, &types, &stats, &model, 1, 5).unwrap().hops, vec![1, 2, 4, 6, 3, 5]);
+        // the client can still be the goal
+        assert_eq!(most_reliable_path(&graph, &types, &stats, &model, 1, 7).unwrap().hops, vec![1, 2, 7]);
+
+        let paths = node_disjoint_paths(&graph, &types, &stats, &model, 1, 5, 3);
+        assert_eq!(paths.len(), 1);
+        assert_eq!(paths[0].hops, vec![1, 2, 4, 6, 3, 5]);
+
+        // without the drones 4 and 6 there is no route at all
+        types.remove(&4);
+        assert!(most_reliable_path(&graph, &types, &stats, &model, 1, 5).is_none());
+    }
+
+    // 1 - 2 - 4 - 5 is the shortest, 1 - 3 - 6 - 4 - 5 is longer
+    fn graph_with_two_routes() -> HashMap<NodeId, Vec<NodeId>> {
+        let mut graph: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        graph.insert(1, vec![2, 3]);
+        graph.insert(2, vec![1, 4]);
+        graph.insert(3, vec![1, 6]);
+        graph.insert(6, vec![3, 4]);
+        graph.insert(4, vec![2, 6, 5]);
+        graph.insert(5, vec![4]);
+        graph
+    }
+
+    // 1 is the server, 5 the client, the others are drones
+    fn types_of_two_routes() -> HashMap<NodeId, NodeType> {
+        let mut types: HashMap<NodeId, NodeType> = [2, 3, 4, 6].into_iter().map(|id| (id, Drone)).collect();
+        types.insert(1, NodeType::Server);
+        types.insert(5, NodeType::Client);
+        types
+    }
+
+    #[test]
+    fn test_most_reliable_path_without_data_is_bfs() {
+        let graph = graph_with_two_routes();
+        let types = types_of_two_routes();
+        let stats = ReliabilityStats::default();
+        let path = most_reliable_path(&graph, &types, &stats, &CostModel::default(), 1, 5);
+        assert_eq!(path.unwrap().hops, bfs_shortest_path(&graph, 1, 5).unwrap().hops);
+    }
+
+    #[test]
+    fn test_most_reliable_path_avoids_lossy_drone() {
+        let graph = graph_with_two_routes();
+        let types = types_of_two_routes();
+        let mut stats = ReliabilityStats::default();
+        for _ in 0..5 {
+            stats.record_dropped(&[1, 2, 4, 5], 2);
+        }
+        stats.record_delivered(&[1, 2, 4, 5]);
+
+        let model = CostModel::default();
+        let path = most_reliable_path(&graph, &types, &stats, &model, 1, 5).unwrap().hops;
+        assert_eq!(path, vec![1, 3, 6, 4, 5]);
+        assert!(stats.route_probability(&path, &model) > stats.route_probability(&[1, 2, 4, 5], &model));
+
+        // with a big enough penalty for each hop the shorter route wins again
+        let model = CostModel { hop_penalty: 2.0, ..CostModel::default() };
+        assert_eq!(most_reliable_path(&graph, &types, &stats, &model, 1, 5).unwrap().hops, vec![1, 2, 4, 5]);
+    }
+
+    #[test]
+    fn test_delivery_statistics() {
+        let mut stats = ReliabilityStats::default();
+        stats.record_dropped(&[1, 2, 4, 5], 4);
+        assert_eq!(stats.node(2).unwrap().delivered, 1.0);
+        assert_eq!(stats.node(4).unwrap().dropped, 1.0);
+        assert_eq!(stats.node(4).unwrap().delivered, 0.0);
+        assert!(stats.link(4, 5).is_none());
+
+        let model = CostModel { prior_delivered: 1.0, prior_dropped: 1.0, ..CostModel::default() };
+        assert_eq!(model.delivery_probability(None), 0.5);
+        assert_eq!(model.delivery_probability(stats.node(4)), 1.0 / 3.0);
+        assert_eq!(model.delivery_probability(stats.node(2)), 2.0 / 3.0);
+
+        stats.record_timeout(&[1, 2, 4, 5]);
+        assert_eq!(stats.node(2).unwrap().dropped, 0.5);
+        assert_eq!(stats.node(4).unwrap().dropped, 1.5);
+    }
+
+    #[test]
+    fn test_node_disjoint_paths() {
+        let graph = graph_with_two_routes();
+        let types = types_of_two_routes();
+        let stats = ReliabilityStats::default();
+        let model = CostModel::default();
+
+        // both routes go through 4, so only one path is disjoint
+        let paths = node_disjoint_paths(&graph, &types, &stats, &model, 1, 5, 3);
+        assert_eq!(paths.len(), 1);
+
+        let paths: Vec<Vec<NodeId>> = node_disjoint_paths(&graph, &types, &stats, &model, 1, 4, 3)
+            .into_iter()
+            .map(|path| path.hops)
+            .collect();
+        assert_eq!(paths, vec![vec![1, 2, 4], vec![1, 3, 6, 4]]);
+    }
+
+    #[test]
+    fn test_nacked_sessions_with_the_same_id() {
+        // clients 5 and 7 both chose session 3, their fragments went out on different routes
+        let mut routes = HashMap::new();
+        routes.insert((5, 3, 0), vec![1, 2, 4, 5]);
+        routes.insert((7, 3, 0), vec![1, 3, 6, 7]);
+        let open = [(5, 3), (7, 3), (5, 8)];
+
+        // drone 6 dropped the fragment: the Nack came back 6 -> 3 -> 1
+        let sessions = nacked_sessions(open, &routes, &[6, 3, 1], 3, 0);
+        assert_eq!(sessions, vec![(7, 3)]);
+        let route = &routes[&(7, 3, 0)];
+        let mut stats = ReliabilityStats::default();
+        stats.record_dropped(route, 6);
+        assert_eq!(stats.node(6).unwrap().dropped, 1.0);
+        assert!(stats.node(2).is_none() && stats.node(4).is_none());
+
+        assert_eq!(nacked_sessions(open, &routes, &[2, 1], 3, 0), vec![(5, 3)]);
+        // a route nobody used
+        assert!(nacked_sessions(open, &routes, &[9, 1], 3, 0).is_empty());
+        // without a recorded route only a session id used by one client is enough
+        assert_eq!(nacked_sessions(open, &routes, &[9, 1], 8, 0), vec![(5, 8)]);
+    }
+
+    #[test]
+    fn test_path_set_spreads_by_reliability() {
+        let mut stats = ReliabilityStats::default();
+        stats.record_dropped(&[1, 3, 6, 4], 3);
+        stats.record_dropped(&[1, 3, 6, 4], 3);
+        stats.record_delivered(&[1, 2, 4]);
+        let model = CostModel::default();
+
+        let mut paths = PathSet::new(vec![vec![1, 2, 4], vec![1, 3, 6, 4]]);
+        let mut on_first = 0;
+        for _ in 0..10 {
+            if paths.pick(&stats, &model).unwrap() == vec![1, 2, 4] {
+                on_first += 1;
+            }
+        }
+        // drone 3 delivers half of the fragments, so its path gets about a third of them
+        assert_eq!(on_first, 7);
+
+        assert_eq!(paths.remove_broken(&Failure::Node(6)), 1);
+        assert_eq!(paths.paths(), &[vec![1, 2, 4]]);
+        assert_eq!(paths.pick(&stats, &model), Some(vec![1, 2, 4]));
+        assert_eq!(paths.remove_broken(&Failure::Link(4, 2)), 1);
+        assert!(paths.is_empty());
+        assert_eq!(paths.pick(&stats, &model), None);
+    }
+
     #[test]
     fn test_remove_neighbor() {
         let mut graph: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
@@ -173,6 +744,40 @@ mod tests {
         assert!(!graph.get(&1).unwrap().contains(&2));
     }
 
+    #[test]
+    fn test_remove_link() {
+        let mut graph: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        graph.insert(1, vec![2, 3]);
+        graph.insert(2, vec![1, 3]);
+        graph.insert(3, vec![1, 2]);
+
+        remove_link(&mut graph, 2, 1);
+
+        assert_eq!(graph.get(&1).unwrap(), &vec![3]);
+        assert_eq!(graph.get(&2).unwrap(), &vec![3]);
+        assert_eq!(graph.get(&3).unwrap(), &vec![1, 2]);
+    }
+
+    #[test]
+    fn test_failure_breaks_path() {
+        let mut graph: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
+        graph.insert(1, vec![2, 3]);
+        graph.insert(2, vec![1, 4]);
+        graph.insert(3, vec![1, 4]);
+        graph.insert(4, vec![2, 3]);
+
+        let path = bfs_shortest_path(&graph, 1, 4).unwrap().hops;
+        assert_eq!(path, vec![1, 2, 4]);
+        assert!(Failure::Link(4, 2).breaks(&path));
+        assert!(Failure::Node(2).breaks(&path));
+        assert!(!Failure::Link(1, 3).breaks(&path));
+
+        Failure::Link(2, 4).apply(&mut graph);
+        assert_eq!(bfs_shortest_path(&graph, 1, 4).unwrap().hops, vec![1, 3, 4]);
+        Failure::Node(3).apply(&mut graph);
+        assert!(bfs_shortest_path(&graph, 1, 4).is_none());
+    }
+
     #[test]
     fn test_recive_flood_response() {
         let mut graph: HashMap<NodeId, Vec<NodeId>> = HashMap::new();
@@ -448,13 +1053,486 @@ mod test_packaging {
     }*/
 }
 
+pub mod retransmission {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+    use wg_2024::network::NodeId;
+
+    pub const RETRANSMISSION_TICK: Duration = Duration::from_millis(100);   //how often the server checks the timers
+    pub const BASE_TIMEOUT: Duration = Duration::from_millis(250);          //first timeout, it doubles at every retry
+    pub const MAX_RETRIES: u32 = 5;
+    pub const SESSION_EXPIRY: Duration = Duration::from_secs(30);           //a session with no ack for this long is dropped
+
+    pub type SessionKey = (NodeId, u64);    // (destination id, session id) like in the paket_ack_manger
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum TimeoutEvent {
+        Retransmit { session: SessionKey, fragment_index: u64, attempt: u32 },  //the fragment must be sent again
+        Abandoned { session: SessionKey, fragment_index: u64 },                 //the fragment used all the retries
+        Expired { session: SessionKey },                                        //no ack for too long
+    }
+
+    struct Timer {
+        deadline: Instant,
+        retries: u32,
+    }
+
+    struct SessionTimers {
+        fragments: HashMap<u64, Timer>,     //fragment index -> timer
+        last_activity: Instant,             //last time a fragment of the session was acked or started
+    }
+
+    /*
+    Keeps one timer for every fragment sent and not acked yet.
+    When a timer fires the fragment is sent again and the timeout doubles (exponential backoff),
+    after max_retries the whole session is abandoned.
+     */
+    pub struct RetransmissionTimers {
+        base_timeout: Duration,
+        max_retries: u32,
+        session_expiry: Duration,
+        sessions: HashMap<SessionKey, SessionTimers>,
+    }
+
+    impl RetransmissionTimers {
+        pub fn new(base_timeout: Duration, max_retries: u32, session_expiry: Duration) -> Self {
+            RetransmissionTimers {
+                base_timeout,
+                max_retries,
+                session_expiry,
+                sessions: HashMap::new(),
+            }
+        }
+
+        //Starts (or restarts) the timer of a fragment that has just been sent. The retries already done are kept
+        pub fn sent(&mut self, session: SessionKey, fragment_index: u64, now: Instant) {
+            let base_timeout = self.base_timeout;
+            let timers = self.sessions.entry(session).or_insert_with(|| SessionTimers {
+                fragments: HashMap::new(),
+                last_activity: now,
+            });
+            let timer = timers.fragments.entry(fragment_index).or_insert(Timer { deadline: now, retries: 0 });
+            timer.deadline = now + Self::backoff(base_timeout, timer.retries);
+        }
+
+        pub fn acked(&mut self, session: SessionKey, fragment_index: u64, now: Instant) {
+            if let Some(timers) = self.sessions.get_mut(&session) {
+                timers.fragments.remove(&fragment_index);
+                timers.last_activity = now;
+            }
+        }
+
+        pub fn remove_session(&mut self, session: SessionKey) {
+            self.sessions.remove(&session);
+        }
+
+        //Fragments still waiting for an ack
+        pub fn pending(&self, session: SessionKey) -> usize {
+            self.sessions.get(&session).map_or(0, |timers| timers.fragments.len())
+        }
+
+        //Checks every timer, the sessions abandoned or expired are removed
+        pub fn poll(&mut self, now: Instant) -> Vec<TimeoutEvent> {
+            let mut events = Vec::new();
+            let mut dead_sessions = Vec::new();
+
+            for (session, timers) in self.sessions.iter_mut() {
+                if now.duration_since(timers.last_activity) >= self.session_expiry {
+                    events.push(TimeoutEvent::Expired { session: *session });
+                    dead_sessions.push(*session);
+                    continue;
+                }
+
+                let mut indexes: Vec<u64> = timers.fragments.keys().cloned().collect();
+                indexes.sort();
+                for fragment_index in indexes {
+                    let timer = timers.fragments.get_mut(&fragment_index).expect("index taken from the map");
+                    if timer.deadline > now {
+                        continue;
+                    }
+                    if timer.retries >= self.max_retries {
+                        events.push(TimeoutEvent::Abandoned { session: *session, fragment_index });
+                        dead_sessions.push(*session);
+                        break;
+                    }
+                    timer.retries += 1;
+                    timer.deadline = now + Self::backoff(self.base_timeout, timer.retries);
+                    events.push(TimeoutEvent::Retransmit { session: *session, fragment_index, attempt: timer.retries });
+                }
+            }
+
+            for session in dead_sessions {
+                self.sessions.remove(&session);
+            }
+            events
+        }
+
+        fn backoff(base_timeout: Duration, retries: u32) -> Duration {
+            base_timeout * 2u32.saturating_pow(retries)
+        }
+    }
+
+    impl Default for RetransmissionTimers {
+        fn default() -> Self {
+            Self::new(BASE_TIMEOUT, MAX_RETRIES, SESSION_EXPIRY)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_retransmission {
+    use crate::message::retransmission::{RetransmissionTimers, TimeoutEvent};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_backoff_and_abandon() {
+        let mut timers = RetransmissionTimers::new(Duration::from_millis(100), 2, Duration::from_secs(60));
+        let start = Instant::now();
+        timers.sent((5, 1), 0, start);
+
+        assert!(timers.poll(start + Duration::from_millis(50)).is_empty());
+        assert_eq!(
+            timers.poll(start + Duration::from_millis(100)),
+            vec![TimeoutEvent::Retransmit { session: (5, 1), fragment_index: 0, attempt: 1 }]
+        );
+        // the second timeout is twice as long
+        assert!(timers.poll(start + Duration::from_millis(250)).is_empty());
+        assert_eq!(
+            timers.poll(start + Duration::from_millis(300)),
+            vec![TimeoutEvent::Retransmit { session: (5, 1), fragment_index: 0, attempt: 2 }]
+        );
+        assert_eq!(
+            timers.poll(start + Duration::from_millis(700)),
+            vec![TimeoutEvent::Abandoned { session: (5, 1), fragment_index: 0 }]
+        );
+        assert_eq!(timers.pending((5, 1)), 0);
+        assert!(timers.poll(start + Duration::from_secs(10)).is_empty());
+    }
+
+    #[test]
+    fn test_ack_stops_timer() {
+        let mut timers = RetransmissionTimers::new(Duration::from_millis(100), 3, Duration::from_secs(1));
+        let start = Instant::now();
+        timers.sent((5, 1), 0, start);
+        timers.acked((5, 1), 0, start + Duration::from_millis(10));
+        assert!(timers.poll(start + Duration::from_millis(500)).is_empty());
+
+        // nothing acked for a whole second, the session expires
+        assert_eq!(
+            timers.poll(start + Duration::from_millis(1010)),
+            vec![TimeoutEvent::Expired { session: (5, 1) }]
+        );
+    }
+}
+
+pub mod sliding_window {
+    use wg_2024::packet::Fragment;
+
+    pub const DEFAULT_WINDOW_SIZE: usize = 8;      //fragments that can wait for an ack at the same time, 1 is stop-and-wait
+
+    /*
+    Selective repeat over the fragments of one response.
+    Up to `size` fragments are in flight, every fragment is acked on its own
+    and the window slides as soon as the first fragment not acked yet is acked.
+     */
+    #[derive(Debug, Clone)]
+    pub struct SendWindow {
+        fragments: Vec<Fragment>,
+        acked: Vec<bool>,
+        base: usize,        //first fragment not acked yet
+        next: usize,        //first fragment never sent
+        size: usize,
+    }
+
+    impl SendWindow {
+        pub fn new(fragments: Vec<Fragment>, size: usize) -> Self {
+            SendWindow {
+                acked: vec![false; fragments.len()],
+                fragments,
+                base: 0,
+                next: 0,
+                size: size.max(1),
+            }
+        }
+
+        //Fragments that entered the window since the last call, they have to be sent now
+        pub fn next_to_send(&mut self) -> Vec<Fragment> {
+            let end = (self.base + self.size).min(self.fragments.len());
+            if self.next >= end {
+                return Vec::new();
+            }
+            let ready = self.fragments[self.next..end].to_vec();
+            self.next = end;
+            ready
+        }
+
+        //Marks the fragment as acked, returns false if it was unknown, never sent or already acked
+        pub fn ack(&mut self, fragment_index: u64) -> bool {
+            let Some(position) = self.position(fragment_index) else {
+                return false;
+            };
+            if position >= self.next || self.acked[position] {
+                return false;
+            }
+            self.acked[position] = true;
+            while self.base < self.acked.len() && self.acked[self.base] {
+                self.base += 1;
+            }
+            true
+        }
+
+        pub fn fragment(&self, fragment_index: u64) -> Option<&Fragment> {
+            self.position(fragment_index).map(|position| &self.fragments[position])
+        }
+
+        pub fn len(&self) -> usize {
+            self.fragments.len()
+        }
+
+        pub fn acked_count(&self) -> usize {
+            self.acked.iter().filter(|acked| **acked).count()
+        }
+
+        //Fragments sent and still waiting for their ack
+        pub fn in_flight(&self) -> usize {
+            (self.base..self.next).filter(|position| !self.acked[*position]).count()
+        }
+
+        pub fn in_flight_fragments(&self) -> Vec<Fragment> {
+            (self.base..self.next)
+                .filter(|position| !self.acked[*position])
+                .map(|position| self.fragments[position].clone())
+                .collect()
+        }
+
+        pub fn is_complete(&self) -> bool {
+            self.base == self.fragments.len()
+        }
+
+        fn position(&self, fragment_index: u64) -> Option<usize> {
+            //the fragments are created in order, so the index is usually the position
+            match self.fragments.get(fragment_index as usize) {
+                Some(fragment) if fragment.fragment_index == fragment_index => Some(fragment_index as usize),
+                _ => self.fragments.iter().position(|f| f.fragment_index == fragment_index),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_sliding_window {
+    use crate::message::sliding_window::SendWindow;
+    use wg_2024::packet::{Fragment, FRAGMENT_DSIZE};
+
+    fn fragments(n: u64) -> Vec<Fragment> {
+        (0..n).map(|i| Fragment::new(i, n, [0; FRAGMENT_DSIZE])).collect()
+    }
+
+    fn indexes(fragments: Vec<Fragment>) -> Vec<u64> {
+        fragments.iter().map(|f| f.fragment_index).collect()
+    }
+
+    #[test]
+    fn test_window_slides_on_first_ack() {
+        let mut window = SendWindow::new(fragments(5), 2);
+        assert_eq!(indexes(window.next_to_send()), vec![0, 1]);
+        assert!(window.next_to_send().is_empty());
+
+        // fragment 1 is acked before 0, the window can't move yet
+        assert!(window.ack(1));
+        assert!(window.next_to_send().is_empty());
+        assert!(window.ack(0));
+        assert_eq!(indexes(window.next_to_send()), vec![2, 3]);
+        assert_eq!(window.in_flight(), 2);
+        assert_eq!(indexes(window.in_flight_fragments()), vec![2, 3]);
+
+        assert!(!window.ack(0));    // duplicate
+        assert!(!window.ack(4));    // never sent
+        assert!(window.ack(2));
+        assert!(window.ack(3));
+        assert_eq!(indexes(window.next_to_send()), vec![4]);
+        assert!(!window.is_complete());
+        assert!(window.ack(4));
+        assert!(window.is_complete());
+        assert_eq!(window.acked_count(), 5);
+    }
+
+    #[test]
+    fn test_acks_of_two_paths_out_of_order() {
+        // the even fragments go on a fast path, the odd ones on a slow path that delivers them reversed
+        let mut window = SendWindow::new(fragments(20), 20);
+        assert_eq!(indexes(window.next_to_send()).len(), 20);
+        let fast: Vec<u64> = (0..20).filter(|i| i % 2 == 0).collect();
+        let slow: Vec<u64> = (0..20).filter(|i| i % 2 == 1).rev().collect();
+        for i in fast {
+            assert!(window.ack(i));
+        }
+        assert!(!window.is_complete());
+        assert_eq!(window.in_flight(), 10);
+        for i in slow {
+            assert!(window.ack(i));
+            assert!(!window.ack(i));    // the same ack from the other path
+        }
+        assert!(window.is_complete());
+        assert!(window.next_to_send().is_empty());
+    }
+
+    #[test]
+    fn test_window_of_one_is_stop_and_wait() {
+        let mut window = SendWindow::new(fragments(3), 0);
+        for i in 0..3 {
+            assert_eq!(indexes(window.next_to_send()), vec![i]);
+            assert!(window.ack(i));
+        }
+        assert!(window.is_complete());
+    }
+}
+
+pub mod discovery {
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+    use wg_2024::network::NodeId;
+
+    pub const DISCOVERY_TICK: Duration = Duration::from_secs(1);        //how often the server checks if it has to flood or age the graph
+    pub const REFRESH_INTERVAL: Duration = Duration::from_secs(15);     //time between two periodic floods
+    pub const EDGE_MAX_AGE: Duration = Duration::from_secs(45);         //an edge not seen in any path trace for this long is removed
+    pub const MIN_FLOOD_GAP: Duration = Duration::from_secs(2);         //many failures together cause only one flood
+
+    pub type Edge = (NodeId, NodeId);   //the lower id first
+
+    pub fn edge(a: NodeId, b: NodeId) -> Edge {
+        (a.min(b), a.max(b))
+    }
+
+    /*
+    Decides when the server floods the network and remembers when every edge was last seen in a path trace.
+    Every round of discovery uses one flood_id for all the neighbours, so the drones flood it only once.
+     */
+    pub struct DiscoveryManager {
+        refresh_interval: Duration,
+        edge_max_age: Duration,
+        min_flood_gap: Duration,
+        flood_id: u64,
+        last_flood: Option<Instant>,
+        last_seen: HashMap<Edge, Instant>,
+    }
+
+    impl DiscoveryManager {
+        pub fn new(refresh_interval: Duration, edge_max_age: Duration, min_flood_gap: Duration) -> Self {
+            DiscoveryManager {
+                refresh_interval,
+                edge_max_age,
+                min_flood_gap,
+                flood_id: rand::random::<u32>() as u64,     //a restarted server doesn't reuse the old ids
+                last_flood: None,
+                last_seen: HashMap::new(),
+            }
+        }
+
+        //Starts a new round and gives back its flood_id
+        pub fn start_round(&mut self, now: Instant) -> u64 {
+            self.flood_id += 1;
+            self.last_flood = Some(now);
+            self.flood_id
+        }
+
+        //The periodic refresh is due
+        pub fn should_refresh(&self, now: Instant) -> bool {
+            self.last_flood.map_or(true, |last| now.duration_since(last) >= self.refresh_interval)
+        }
+
+        //A failure can start a new round only if the last one is not too recent
+        pub fn can_flood(&self, now: Instant) -> bool {
+            self.last_flood.map_or(true, |last| now.duration_since(last) >= self.min_flood_gap)
+        }
+
+        //Every edge of the path trace exists right now
+        pub fn confirm_path(&mut self, path: &[NodeId], now: Instant) {
+            for hop in path.windows(2) {
+                self.last_seen.insert(edge(hop[0], hop[1]), now);
+            }
+        }
+
+        pub fn forget_link(&mut self, a: NodeId, b: NodeId) {
+            self.last_seen.remove(&edge(a, b));
+        }
+
+        pub fn forget_node(&mut self, node: NodeId) {
+            self.last_seen.retain(|(a, b), _| *a != node && *b != node);
+        }
+
+        //Removes and gives back the edges not seen for too long
+        pub fn expired_edges(&mut self, now: Instant) -> Vec<Edge> {
+            let mut expired: Vec<Edge> = self.last_seen.iter()
+                .filter(|(_, seen)| now.duration_since(**seen) >= self.edge_max_age)
+                .map(|(edge, _)| *edge)
+                .collect();
+            expired.sort();
+            for edge in &expired {
+                self.last_seen.remove(edge);
+            }
+            expired
+        }
+
+        pub fn last_seen(&self) -> &HashMap<Edge, Instant> {
+            &self.last_seen
+        }
+    }
+
+    impl Default for DiscoveryManager {
+        fn default() -> Self {
+            Self::new(REFRESH_INTERVAL, EDGE_MAX_AGE, MIN_FLOOD_GAP)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_discovery {
+    use crate::message::discovery::{edge, DiscoveryManager};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_rounds() {
+        let mut discovery = DiscoveryManager::new(Duration::from_secs(10), Duration::from_secs(30), Duration::from_secs(2));
+        let start = Instant::now();
+        assert!(discovery.should_refresh(start));
+
+        let first = discovery.start_round(start);
+        assert!(!discovery.should_refresh(start + Duration::from_secs(5)));
+        assert!(!discovery.can_flood(start + Duration::from_secs(1)));
+        assert!(discovery.can_flood(start + Duration::from_secs(2)));
+        assert!(discovery.should_refresh(start + Duration::from_secs(10)));
+
+        let second = discovery.start_round(start + Duration::from_secs(10));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_edges_age_out() {
+        let mut discovery = DiscoveryManager::new(Duration::from_secs(10), Duration::from_secs(30), Duration::from_secs(2));
+        let start = Instant::now();
+        discovery.confirm_path(&[7, 1, 2, 5], start);
+        discovery.confirm_path(&[7, 1, 3], start + Duration::from_secs(20));
+        discovery.forget_node(5);
+
+        assert!(discovery.expired_edges(start + Duration::from_secs(29)).is_empty());
+        assert_eq!(discovery.expired_edges(start + Duration::from_secs(30)), vec![edge(1, 2)]);
+        // 1 - 7 was seen again in the second path
+        assert_eq!(discovery.last_seen().len(), 2);
+        assert!(discovery.last_seen().contains_key(&(1, 7)));
+    }
+}
+
 pub mod file_system {
     use crate::message::packaging::Repackager;
     use std::io::Read;
     use std::path::Path;
     use std::{fmt, fs};
+    use std::time::{Duration, Instant};
     use wg_2024::packet::{Fragment, Packet};
 
+    pub use common::presence::CLIENT_TIMEOUT;   //a client not heard for this long is offline, the clients' keepalive is based on it
+
     /*
     The module file system contains all the server implementation.
     I defined the ServerTrait to be able to initialize the server with only one function new the
@@ -475,6 +1553,33 @@ pub mod file_system {
             flag: &mut i32,
         ) -> Result<Vec<Fragment>, String>;
         fn kind (&self) ->  ServerType;
+
+        //Messages the server has to send on its own, not as the response of a request
+        fn take_outgoing(&mut self) -> Vec<Outgoing> {
+            Vec::new()
+        }
+
+        //If the last response carries something the server wants to know the fate of, the id of that delivery
+        fn take_delivery_id(&mut self) -> Option<u64> {
+            None
+        }
+
+        //A delivery was acked by the client (true) or given up (false)
+        fn delivery_report(&mut self, delivery_id: u64, delivered: bool) {}
+
+        //Something arrived from the client (a request or an ack)
+        fn heard_from(&mut self, client: u32, now: Instant) {}
+
+        //Called periodically, to notice the clients that went silent
+        fn check_presence(&mut self, now: Instant) {}
+    }
+
+    //A message pushed to a client without a request
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Outgoing {
+        pub destination: u32,
+        pub message: String,
+        pub delivery_id: Option<u64>,   //Some if the server has to report if it arrived
     }
 
     impl ServerTrait for ContentServer {
@@ -587,20 +1692,48 @@ pub mod file_system {
         ) -> Result<Vec<Fragment>, String> {
             //println!("{command}");
             // Repackager::create_fragments(&*"error_unsupported_request!".to_string(), None)
+            self.heard_from(source_id, Instant::now());
+            if !command.starts_with("messages?") && !command.starts_with("unregister?") {
+                //the client is back, the messages it missed are sent to it
+                self.deliver_mailbox(source_id);
+            }
             match command {
                 cmd if cmd.starts_with("server_type?") => {
                     //Ask for server type
                     let serv_type = self.serv.to_string();
                     //println!("{}",format!("server_type!({})",self.serv.to_string()));
                     //println!("{:?}",Repackager::create_fragments(&format!("server_type!({})",self.serv.to_string()), None).unwrap());
-                    self.add_client(source_id);
                     Repackager::create_fragments(
                         &format!("server_type!({})", self.serv.to_string()),
                         None,
                     )
                 }
                 cmd if cmd.starts_with("client_list?") => {
-                    Repackager::create_fragments(&*self.get_client_ids(source_id), None)
+                    Repackager::create_fragments(&*self.get_client_ids(), None)
+                }
+
+                cmd if cmd.starts_with("register?(") && cmd.ends_with(")") => {
+                    let name = &cmd["register?(".len()..cmd.len() - 1];
+                    if name.is_empty() {
+                        return Repackager::create_fragments("error_unsupported_request!(The name of the client can't be empty)", None);
+                    }
+                    self.register_client(source_id, Some(name.to_string()));
+                    Repackager::create_fragments(&format!("registered!({})", source_id), None)
+                }
+
+                cmd if cmd.starts_with("unregister?") => {
+                    if self.remove_client(source_id) {
+                        Repackager::create_fragments(&format!("unregistered!({})", source_id), None)
+                    } else {
+                        Repackager::create_fragments("error_wrong_client_id!", None)
+                    }
+                }
+
+                cmd if cmd.starts_with("messages?") => {
+                    //how many messages were waiting, each one follows as a message_from!
+                    let pending = self.pending_messages(source_id).len();
+                    self.deliver_mailbox(source_id);
+                    Repackager::create_fragments(&format!("messages!({})", pending), None)
                 }
 
                 cmd if cmd.starts_with("message_for?") => {
@@ -616,10 +1749,18 @@ pub mod file_system {
 
 
                             if let Ok(client_id) = id.parse::<u32>() {
-                                *flag = 1;
-                                if (self.list_of_client.contains(&client_id)) {
+                                if (self.clients.contains_key(&client_id)) && !self.is_online(client_id) {
+                                    //nobody would ack it: it waits in the mailbox and the sender is told so
+                                    self.mailboxes.entry(client_id).or_default().push((source_id, message.to_string()));
+                                    let response = format!("message_queued!({},{})", client_id, message);
+                                    return Repackager::create_fragments(&response, None);
+                                } else if (self.clients.contains_key(&client_id)) {
+                                    //the response goes to the recipient, not to the sender
+                                    *flag = 1;
                                     let response =
                                         format!("message_from!({},{})", source_id, message);
+                                    //if it doesn't arrive it goes in the mailbox of the client
+                                    self.last_delivery = Some(self.track_delivery(client_id, vec![(source_id, message.to_string())]));
                                     return Repackager::create_fragments(
                                         &*response.to_string(),
                                         None,
@@ -668,6 +1809,63 @@ pub mod file_system {
                 message::file_system::ServerType::CommunicationServer => {ServerType::CommunicationServer},
             }
         }
+
+        fn take_outgoing(&mut self) -> Vec<Outgoing> {
+            std::mem::take(&mut self.outgoing)
+        }
+
+        fn take_delivery_id(&mut self) -> Option<u64> {
+            self.last_delivery.take()
+        }
+
+        fn delivery_report(&mut self, delivery_id: u64, delivered: bool) {
+            let Some((recipient, messages)) = self.in_delivery.remove(&delivery_id) else {
+                return;
+            };
+            if delivered {
+                //receipt for the sender of every message
+                for (sender, message) in messages {
+                    self.outgoing.push(Outgoing {
+                        destination: sender,
+                        message: format!("message_delivered!({},{})", recipient, message),
+                        delivery_id: None,
+                    });
+                }
+            } else if self.clients.contains_key(&recipient) {
+                //kept until the client contacts the server again
+                self.mailboxes.entry(recipient).or_default().extend(messages);
+            } else {
+                //the client unregistered in the meantime, nobody will read them
+                self.report_undelivered(recipient, messages);
+            }
+        }
+
+        fn heard_from(&mut self, client: u32, now: Instant) {
+            let came_back = match self.clients.get_mut(&client) {
+                Some(presence) => {
+                    presence.last_seen = now;
+                    !std::mem::replace(&mut presence.online, true)
+                }
+                None => false,
+            };
+            if came_back {
+                self.announce_joined(client);
+            }
+        }
+
+        fn check_presence(&mut self, now: Instant) {
+            let mut silent: Vec<u32> = self.clients.iter()
+                .filter(|(_, presence)| presence.online && now.duration_since(presence.last_seen) >= CLIENT_TIMEOUT)
+                .map(|(id, _)| *id)
+                .collect();
+            silent.sort();
+            for client in silent {
+                if let Some(presence) = self.clients.get_mut(&client) {
+                    presence.online = false;
+                }
+                self.announce(client, format!("client_left!({})", client));
+            }
+        }
     }
 
     pub enum ServerType {
@@ -763,31 +1961,137 @@ pub mod file_system {
     use crate::file_system::ServerType::TextServer;
     //use crate::message::packaging::Repackager;
 
+    //What the chat server knows about a registered client
+    struct Presence {
+        name: Option<String>,   //None if it was added with add_client
+        last_seen: Instant,
+        online: bool,
+    }
+
     pub struct ChatServer {
         //chats: HashMap<(u32, u32), Vec<String>>,        //CURRENTLY NOT IN USE  -I had a problem reading the documentation now. I thought that the server kept the message inside hime and the client connected to him to retrive the information
-        list_of_client: Vec<u32>,
+        clients: HashMap<u32, Presence>,                        // registered clients
         serv: ServerType,
+        mailboxes: HashMap<u32, Vec<(u32, String)>>,            // recipient -> (sender, message) not delivered yet
+        in_delivery: HashMap<u64, (u32, Vec<(u32, String)>)>,   // delivery id -> recipient and the messages on their way
+        outgoing: Vec<Outgoing>,
+        next_delivery: u64,
+        last_delivery: Option<u64>,                             // delivery of the last response, taken by the server
     }
 
     impl ChatServer {
         pub fn new() -> Self {
             ChatServer {
                 //chats: HashMap::new(),
-                list_of_client: Vec::new(),
+                clients: HashMap::new(),
                 serv: ServerType::CommunicationServer, //it's useless but if in the future I add another type of chat server....
+                mailboxes: HashMap::new(),
+                in_delivery: HashMap::new(),
+                outgoing: Vec::new(),
+                next_delivery: 0,
+                last_delivery: None,
+            }
+        }
+
+        //Messages waiting in the mailbox of a client
+        pub fn pending_messages(&self, client: u32) -> &[(u32, String)] {
+            self.mailboxes.get(&client).map_or(&[], |messages| messages.as_slice())
+        }
+
+        fn track_delivery(&mut self, recipient: u32, messages: Vec<(u32, String)>) -> u64 {
+            self.next_delivery += 1;
+            self.in_delivery.insert(self.next_delivery, (recipient, messages));
+            self.next_delivery
+        }
+
+        //Pushes every message of the mailbox to the client, each one tracked on its own
+        fn deliver_mailbox(&mut self, client: u32) {
+            for (sender, message) in self.mailboxes.remove(&client).unwrap_or_default() {
+                let text = format!("message_from!({},{})", sender, message);
+                let delivery_id = self.track_delivery(client, vec![(sender, message)]);
+                self.outgoing.push(Outgoing {
+                    destination: client,
+                    message: text,
+                    delivery_id: Some(delivery_id),
+                });
             }
         }
 
         pub fn add_client(&mut self, client: u32) {
-            if !self.list_of_client.contains(&client) {
-                self.list_of_client.push(client);
+            self.register_client(client, None);
+        }
+
+        //A new client is announced to the others, one already registered only changes name
+        fn register_client(&mut self, client: u32, name: Option<String>) {
+            match self.clients.get_mut(&client) {
+                Some(presence) => {
+                    if name.is_some() {
+                        presence.name = name;
+                    }
+                }
+                None => {
+                    self.clients.insert(client, Presence { name, last_seen: Instant::now(), online: true });
+                    self.announce_joined(client);
+                }
             }
         }
 
-        // Function to return the list of all unique client IDs
-        pub fn get_client_ids(&mut self, source_id: u32) -> String {
-            self.add_client(source_id);
-            let mut ids = self.list_of_client.clone();
+        //Gives back false if the client was not registered
+        pub fn remove_client(&mut self, client: u32) -> bool {
+            match self.clients.remove(&client) {
+                Some(presence) => {
+                    let waiting = self.mailboxes.remove(&client).unwrap_or_default();
+                    self.report_undelivered(client, waiting);
+                    if presence.online {
+                        self.announce(client, format!("client_left!({})", client));
+                    }
+                    true
+                }
+                None => false,
+            }
+        }
+
+        //Failure receipt for the sender of every message that will never reach the recipient
+        fn report_undelivered(&mut self, recipient: u32, messages: Vec<(u32, String)>) {
+            for (sender, message) in messages {
+                self.outgoing.push(Outgoing {
+                    destination: sender,
+                    message: format!("message_undelivered!({},{})", recipient, message),
+                    delivery_id: None,
+                });
+            }
+        }
+
+        pub fn is_online(&self, client: u32) -> bool {
+            self.clients.get(&client).map_or(false, |presence| presence.online)
+        }
+
+        fn announce_joined(&mut self, client: u32) {
+            let message = match self.clients.get(&client).and_then(|presence| presence.name.as_ref()) {
+                Some(name) => format!("client_joined!({},{})", client, name),
+                None => format!("client_joined!({})", client),
+            };
+            self.announce(client, message);
+        }
+
+        //Pushes a notification about a client to all the other online clients
+        fn announce(&mut self, about: u32, message: String) {
+            let mut online: Vec<u32> = self.clients.iter()
+                .filter(|(id, presence)| **id != about && presence.online)
+                .map(|(id, _)| *id)
+                .collect();
+            online.sort();
+            for destination in online {
+                self.outgoing.push(Outgoing { destination, message: message.clone(), delivery_id: None });
+            }
+        }
+
+        // Function to return the list of the online client IDs, asking for it doesn't register the caller (only register? does)
+        pub fn get_client_ids(&self) -> String {
+            let mut ids: Vec<u32> = self.clients.iter()
+                .filter(|(_, presence)| presence.online)
+                .map(|(id, _)| *id)
+                .collect();
             ids.sort(); // it's easier when we debug
             //println!("Processed req");
             format!("client_list!({:?})", ids)
@@ -821,7 +2125,8 @@ pub mod file_system {
 #[cfg(test)]
 mod tests_chat_server {
     use super::*;
-    use crate::message::file_system::ServerTrait;
+    use crate::message::file_system::{ServerTrait, CLIENT_TIMEOUT};
+    use std::time::Instant;
     use crate::message::packaging::Repackager;
     use wg_2024::packet::Fragment;
 
@@ -852,9 +2157,9 @@ mod tests_chat_server {
         let mut server = ChatServer::new();
         server.add_client(1);
         server.add_client(2);
-        let response = server.get_client_ids(3); // Aggiunge anche il client 3
+        let response = server.get_client_ids();
 
-        assert_eq!(response, "client_list!([1, 2, 3])");
+        assert_eq!(response, "client_list!([1, 2])");
     }
 
     #[test]
@@ -865,8 +2170,9 @@ mod tests_chat_server {
         server.add_client(2);
         let mut c = 0;
 
-        let value = server.process_request("client_list?".to_string(), 3, &mut c); // Aggiunge anche il client 3
-        assert_eq!(convert_back(value), "client_list!([1, 2, 3])");
+        let value = server.process_request("client_list?".to_string(), 3, &mut c); // the client 3 is not registered by asking
+        assert_eq!(convert_back(value), "client_list!([1, 2])");
+        assert!(server.take_outgoing().is_empty());
     }
 
     #[test]
@@ -884,6 +2190,189 @@ mod tests_chat_server {
         let value = server.process_request("unsupported_command?".to_string(), 1, &mut c);
         assert_eq!(convert_back(value), "error_unsupported_request!");
     }
+
+    #[test]
+    fn test_undelivered_message_goes_to_mailbox() {
+        let mut c = 0;
+        let mut server = ChatServer::new();
+        server.add_client(2);
+        let value = server.process_request("message_for?(2,Hello)".to_string(), 1, &mut c);
+        assert_eq!(convert_back(value), "message_from!(1,Hello)");
+
+        // the client 2 never acked it
+        let delivery = server.take_delivery_id().unwrap();
+        server.delivery_report(delivery, false);
+        assert_eq!(server.pending_messages(2), &[(1, "Hello".to_string())]);
+        assert!(server.take_outgoing().is_empty());
+
+        let value = server.process_request("messages?".to_string(), 2, &mut c);
+        assert_eq!(convert_back(value), "messages!(1)");
+        assert!(server.pending_messages(2).is_empty());
+        assert_eq!(server.take_delivery_id(), None);
+
+        let pushed = server.take_outgoing();
+        assert_eq!(pushed.len(), 1);
+        assert_eq!(pushed[0].destination, 2);
+        assert_eq!(pushed[0].message, "message_from!(1,Hello)");
+        server.delivery_report(pushed[0].delivery_id.unwrap(), true);
+        let receipts = server.take_outgoing();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].destination, 1);
+        assert_eq!(receipts[0].message, "message_delivered!(2,Hello)");
+    }
+
+    #[test]
+    fn test_mailbox_delivered_on_contact() {
+        let mut c = 0;
+        let mut server = ChatServer::new();
+        server.add_client(2);
+        server.process_request("message_for?(2,Hi)".to_string(), 1, &mut c);
+        let delivery = server.take_delivery_id().unwrap();
+        server.delivery_report(delivery, false);
+
+        // any request of client 2 makes the server push the message
+        server.process_request("client_list?".to_string(), 2, &mut c);
+        assert_eq!(server.take_delivery_id(), None);
+        let pushed = server.take_outgoing();
+        assert_eq!(pushed.len(), 1);
+        assert_eq!(pushed[0].destination, 2);
+        assert_eq!(pushed[0].message, "message_from!(1,Hi)");
+
+        server.delivery_report(pushed[0].delivery_id.unwrap(), true);
+        assert_eq!(server.take_outgoing()[0].message, "message_delivered!(2,Hi)");
+    }
+
+    #[test]
+    fn test_undelivered_receipt_when_recipient_unregisters() {
+        let mut c = 0;
+        let mut server = ChatServer::new();
+        server.add_client(2);
+        server.process_request("message_for?(2,Hi)".to_string(), 1, &mut c);
+        let first = server.take_delivery_id().unwrap();
+        server.delivery_report(first, false);
+        server.process_request("message_for?(2,Bye)".to_string(), 3, &mut c);
+        let second = server.take_delivery_id().unwrap();
+        server.take_outgoing();
+
+        // the waiting message goes back to its sender when the mailbox is deleted
+        server.process_request("unregister?".to_string(), 2, &mut c);
+        let receipts = server.take_outgoing();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].destination, 1);
+        assert_eq!(receipts[0].message, "message_undelivered!(2,Hi)");
+
+        // and so does the one that was on its way
+        server.delivery_report(second, false);
+        let receipts = server.take_outgoing();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].destination, 3);
+        assert_eq!(receipts[0].message, "message_undelivered!(2,Bye)");
+    }
+
+    #[test]
+    fn test_message_for_offline_client_is_queued() {
+        let mut c = 0;
+        let mut server = ChatServer::new();
+        server.add_client(1);
+        server.add_client(2);
+        let later = Instant::now() + CLIENT_TIMEOUT;
+        server.heard_from(1, later);
+        server.check_presence(later);
+        server.take_outgoing();
+
+        // no push and no retries, the message waits in the mailbox and the sender gets the receipt
+        let value = server.process_request("message_for?(2,Hi)".to_string(), 1, &mut c);
+        assert_eq!(convert_back(value), "message_queued!(2,Hi)");
+        assert_eq!(c, 0);
+        assert_eq!(server.take_delivery_id(), None);
+        assert_eq!(server.pending_messages(2), &[(1, "Hi".to_string())]);
+
+        server.heard_from(2, later);
+        server.take_outgoing();
+        let value = server.process_request("message_for?(2,Back)".to_string(), 1, &mut c);
+        assert_eq!(convert_back(value), "message_from!(1,Back)");
+        assert_eq!(c, 1);
+    }
+
+    #[test]
+    fn test_wrong_client_id_answers_the_sender() {
+        let mut c = 0;
+        let mut server = ChatServer::new();
+        server.add_client(1);
+        let value = server.process_request("message_for?(7,Hi)".to_string(), 1, &mut c);
+        assert_eq!(convert_back(value), "error_wrong_client_id!");
+        assert_eq!(c, 0);
+    }
+
+    #[test]
+    fn test_register_and_unregister() {
+        let mut c = 0;
+        let mut server = ChatServer::new();
+        server.add_client(1);
+
+        let value = server.process_request("register?(alice)".to_string(), 2, &mut c);
+        assert_eq!(convert_back(value), "registered!(2)");
+        let joined = server.take_outgoing();
+        assert_eq!(joined.len(), 1);
+        assert_eq!(joined[0].destination, 1);
+        assert_eq!(joined[0].message, "client_joined!(2,alice)");
+
+        let value = server.process_request("unregister?".to_string(), 2, &mut c);
+        assert_eq!(convert_back(value), "unregistered!(2)");
+        assert_eq!(server.take_outgoing()[0].message, "client_left!(2)");
+
+        let value = server.process_request("unregister?".to_string(), 2, &mut c);
+        assert_eq!(convert_back(value), "error_wrong_client_id!");
+        let value = server.process_request("register?()".to_string(), 2, &mut c);
+        assert!(convert_back(value).starts_with("error_unsupported_request!"));
+    }
+
+    #[test]
+    fn test_keepalive_does_not_register_again() {
+        let mut c = 0;
+        let mut server = ChatServer::new();
+        server.add_client(1);
+        server.process_request("register?(bob)".to_string(), 2, &mut c);
+        server.process_request("unregister?".to_string(), 2, &mut c);
+        server.take_outgoing();
+
+        // the periodic client_list? of the unregistered client
+        let value = server.process_request("client_list?".to_string(), 2, &mut c);
+        assert_eq!(convert_back(value), "client_list!([1])");
+        assert!(!server.is_online(2));
+        assert!(server.take_outgoing().is_empty());
+
+        server.process_request("server_type?".to_string(), 2, &mut c);
+        assert!(server.take_outgoing().is_empty());
+        let value = server.process_request("client_list?".to_string(), 1, &mut c);
+        assert_eq!(convert_back(value), "client_list!([1])");
+    }
+
+    #[test]
+    fn test_silent_client_goes_offline_and_comes_back() {
+        let mut c = 0;
+        let mut server = ChatServer::new();
+        server.add_client(1);
+        server.add_client(2);
+        server.take_outgoing();
+
+        // client 1 keeps talking, client 2 goes silent
+        let later = Instant::now() + CLIENT_TIMEOUT;
+        server.heard_from(1, later);
+        server.check_presence(later);
+        assert!(!server.is_online(2));
+        let left = server.take_outgoing();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].destination, 1);
+        assert_eq!(left[0].message, "client_left!(2)");
+
+        let value = server.process_request("client_list?".to_string(), 1, &mut c);
+        assert_eq!(convert_back(value), "client_list!([1])");
+
+        server.heard_from(2, later);
+        assert!(server.is_online(2));
+        assert_eq!(server.take_outgoing()[0].message, "client_joined!(2)");
+    }
     /*
         #[test]
     fn test_write_message_not_in_use() {
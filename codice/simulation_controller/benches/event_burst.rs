@@ -0,0 +1,260 @@
+use common::get_drone_impl::get_drone_impl;
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender};
+use simulation_controller::forwarded_event::{ForwardedEvent, FORWARDED_EVENT_CAPACITY};
+use simulation_controller::node_stats::{DroneStats, EndpointStats};
+use simulation_controller::traffic::TRAFFIC_TICK;
+use simulation_controller::{SimulationController, SimulationControllerUI};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::thread;
+use std::time::{Duration, Instant};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{Fragment, NackType, Packet, PacketType, FRAGMENT_DSIZE};
+
+const CLIENT: NodeId = 10;
+const SERVER: NodeId = 20;
+const DRONES: [NodeId; 3] = [1, 2, 3];
+const FRAGMENTS: u64 = 2_000;
+const PDR: f32 = 0.1;
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30);
+// Time between two frames of the UI
+const FRAME: Duration = Duration::from_micros(16_667);
+
+/// Events forwarded by the controller, with the time they arrived since the recording started
+type Trace = VecDeque<(Duration, ForwardedEvent)>;
+
+fn route() -> Vec<NodeId> {
+    [CLIENT].into_iter().chain(DRONES).chain([SERVER]).collect()
+}
+
+fn neighbours(node_id: NodeId) -> HashSet<NodeId> {
+    let route = route();
+    let index = route
+        .iter()
+        .position(|id| *id == node_id)
+        .expect("Node should be on the route");
+    [index.checked_sub(1), Some(index + 1)]
+        .into_iter()
+        .flatten()
+        .filter_map(|index| route.get(index).copied())
+        .collect()
+}
+
+/// Answers every fragment with an ack until `done` is dropped
+fn run_server(packets: Receiver<Packet>, drone: Sender<Packet>, done: Receiver<()>) {
+    let back = route().into_iter().rev().collect::<Vec<_>>();
+    loop {
+        select! {
+            recv(packets) -> packet => {
+                if let Ok(Packet { pack_type: PacketType::MsgFragment(fragment), session_id, .. }) = packet {
+                    let ack = Packet::new_ack(
+                        SourceRoutingHeader::new(back.clone(), 1),
+                        session_id,
+                        fragment.fragment_index,
+                    );
+                    drone.send(ack).expect("The drone should be running");
+                }
+            }
+            recv(done) -> _ => return,
+        }
+    }
+}
+
+/// Records what the controller forwards while a client sends a file to a server
+/// through three real drones, sending again every fragment they drop
+fn record_transfer() -> Trace {
+    let mut packet_senders = HashMap::new();
+    let mut packet_receivers = HashMap::new();
+    for node_id in route() {
+        let (sender, receiver) = unbounded();
+        packet_senders.insert(node_id, sender);
+        packet_receivers.insert(node_id, receiver);
+    }
+    let (event_sender, event_receiver) = unbounded();
+    let mut command_senders = HashMap::new();
+
+    for (index, drone_id) in DRONES.into_iter().enumerate() {
+        let (command_sender, command_receiver) = unbounded();
+        command_senders.insert(drone_id, command_sender);
+        let mut drone = get_drone_impl(
+            u8::try_from(index).expect("There are only three drones"),
+            drone_id,
+            event_sender.clone(),
+            command_receiver,
+            packet_receivers[&drone_id].clone(),
+            neighbours(drone_id)
+                .into_iter()
+                .map(|neighbour| (neighbour, packet_senders[&neighbour].clone()))
+                .collect(),
+            PDR,
+        );
+        thread::spawn(move || drone.run());
+    }
+
+    let (ui_command_sender, ui_command_receiver) = unbounded();
+    let (ui_response_sender, _ui_response_receiver) = unbounded();
+    let (forwarded_event_sender, forwarded_event_receiver) = unbounded();
+    let client_drone = packet_senders[&DRONES[0]].clone();
+    let server_drone = packet_senders[&DRONES[2]].clone();
+    let mut controller = SimulationController::new(
+        command_senders,
+        packet_senders,
+        event_receiver,
+        event_sender,
+        route()
+            .into_iter()
+            .map(|node_id| (node_id, neighbours(node_id)))
+            .collect(),
+        DRONES.to_vec(),
+        vec![CLIENT],
+        vec![SERVER],
+        0,
+        ui_command_receiver,
+        ui_response_sender,
+        forwarded_event_sender,
+        vec![],
+        HashMap::new(),
+        HashMap::new(),
+        HashMap::new(),
+        None,
+    );
+    thread::spawn(move || controller.run());
+
+    let start = Instant::now();
+    let recorder = thread::spawn(move || {
+        forwarded_event_receiver
+            .iter()
+            .map(|event| (start.elapsed(), event))
+            .collect::<Trace>()
+    });
+    let (done_sender, done_receiver) = bounded::<()>(0);
+    let server_packets = packet_receivers[&SERVER].clone();
+    thread::spawn(move || run_server(server_packets, server_drone, done_receiver));
+
+    let fragment = |fragment_index| {
+        Packet::new_fragment(
+            SourceRoutingHeader::new(route(), 1),
+            1,
+            Fragment::new(fragment_index, FRAGMENTS, [0; FRAGMENT_DSIZE]),
+        )
+    };
+    for fragment_index in 0..FRAGMENTS {
+        client_drone
+            .send(fragment(fragment_index))
+            .expect("The drone should be running");
+    }
+    let mut acked = HashSet::new();
+    while acked.len() < usize::try_from(FRAGMENTS).expect("Should fit") {
+        let packet = packet_receivers[&CLIENT]
+            .recv_timeout(TRANSFER_TIMEOUT)
+            .expect("The transfer should complete");
+        match packet.pack_type {
+            PacketType::Ack(ack) => {
+                acked.insert(ack.fragment_index);
+            }
+            PacketType::Nack(nack) if matches!(nack.nack_type, NackType::Dropped) => {
+                client_drone
+                    .send(fragment(nack.fragment_index))
+                    .expect("The drone should be running");
+            }
+            _ => {}
+        }
+    }
+
+    // Let the controller send the last counters, then stop everything
+    thread::sleep(TRAFFIC_TICK * 2);
+    drop((done_sender, client_drone, ui_command_sender));
+    recorder.join().expect("The recorder should not panic")
+}
+
+/// A UI for the recorded network, with its end of the channel the trace is replayed into
+fn recorded_ui() -> (SimulationControllerUI, Sender<ForwardedEvent>) {
+    let drone_stats = DRONES
+        .into_iter()
+        .map(|drone_id| {
+            (
+                drone_id,
+                DroneStats::new(neighbours(drone_id), PDR, "Drone".to_string()),
+            )
+        })
+        .collect();
+    let endpoint_stats = HashMap::from([
+        (
+            CLIENT,
+            EndpointStats::new(neighbours(CLIENT), "Client".to_string()),
+        ),
+        (
+            SERVER,
+            EndpointStats::new(neighbours(SERVER), "Server".to_string()),
+        ),
+    ]);
+
+    let (ui_command_sender, _) = unbounded();
+    let (_, ui_response_receiver) = unbounded();
+    let (forwarded_event_sender, forwarded_event_receiver) = bounded(FORWARDED_EVENT_CAPACITY);
+    let ui = SimulationControllerUI::new(
+        drone_stats,
+        endpoint_stats,
+        ui_command_sender,
+        ui_response_receiver,
+        forwarded_event_receiver,
+        vec![CLIENT],
+        vec![SERVER],
+    );
+    (ui, forwarded_event_sender)
+}
+
+/// Sends the UI the events recorded up to `until` the way the controller does: the
+/// packet events that don't fit in the channel are skipped, the others wait for room
+fn feed(trace: &mut Trace, sender: &Sender<ForwardedEvent>, until: Duration) {
+    while trace.front().is_some_and(|(at, _)| *at <= until) {
+        let (at, event) = trace.pop_front().expect("Should have an event");
+        if event.is_droppable() {
+            let _ = sender.try_send(event);
+        } else if let Err(error) = sender.try_send(event) {
+            trace.push_front((at, error.into_inner()));
+            return;
+        }
+    }
+}
+
+/// Runs the UI frame by frame, starting `delay` after the recording did, until
+/// every event is handled. Returns how many frames it took.
+fn replay(mut trace: Trace, delay: Duration) -> u32 {
+    let (mut ui, sender) = recorded_ui();
+    let mut frames = 0;
+    let mut time = delay;
+    while !trace.is_empty() || ui.backlog() > 0 {
+        feed(&mut trace, &sender, time);
+        ui.process_forwarded_events(time.as_secs_f64());
+        frames += 1;
+        time += FRAME;
+    }
+    frames
+}
+
+fn replay_recorded_transfer(c: &mut Criterion) {
+    let trace = record_transfer();
+    let length = trace.back().map_or(Duration::ZERO, |(at, _)| *at);
+
+    // The UI keeps up with the transfer as it happens
+    c.bench_function("recorded transfer, live", |b| {
+        b.iter_batched(
+            || trace.clone(),
+            |trace| replay(trace, Duration::ZERO),
+            BatchSize::LargeInput,
+        );
+    });
+
+    // The UI was stalled for the whole transfer and catches up afterwards
+    c.bench_function("recorded transfer, after a stall", |b| {
+        b.iter_batched(
+            || trace.clone(),
+            |trace| replay(trace, length),
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+criterion_group!(benches, replay_recorded_transfer);
+criterion_main!(benches);
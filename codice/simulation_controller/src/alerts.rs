@@ -0,0 +1,482 @@
+use crate::node_stats::DroneStats;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NackType, Packet, PacketType};
+
+/// Fragments a drone must handle before its drop rate is tested
+const MIN_DROP_RATE_SAMPLES: u32 = 30;
+/// How many standard deviations above the expected drops raise an alert
+const DROP_RATE_Z_THRESHOLD: f64 = 3.0;
+/// `ErrorInRouting` NACKs for the same existing link before raising an alert
+const ROUTING_ERROR_THRESHOLD: u32 = 3;
+/// Seconds after which a flood still being forwarded is considered endless
+const FLOOD_MAX_AGE: f64 = 10.0;
+/// Seconds a fragment can wait for its ACK or NACK
+const ACK_TIMEOUT: f64 = 10.0;
+/// Seconds before the same alert can be raised again
+const ALERT_COOLDOWN: f64 = 30.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    HighDropRate,
+    RoutingErrorOnExistingLink,
+    EndlessFlood,
+    UnacknowledgedSession,
+    CrashedDroneTraffic,
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AlertKind::HighDropRate => "High drop rate",
+            AlertKind::RoutingErrorOnExistingLink => "Routing error on existing link",
+            AlertKind::EndlessFlood => "Endless flood",
+            AlertKind::UnacknowledgedSession => "Unacknowledged session",
+            AlertKind::CrashedDroneTraffic => "Crashed drone traffic",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    // UI time the alert was raised at
+    pub time: f64,
+    pub kind: AlertKind,
+    pub node: NodeId,
+    pub message: String,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:.1}s] {} (node {}): {}",
+            self.time, self.kind, self.node, self.message
+        )
+    }
+}
+
+/// Drops observed on a drone against the drops expected from its PDR at the time
+#[derive(Debug, Default)]
+struct DropRateTest {
+    fragments: u32,
+    dropped: u32,
+    expected: f64,
+    variance: f64,
+}
+
+impl DropRateTest {
+    fn record(&mut self, pdr: f32, dropped: bool) {
+        let pdr = f64::from(pdr);
+        self.fragments += 1;
+        self.dropped += u32::from(dropped);
+        self.expected += pdr;
+        self.variance += pdr * (1.0 - pdr);
+    }
+
+    /// Standard score of the observed drops, if there are enough samples
+    fn z_score(&self) -> Option<f64> {
+        if self.fragments < MIN_DROP_RATE_SAMPLES {
+            return None;
+        }
+        let excess = f64::from(self.dropped) - self.expected;
+        if self.variance < f64::EPSILON {
+            // A drone with PDR 0 should never drop
+            return Some(if excess > 0.0 { f64::INFINITY } else { 0.0 });
+        }
+        Some(excess / self.variance.sqrt())
+    }
+}
+
+/// Watches the forwarded events for suspicious behaviour
+#[derive(Debug, Default)]
+pub struct AnomalyDetector {
+    alerts: Vec<Alert>,
+    // Alerts not yet taken by the event log
+    unlogged: Vec<Alert>,
+    last_raised: HashMap<(AlertKind, NodeId, u64), f64>,
+    drop_rate_tests: HashMap<NodeId, DropRateTest>,
+    // Count and last time of the ErrorInRouting NACKs for each link
+    routing_errors: HashMap<(NodeId, NodeId), (u32, f64)>,
+    // First time each (initiator, flood id) was seen
+    floods: HashMap<(NodeId, u64), f64>,
+    // First time each (source, session, fragment index) was seen without an ACK or NACK
+    pending_fragments: HashMap<(NodeId, u64, u64), f64>,
+}
+
+impl AnomalyDetector {
+    #[must_use]
+    pub fn alerts(&self) -> &[Alert] {
+        &self.alerts
+    }
+
+    pub fn clear(&mut self) {
+        self.alerts.clear();
+    }
+
+    /// Returns the alerts raised since the last call
+    pub fn take_unlogged(&mut self) -> Vec<Alert> {
+        std::mem::take(&mut self.unlogged)
+    }
+
+    /// Checks a packet `node_id` has sent
+    pub fn packet_sent(
+        &mut self,
+        now: f64,
+        node_id: NodeId,
+        packet: &Packet,
+        drone_stats: &HashMap<NodeId, DroneStats>,
+    ) {
+        self.check_crashed_traffic(now, node_id, packet, drone_stats);
+
+        let source = packet.routing_header.source();
+        match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => {
+                if let Some(stats) = drone_stats.get(&node_id) {
+                    self.record_fragment(now, node_id, stats.pdr, false);
+                }
+                if let Some(source) = source {
+                    self.pending_fragments
+                        .entry((source, packet.session_id, fragment.fragment_index))
+                        .or_insert(now);
+                }
+            }
+            PacketType::Ack(ack) => {
+                if let Some(destination) = packet.routing_header.destination() {
+                    self.pending_fragments.remove(&(
+                        destination,
+                        packet.session_id,
+                        ack.fragment_index,
+                    ));
+                }
+            }
+            PacketType::Nack(nack) => {
+                if let Some(destination) = packet.routing_header.destination() {
+                    self.pending_fragments.remove(&(
+                        destination,
+                        packet.session_id,
+                        nack.fragment_index,
+                    ));
+                }
+                // Only count the NACK once, when its creator sends it
+                if let NackType::ErrorInRouting(next_hop) = nack.nack_type {
+                    if source == Some(node_id) {
+                        self.check_routing_error(now, node_id, next_hop, drone_stats);
+                    }
+                }
+            }
+            PacketType::FloodRequest(flood_request) => {
+                let first_seen = *self
+                    .floods
+                    .entry((flood_request.initiator_id, flood_request.flood_id))
+                    .or_insert(now);
+                let mut visited = HashSet::new();
+                let looping = !flood_request
+                    .path_trace
+                    .iter()
+                    .all(|(id, _)| visited.insert(*id));
+
+                if looping || now - first_seen > FLOOD_MAX_AGE {
+                    self.raise(
+                        now,
+                        AlertKind::EndlessFlood,
+                        flood_request.initiator_id,
+                        flood_request.flood_id,
+                        format!(
+                            "Flood {} is still circulating after {:.0} s, path trace {:?}",
+                            flood_request.flood_id,
+                            now - first_seen,
+                            flood_request.path_trace
+                        ),
+                    );
+                }
+            }
+            PacketType::FloodResponse(_) => {}
+        }
+    }
+
+    /// Checks a packet `node_id` has dropped
+    pub fn packet_dropped(
+        &mut self,
+        now: f64,
+        node_id: NodeId,
+        packet: &Packet,
+        drone_stats: &HashMap<NodeId, DroneStats>,
+    ) {
+        if let (PacketType::MsgFragment(_), Some(stats)) =
+            (&packet.pack_type, drone_stats.get(&node_id))
+        {
+            self.record_fragment(now, node_id, stats.pdr, true);
+        }
+    }
+
+    /// Raises the alerts that depend on time passing
+    pub fn check_timeouts(&mut self, now: f64) {
+        let expired = self
+            .pending_fragments
+            .iter()
+            .filter(|(_, first_seen)| now - **first_seen > ACK_TIMEOUT)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for (source, session_id, fragment_index) in expired {
+            self.pending_fragments
+                .remove(&(source, session_id, fragment_index));
+            self.raise(
+                now,
+                AlertKind::UnacknowledgedSession,
+                source,
+                session_id,
+                format!(
+                    "Session {session_id}: fragment {fragment_index} not acknowledged after {ACK_TIMEOUT} s"
+                ),
+            );
+        }
+
+        self.floods
+            .retain(|_, first_seen| now - *first_seen < ALERT_COOLDOWN);
+        self.routing_errors
+            .retain(|_, (_, last_seen)| now - *last_seen < ALERT_COOLDOWN);
+        self.last_raised
+            .retain(|_, last_raised| now - *last_raised < ALERT_COOLDOWN);
+    }
+
+    /// Forgets the statistics of a drone that crashed
+    pub fn forget_node(&mut self, node_id: NodeId) {
+        self.drop_rate_tests.remove(&node_id);
+        self.routing_errors
+            .retain(|(from, to), _| *from != node_id && *to != node_id);
+    }
+
+    fn record_fragment(&mut self, now: f64, node_id: NodeId, pdr: f32, dropped: bool) {
+        let test = self.drop_rate_tests.entry(node_id).or_default();
+        test.record(pdr, dropped);
+
+        if let Some(z) = test.z_score() {
+            if z > DROP_RATE_Z_THRESHOLD {
+                let message = format!(
+                    "Dropped {} of {} fragments, {:.1} expected from its PDR",
+                    test.dropped, test.fragments, test.expected
+                );
+                self.raise(now, AlertKind::HighDropRate, node_id, 0, message);
+            }
+        }
+    }
+
+    fn check_routing_error(
+        &mut self,
+        now: f64,
+        node_id: NodeId,
+        next_hop: NodeId,
+        drone_stats: &HashMap<NodeId, DroneStats>,
+    ) {
+        let link_exists = drone_stats
+            .get(&node_id)
+            .is_some_and(|stats| stats.neigbours.contains(&next_hop))
+            && !drone_stats
+                .get(&next_hop)
+                .is_some_and(|stats| stats.crashed);
+        if !link_exists {
+            return;
+        }
+
+        let (count, last_seen) = self
+            .routing_errors
+            .entry((node_id, next_hop))
+            .or_insert((0, now));
+        *count += 1;
+        *last_seen = now;
+        let count = *count;
+        if count >= ROUTING_ERROR_THRESHOLD {
+            let message = format!("{count} ErrorInRouting NACKs for existing link to {next_hop}");
+            self.raise(
+                now,
+                AlertKind::RoutingErrorOnExistingLink,
+                node_id,
+                u64::from(next_hop),
+                message,
+            );
+        }
+    }
+
+    fn check_crashed_traffic(
+        &mut self,
+        now: f64,
+        node_id: NodeId,
+        packet: &Packet,
+        drone_stats: &HashMap<NodeId, DroneStats>,
+    ) {
+        let crashed = |id: NodeId| drone_stats.get(&id).is_some_and(|stats| stats.crashed);
+
+        // While draining, a crashed drone may still send back ACKs, NACKs and flood responses
+        if crashed(node_id)
+            && matches!(
+                packet.pack_type,
+                PacketType::MsgFragment(_) | PacketType::FloodRequest(_)
+            )
+        {
+            self.raise(
+                now,
+                AlertKind::CrashedDroneTraffic,
+                node_id,
+                0,
+                "Forwarded traffic after crashing".to_string(),
+            );
+        }
+
+        if let Some(next_hop) = packet.routing_header.current_hop() {
+            if next_hop != node_id && crashed(next_hop) {
+                self.raise(
+                    now,
+                    AlertKind::CrashedDroneTraffic,
+                    next_hop,
+                    u64::from(node_id),
+                    format!("Node {node_id} sent a packet through it after the crash"),
+                );
+            }
+        }
+    }
+
+    /// Adds an alert unless the same one was raised less than `ALERT_COOLDOWN` ago
+    fn raise(&mut self, now: f64, kind: AlertKind, node: NodeId, key: u64, message: String) {
+        if let Some(last) = self.last_raised.get(&(kind, node, key)) {
+            if now - last < ALERT_COOLDOWN {
+                return;
+            }
+        }
+        self.last_raised.insert((kind, node, key), now);
+        let alert = Alert {
+            time: now,
+            kind,
+            node,
+            message,
+        };
+        self.unlogged.push(alert.clone());
+        self.alerts.push(alert);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::{Fragment, Nack, FRAGMENT_DSIZE};
+
+    fn drone_stats(pdr: f32) -> HashMap<NodeId, DroneStats> {
+        HashMap::from([
+            (
+                1,
+                DroneStats::new(HashSet::from([2, 3]), pdr, String::new()),
+            ),
+            (2, DroneStats::new(HashSet::from([1]), pdr, String::new())),
+            (3, DroneStats::new(HashSet::from([1]), pdr, String::new())),
+        ])
+    }
+
+    fn fragment(session_id: u64) -> Packet {
+        Packet::new_fragment(
+            SourceRoutingHeader::new(vec![10, 1, 3], 2),
+            session_id,
+            Fragment::new(0, 1, [0; FRAGMENT_DSIZE]),
+        )
+    }
+
+    /// Drone 1 handles one fragment per session, dropping every `drop_every`-th
+    fn forward_or_drop(
+        detector: &mut AnomalyDetector,
+        stats: &HashMap<NodeId, DroneStats>,
+        sessions: u64,
+        drop_every: u64,
+    ) {
+        for session_id in 0..sessions {
+            // A fragment is either forwarded or dropped, never both
+            if session_id % drop_every == 0 {
+                detector.packet_dropped(0.0, 1, &fragment(session_id), stats);
+            } else {
+                detector.packet_sent(0.0, 1, &fragment(session_id), stats);
+            }
+        }
+    }
+
+    #[test]
+    fn test_high_drop_rate() {
+        let stats = drone_stats(0.1);
+        let mut detector = AnomalyDetector::default();
+        forward_or_drop(&mut detector, &stats, 40, 2);
+        assert_eq!(detector.alerts().len(), 1);
+        assert_eq!(detector.alerts()[0].kind, AlertKind::HighDropRate);
+    }
+
+    #[test]
+    fn test_expected_drop_rate() {
+        let stats = drone_stats(0.5);
+        let mut detector = AnomalyDetector::default();
+        forward_or_drop(&mut detector, &stats, 100, 2);
+        assert!(detector.alerts().is_empty());
+    }
+
+    #[test]
+    fn test_crashed_drone_forgotten() {
+        let stats = drone_stats(0.1);
+        let mut detector = AnomalyDetector::default();
+        // Not enough samples yet, then the drone crashes
+        forward_or_drop(&mut detector, &stats, 20, 2);
+        detector.forget_node(1);
+        forward_or_drop(&mut detector, &stats, 20, 2);
+        assert!(detector.alerts().is_empty());
+    }
+
+    #[test]
+    fn test_routing_error_on_existing_link() {
+        let stats = drone_stats(0.0);
+        let mut detector = AnomalyDetector::default();
+        let nack = Packet::new_nack(
+            SourceRoutingHeader::new(vec![1, 2], 1),
+            0,
+            Nack {
+                fragment_index: 0,
+                nack_type: NackType::ErrorInRouting(3),
+            },
+        );
+        for _ in 0..ROUTING_ERROR_THRESHOLD {
+            detector.packet_sent(0.0, 1, &nack, &stats);
+        }
+        assert_eq!(
+            detector.alerts()[0].kind,
+            AlertKind::RoutingErrorOnExistingLink
+        );
+    }
+
+    #[test]
+    fn test_unacknowledged_session() {
+        let stats = drone_stats(0.0);
+        let mut detector = AnomalyDetector::default();
+        detector.packet_sent(0.0, 1, &fragment(7), &stats);
+        detector.packet_sent(0.0, 1, &fragment(8), &stats);
+
+        let ack = Packet::new_ack(SourceRoutingHeader::new(vec![3, 1, 10], 2), 8, 0);
+        detector.packet_sent(1.0, 1, &ack, &stats);
+
+        detector.check_timeouts(ACK_TIMEOUT + 1.0);
+        assert_eq!(detector.alerts().len(), 1);
+        assert_eq!(detector.alerts()[0].kind, AlertKind::UnacknowledgedSession);
+        assert_eq!(detector.alerts()[0].node, 10);
+        assert!(detector.pending_fragments.is_empty());
+
+        // The cooldown of the alert is forgotten once it's over
+        detector.check_timeouts(ACK_TIMEOUT + 1.0 + ALERT_COOLDOWN);
+        assert!(detector.last_raised.is_empty());
+    }
+
+    #[test]
+    fn test_crashed_drone_traffic() {
+        let mut stats = drone_stats(0.0);
+        stats.get_mut(&3).expect("Drone 3 should exist").crashed = true;
+        let mut detector = AnomalyDetector::default();
+        detector.packet_sent(0.0, 1, &fragment(0), &stats);
+        assert_eq!(detector.alerts()[0].kind, AlertKind::CrashedDroneTraffic);
+        assert_eq!(detector.alerts()[0].node, 3);
+    }
+}
@@ -0,0 +1,129 @@
+use serde::Deserialize;
+use std::time::Duration;
+use wg_2024::packet::PacketType;
+
+/// How often the controller charges the idle cost and reports the batteries
+pub const BATTERY_TICK: Duration = Duration::from_secs(1);
+
+/// Energy budget of a drone, in arbitrary units
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct BatteryConfig {
+    pub capacity: f32,
+    /// Cost of each fragment, ACK or NACK forwarded
+    pub fragment_cost: f32,
+    /// Cost of each flood request or response forwarded
+    pub flood_cost: f32,
+    /// Cost of every second spent running
+    pub idle_cost: f32,
+}
+
+impl BatteryConfig {
+    /// Checks that the capacity is positive and no cost is negative
+    ///
+    /// # Errors
+    /// Returns a description of the first invalid parameter
+    pub fn validate(&self) -> Result<(), String> {
+        if self.capacity <= 0.0 {
+            return Err("Battery capacity must be positive".to_string());
+        }
+        if [self.fragment_cost, self.flood_cost, self.idle_cost]
+            .iter()
+            .any(|cost| *cost < 0.0)
+        {
+            return Err("Battery costs can't be negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Charge left in the battery of a drone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Battery {
+    pub config: BatteryConfig,
+    pub charge: f32,
+}
+
+impl Battery {
+    #[must_use]
+    pub fn new(config: BatteryConfig) -> Self {
+        Self {
+            config,
+            charge: config.capacity,
+        }
+    }
+
+    /// Spends the cost of forwarding a packet, returns whether this emptied the battery
+    pub fn packet_sent(&mut self, pack_type: &PacketType) -> bool {
+        let cost = match pack_type {
+            PacketType::MsgFragment(_) | PacketType::Ack(_) | PacketType::Nack(_) => {
+                self.config.fragment_cost
+            }
+            PacketType::FloodRequest(_) | PacketType::FloodResponse(_) => self.config.flood_cost,
+        };
+        self.spend(cost)
+    }
+
+    /// Spends the idle cost of `elapsed`, returns whether this emptied the battery
+    pub fn idle(&mut self, elapsed: Duration) -> bool {
+        self.spend(self.config.idle_cost * elapsed.as_secs_f32())
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.charge <= 0.0
+    }
+
+    /// Share of the capacity left, between 0 and 1
+    #[must_use]
+    pub fn level(&self) -> f32 {
+        (self.charge / self.config.capacity).clamp(0.0, 1.0)
+    }
+
+    fn spend(&mut self, cost: f32) -> bool {
+        let was_empty = self.is_empty();
+        self.charge = (self.charge - cost).max(0.0);
+        !was_empty && self.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wg_2024::packet::{Ack, FloodRequest};
+
+    const CONFIG: BatteryConfig = BatteryConfig {
+        capacity: 10.0,
+        fragment_cost: 1.0,
+        flood_cost: 3.0,
+        idle_cost: 0.5,
+    };
+
+    #[test]
+    fn test_battery_drains_once() {
+        let mut battery = Battery::new(CONFIG);
+        assert!(!battery.packet_sent(&PacketType::Ack(Ack { fragment_index: 0 })));
+        assert!(!battery.packet_sent(&PacketType::FloodRequest(FloodRequest::new(1, 5))));
+        assert!(!battery.idle(Duration::from_secs(6)));
+        assert!((battery.level() - 0.3).abs() < f32::EPSILON);
+
+        assert!(battery.packet_sent(&PacketType::FloodRequest(FloodRequest::new(2, 5))));
+        assert!(battery.is_empty());
+        assert!(!battery.idle(Duration::from_secs(1)));
+        assert!(battery.level().abs() < f32::EPSILON);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(CONFIG.validate().is_ok());
+        let config = BatteryConfig {
+            capacity: 0.0,
+            ..CONFIG
+        };
+        assert!(config.validate().is_err());
+        let config = BatteryConfig {
+            flood_cost: -1.0,
+            ..CONFIG
+        };
+        assert!(config.validate().is_err());
+    }
+}
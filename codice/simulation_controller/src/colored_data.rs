@@ -16,6 +16,10 @@ pub enum NodeType {
 pub struct NodeData {
     pub label: String,
     pub node_type: NodeType,
+    // Whether losing this node partitions the network
+    pub critical: bool,
+    // Share of battery left, for drones that have one
+    pub battery: Option<f32>,
 }
 
 trait ColoredData {
@@ -26,7 +30,16 @@ trait ColoredData {
 impl ColoredData for NodeData {
     fn color(&self) -> egui::Color32 {
         match self.node_type {
-            NodeType::Drone => egui::Color32::from_rgb(0, 0, 255),
+            NodeType::Drone if self.critical => egui::Color32::from_rgb(255, 140, 0),
+            // Fades to grey as the battery runs out
+            NodeType::Drone => match self.battery {
+                Some(level) => egui::Color32::from_rgb(
+                    (120.0 * (1.0 - level)) as u8,
+                    (120.0 * (1.0 - level)) as u8,
+                    (120.0 + 135.0 * level) as u8,
+                ),
+                None => egui::Color32::from_rgb(0, 0, 255),
+            },
             NodeType::Client => egui::Color32::from_rgb(255, 0, 0),
             NodeType::Server => egui::Color32::from_rgb(0, 255, 0),
         }
@@ -101,5 +114,6 @@ impl<N: Clone + ColoredData, E: Clone, Ty: EdgeType, Ix: IndexType> DisplayNode<
 
     fn update(&mut self, state: &NodeProps<N>) {
         self.loc = state.location();
+        self.color = state.payload.color();
     }
 }
@@ -0,0 +1,152 @@
+use crate::forwarded_event::ForwardedEvent;
+use crossbeam_channel::Receiver;
+use std::time::{Duration, Instant};
+use wg_2024::packet::Packet;
+
+/// Most events handled in one frame, the rest wait in the channel for the next frames
+pub const EVENTS_PER_FRAME: usize = 2_000;
+/// Most time spent handling events in one frame
+pub const FRAME_TIME_BUDGET: Duration = Duration::from_millis(8);
+/// Backlog above which only a sample of the packets is animated
+pub const SAMPLING_BACKLOG: usize = 1_000;
+
+/// Takes the forwarded events out of the channel in batches that fit in a frame
+pub struct EventBatcher {
+    receiver: Receiver<ForwardedEvent>,
+    events_per_frame: usize,
+    time_budget: Duration,
+}
+
+/// What is left of the budget of the current frame
+pub struct FrameBudget {
+    start: Instant,
+    handled: usize,
+}
+
+impl FrameBudget {
+    #[must_use]
+    pub fn handled(&self) -> usize {
+        self.handled
+    }
+}
+
+impl EventBatcher {
+    #[must_use]
+    pub fn new(receiver: Receiver<ForwardedEvent>) -> Self {
+        Self::with_budget(receiver, EVENTS_PER_FRAME, FRAME_TIME_BUDGET)
+    }
+
+    #[must_use]
+    pub fn with_budget(
+        receiver: Receiver<ForwardedEvent>,
+        events_per_frame: usize,
+        time_budget: Duration,
+    ) -> Self {
+        Self {
+            receiver,
+            events_per_frame,
+            time_budget,
+        }
+    }
+
+    #[must_use]
+    pub fn start_frame(&self) -> FrameBudget {
+        FrameBudget {
+            start: Instant::now(),
+            handled: 0,
+        }
+    }
+
+    /// Next queued event, `None` when the channel is empty or the frame budget ran out
+    pub fn next(&self, budget: &mut FrameBudget) -> Option<ForwardedEvent> {
+        if budget.handled >= self.events_per_frame || budget.start.elapsed() >= self.time_budget {
+            return None;
+        }
+        let event = self.receiver.try_recv().ok()?;
+        budget.handled += 1;
+        Some(event)
+    }
+
+    /// Events waiting to be handled
+    #[must_use]
+    pub fn backlog(&self) -> usize {
+        self.receiver.len()
+    }
+
+    /// One packet out of this many is animated, so that a large backlog does not
+    /// queue more animations than the graph can draw
+    #[must_use]
+    pub fn animation_stride(&self) -> u64 {
+        (self.backlog() / SAMPLING_BACKLOG) as u64 + 1
+    }
+}
+
+/// Whether the packet is in the animated sample. Every hop of a packet gets the
+/// same answer, so that sampled packets are animated along their whole route.
+#[must_use]
+pub fn is_sampled(packet: &Packet, stride: u64) -> bool {
+    stride <= 1
+        || packet
+            .session_id
+            .wrapping_add(packet.get_fragment_index())
+            .is_multiple_of(stride)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossbeam_channel::unbounded;
+    use wg_2024::network::SourceRoutingHeader;
+
+    #[test]
+    fn test_frame_budget_limits_events() {
+        let (sender, receiver) = unbounded();
+        for drone_id in 0..10 {
+            sender.send(ForwardedEvent::DroneCrashed(drone_id)).unwrap();
+        }
+        let batcher = EventBatcher::with_budget(receiver, 4, Duration::from_secs(1));
+
+        let mut budget = batcher.start_frame();
+        while batcher.next(&mut budget).is_some() {}
+        assert_eq!(budget.handled(), 4);
+        assert_eq!(batcher.backlog(), 6);
+
+        let mut budget = batcher.start_frame();
+        while batcher.next(&mut budget).is_some() {}
+        let mut budget = batcher.start_frame();
+        while batcher.next(&mut budget).is_some() {}
+        assert_eq!(budget.handled(), 2);
+        assert_eq!(batcher.backlog(), 0);
+    }
+
+    #[test]
+    fn test_animation_stride_grows_with_backlog() {
+        let (sender, receiver) = unbounded();
+        let batcher = EventBatcher::new(receiver);
+        assert_eq!(batcher.animation_stride(), 1);
+
+        for _ in 0..SAMPLING_BACKLOG * 3 {
+            sender.send(ForwardedEvent::DroneCrashed(1)).unwrap();
+        }
+        assert_eq!(batcher.animation_stride(), 4);
+    }
+
+    #[test]
+    fn test_sampling_is_per_packet() {
+        let packet = |session_id, hop_index| {
+            Packet::new_ack(
+                SourceRoutingHeader::new(vec![1, 2, 3], hop_index),
+                session_id,
+                0,
+            )
+        };
+        assert!(is_sampled(&packet(3, 1), 1));
+        assert_eq!(is_sampled(&packet(4, 1), 2), is_sampled(&packet(4, 2), 2));
+        assert_eq!(
+            (0..100)
+                .filter(|session_id| is_sampled(&packet(*session_id, 1), 4))
+                .count(),
+            25
+        );
+    }
+}
@@ -0,0 +1,698 @@
+use crate::alerts::Alert;
+use crate::forwarded_event::ForwardedEvent;
+use crate::topology_analysis::ImpactAction;
+use crate::ui_commands::UIResponse;
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Packet, PacketType};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 3] = [Severity::Info, Severity::Warning, Severity::Error];
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Severity::Info => "INFO",
+            Severity::Warning => "WARN",
+            Severity::Error => "ERROR",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogEventType {
+    Response,
+    PacketSent,
+    PacketDropped,
+    Shortcut,
+    Pdr,
+    Crash,
+    Battery,
+    Connection,
+    Topology,
+    Movement,
+    Alert,
+}
+
+impl LogEventType {
+    pub const ALL: [LogEventType; 11] = [
+        LogEventType::Response,
+        LogEventType::PacketSent,
+        LogEventType::PacketDropped,
+        LogEventType::Shortcut,
+        LogEventType::Pdr,
+        LogEventType::Crash,
+        LogEventType::Battery,
+        LogEventType::Connection,
+        LogEventType::Topology,
+        LogEventType::Movement,
+        LogEventType::Alert,
+    ];
+}
+
+impl fmt::Display for LogEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LogEventType::Response => "Response",
+            LogEventType::PacketSent => "Packet sent",
+            LogEventType::PacketDropped => "Packet dropped",
+            LogEventType::Shortcut => "Shortcut",
+            LogEventType::Pdr => "PDR",
+            LogEventType::Crash => "Crash",
+            LogEventType::Battery => "Battery",
+            LogEventType::Connection => "Connection",
+            LogEventType::Topology => "Topology",
+            LogEventType::Movement => "Movement",
+            LogEventType::Alert => "Alert",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    // UI time the entry was logged at
+    pub time: f64,
+    pub event_type: LogEventType,
+    pub severity: Severity,
+    // Nodes the entry is about, used by the node filter
+    pub nodes: Vec<NodeId>,
+    pub text: String,
+}
+
+impl LogEntry {
+    /// The entry for an event, `None` for the events too frequent to be logged
+    /// one by one, which `SuppressedEvents` counts instead
+    #[must_use]
+    pub fn from_event(time: f64, event: &ForwardedEvent) -> Option<Self> {
+        let (event_type, severity, nodes, text) = match event {
+            // Only the NACKs, the other packets would bury every other entry
+            ForwardedEvent::PacketSent(packet, _) => match packet.pack_type {
+                PacketType::Nack(_) => (
+                    LogEventType::PacketSent,
+                    Severity::Info,
+                    packet_nodes(packet),
+                    describe_packet(packet),
+                ),
+                _ => return None,
+            },
+            ForwardedEvent::TrafficCounted(_) => return None,
+            ForwardedEvent::PacketDropped(packet, _) => (
+                LogEventType::PacketDropped,
+                Severity::Warning,
+                packet_nodes(packet),
+                describe_packet(packet),
+            ),
+            ForwardedEvent::ShortcutDelivered(packet, _) => (
+                LogEventType::Shortcut,
+                Severity::Info,
+                packet_nodes(packet),
+                format!("Delivered directly: {}", describe_packet(packet)),
+            ),
+            ForwardedEvent::PDRSet(node_id, pdr) => (
+                LogEventType::Pdr,
+                Severity::Info,
+                vec![*node_id],
+                format!("Drone {node_id} PDR set to {pdr:.3}"),
+            ),
+            ForwardedEvent::PDRProfileSet(node_id, profile) => (
+                LogEventType::Pdr,
+                Severity::Info,
+                vec![*node_id],
+                match profile {
+                    Some(profile) => format!("Drone {node_id} PDR profile set to {profile:?}"),
+                    None => format!("Drone {node_id} PDR profile removed"),
+                },
+            ),
+            ForwardedEvent::DroneCrashed(node_id) => (
+                LogEventType::Crash,
+                Severity::Warning,
+                vec![*node_id],
+                format!("Drone {node_id} crashed"),
+            ),
+            // Sent every second, the levels are shown by the drone panels
+            ForwardedEvent::BatteryLevels(_) => return None,
+            ForwardedEvent::BatteryDepleted(node_id, result) => match result {
+                Ok(()) => (
+                    LogEventType::Battery,
+                    Severity::Warning,
+                    vec![*node_id],
+                    format!("Drone {node_id} ran out of battery and crashed"),
+                ),
+                Err(e) => (
+                    LogEventType::Battery,
+                    Severity::Error,
+                    vec![*node_id],
+                    format!("Drone {node_id} ran out of battery but can't crash: {e}"),
+                ),
+            },
+            ForwardedEvent::ConnectionAdded(node1, node2) => (
+                LogEventType::Connection,
+                Severity::Info,
+                vec![*node1, *node2],
+                format!("Connection {node1} - {node2} added"),
+            ),
+            ForwardedEvent::ConnectionRemoved(node1, node2) => (
+                LogEventType::Connection,
+                Severity::Info,
+                vec![*node1, *node2],
+                format!("Connection {node1} - {node2} removed"),
+            ),
+            // Sent several times a second, the positions are shown by the graph
+            ForwardedEvent::NodesMoved(_) => return None,
+            ForwardedEvent::TopologyAnalysed(analysis) => (
+                LogEventType::Topology,
+                Severity::Info,
+                analysis.articulation_points.clone(),
+                format!(
+                    "Topology analysed: critical drones {:?}, critical links {:?}",
+                    analysis.articulation_points, analysis.bridges
+                ),
+            ),
+            ForwardedEvent::ViewDiscovered(view, diff) => (
+                LogEventType::Topology,
+                Severity::Info,
+                vec![view.node_id],
+                format!(
+                    "Node {} view: {} missing links, {} stale links, {} unknown nodes",
+                    view.node_id,
+                    diff.missing_edges.len(),
+                    diff.stale_edges.len(),
+                    diff.unknown_nodes.len()
+                ),
+            ),
+            ForwardedEvent::ImpactPreviewed(preview) => (
+                LogEventType::Topology,
+                Severity::Info,
+                match preview.action {
+                    ImpactAction::Crash(drone_id) => vec![drone_id],
+                    ImpactAction::RemoveConnection(node1, node2) => vec![node1, node2],
+                },
+                format!(
+                    "Previewed {}: {}",
+                    preview.action,
+                    preview
+                        .failed_invariant
+                        .as_deref()
+                        .unwrap_or("no invariant broken")
+                ),
+            ),
+        };
+
+        Some(Self {
+            time,
+            event_type,
+            severity,
+            nodes,
+            text,
+        })
+    }
+
+    #[must_use]
+    pub fn from_response(time: f64, response: &UIResponse) -> Self {
+        let (severity, text) = match response {
+            UIResponse::Success(message) => (Severity::Info, message.clone()),
+            UIResponse::Falure(message) => (Severity::Error, message.clone()),
+        };
+        Self {
+            time,
+            event_type: LogEventType::Response,
+            severity,
+            nodes: vec![],
+            text,
+        }
+    }
+
+    #[must_use]
+    pub fn from_alert(alert: &Alert) -> Self {
+        Self {
+            time: alert.time,
+            event_type: LogEventType::Alert,
+            severity: Severity::Warning,
+            nodes: vec![alert.node],
+            text: format!("{}: {}", alert.kind, alert.message),
+        }
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{:.3}s] {} {} {:?}: {}",
+            self.time, self.severity, self.event_type, self.nodes, self.text
+        )
+    }
+}
+
+/// Every node on the route of the packet, or in its path trace for floods
+fn packet_nodes(packet: &Packet) -> Vec<NodeId> {
+    match &packet.pack_type {
+        PacketType::FloodRequest(flood_request) => {
+            flood_request.path_trace.iter().map(|(id, _)| *id).collect()
+        }
+        PacketType::FloodResponse(flood_response) if packet.routing_header.hops.is_empty() => {
+            flood_response
+                .path_trace
+                .iter()
+                .map(|(id, _)| *id)
+                .collect()
+        }
+        _ => packet.routing_header.hops.clone(),
+    }
+}
+
+/// A one line summary of the packet, without the fragment data
+fn describe_packet(packet: &Packet) -> String {
+    let kind = match &packet.pack_type {
+        PacketType::MsgFragment(fragment) => format!(
+            "Fragment {}/{}",
+            fragment.fragment_index + 1,
+            fragment.total_n_fragments
+        ),
+        PacketType::Ack(ack) => format!("Ack {}", ack.fragment_index),
+        PacketType::Nack(nack) => format!("Nack {} {:?}", nack.fragment_index, nack.nack_type),
+        PacketType::FloodRequest(flood_request) => format!(
+            "Flood request {} from {}",
+            flood_request.flood_id, flood_request.initiator_id
+        ),
+        PacketType::FloodResponse(flood_response) => {
+            format!("Flood response {}", flood_response.flood_id)
+        }
+    };
+    format!(
+        "{kind}, session {}, route {:?} at hop {}",
+        packet.session_id, packet.routing_header.hops, packet.routing_header.hop_index
+    )
+}
+
+/// UI seconds between two summaries of the suppressed events
+pub const SUMMARY_INTERVAL: f64 = 5.0;
+
+/// How many times an event without its own entry was received, and the nodes
+/// it was about
+#[derive(Debug, Clone, Default, PartialEq)]
+struct SuppressedCount {
+    count: u32,
+    nodes: BTreeSet<NodeId>,
+}
+
+impl SuppressedCount {
+    fn add(&mut self, nodes: impl IntoIterator<Item = NodeId>) {
+        self.count += 1;
+        self.nodes.extend(nodes);
+    }
+
+    fn take_entry(
+        &mut self,
+        time: f64,
+        event_type: LogEventType,
+        what: &str,
+        interval: f64,
+    ) -> Option<LogEntry> {
+        if self.count == 0 {
+            return None;
+        }
+        let Self { count, nodes } = std::mem::take(self);
+        Some(LogEntry {
+            time,
+            event_type,
+            severity: Severity::Info,
+            nodes: nodes.into_iter().collect(),
+            text: format!("{count} {what} in the last {interval:.1}s"),
+        })
+    }
+}
+
+/// The events `LogEntry::from_event` skips, logged as one entry per kind every
+/// `SUMMARY_INTERVAL`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SuppressedEvents {
+    // UI time of the last summary
+    since: f64,
+    packets_sent: SuppressedCount,
+    traffic_counts: SuppressedCount,
+    battery_levels: SuppressedCount,
+    positions: SuppressedCount,
+}
+
+impl SuppressedEvents {
+    /// Counts `event` if it has no entry of its own
+    pub fn count(&mut self, event: &ForwardedEvent) {
+        match event {
+            ForwardedEvent::PacketSent(packet, _) => self.packets_sent.add(packet_nodes(packet)),
+            ForwardedEvent::TrafficCounted(traffic) => self.traffic_counts.add(
+                traffic
+                    .drones
+                    .keys()
+                    .chain(traffic.endpoints.keys())
+                    .copied(),
+            ),
+            ForwardedEvent::BatteryLevels(batteries) => {
+                self.battery_levels.add(batteries.keys().copied());
+            }
+            ForwardedEvent::NodesMoved(positions) => self.positions.add(positions.keys().copied()),
+            _ => {}
+        }
+    }
+
+    /// The summary entries, once `SUMMARY_INTERVAL` passed since the last ones
+    pub fn take_entries(&mut self, now: f64) -> Vec<LogEntry> {
+        let interval = now - self.since;
+        if interval < SUMMARY_INTERVAL {
+            return Vec::new();
+        }
+        self.since = now;
+        [
+            self.packets_sent.take_entry(
+                now,
+                LogEventType::PacketSent,
+                "packets other than NACKs sent",
+                interval,
+            ),
+            self.traffic_counts.take_entry(
+                now,
+                LogEventType::PacketSent,
+                "traffic counter updates",
+                interval,
+            ),
+            self.battery_levels.take_entry(
+                now,
+                LogEventType::Battery,
+                "battery level updates",
+                interval,
+            ),
+            self.positions.take_entry(
+                now,
+                LogEventType::Movement,
+                "position updates",
+                interval,
+            ),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+}
+
+/// What the event log panel shows
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFilter {
+    pub node: Option<NodeId>,
+    pub event_type: Option<LogEventType>,
+    pub min_severity: Severity,
+    pub search: String,
+}
+
+impl Default for LogFilter {
+    fn default() -> Self {
+        Self {
+            node: None,
+            event_type: None,
+            min_severity: Severity::Info,
+            search: String::new(),
+        }
+    }
+}
+
+impl LogFilter {
+    #[must_use]
+    pub fn matches(&self, entry: &LogEntry) -> bool {
+        self.node.is_none_or(|node| entry.nodes.contains(&node))
+            && self
+                .event_type
+                .is_none_or(|event_type| entry.event_type == event_type)
+            && entry.severity >= self.min_severity
+            && (self.search.is_empty()
+                || entry
+                    .text
+                    .to_lowercase()
+                    .contains(&self.search.to_lowercase()))
+    }
+}
+
+/// Entries kept by the event log, the oldest are dropped first
+pub const MAX_LOG_ENTRIES: usize = 10_000;
+
+/// The latest entries logged, along with the ones matching the last filter
+#[derive(Debug)]
+pub struct EventLog {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    // Entries dropped from the front since the simulation started
+    evicted: usize,
+    suppressed: SuppressedEvents,
+    // Last filter applied and the positions, counted from the first entry ever
+    // logged, of the entries matching it
+    cache: Option<(LogFilter, VecDeque<usize>)>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::with_capacity(MAX_LOG_ENTRIES)
+    }
+}
+
+impl EventLog {
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            evicted: 0,
+            suppressed: SuppressedEvents::default(),
+            cache: None,
+        }
+    }
+
+    /// Logs an event, or counts it for the next summary if it is too frequent
+    /// to get an entry of its own
+    pub fn log_event(&mut self, time: f64, event: &ForwardedEvent) {
+        match LogEntry::from_event(time, event) {
+            Some(entry) => self.push(entry),
+            None => self.suppressed.count(event),
+        }
+    }
+
+    /// Logs the summaries of the suppressed events when they are due
+    pub fn log_summaries(&mut self, now: f64) {
+        for entry in self.suppressed.take_entries(now) {
+            self.push(entry);
+        }
+    }
+
+    /// Adds an entry, dropping the oldest one if the log is full
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+            self.evicted += 1;
+            if let Some((_, matching)) = &mut self.cache {
+                while matching
+                    .front()
+                    .is_some_and(|position| *position < self.evicted)
+                {
+                    matching.pop_front();
+                }
+            }
+        }
+        if let Some((filter, matching)) = &mut self.cache {
+            if filter.matches(&entry) {
+                matching.push_back(self.evicted + self.entries.len());
+            }
+        }
+        self.entries.push_back(entry);
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Says how many entries were dropped, `None` if the log never filled up
+    #[must_use]
+    pub fn eviction_note(&self) -> Option<String> {
+        (self.evicted > 0).then(|| {
+            format!(
+                "{} older entries dropped, only the last {} are kept",
+                self.evicted, self.capacity
+            )
+        })
+    }
+
+    /// Makes `filter` the one used by `matching_entry` and returns how many
+    /// entries match it. The entries are only checked again if the filter changed
+    pub fn apply_filter(&mut self, filter: &LogFilter) -> usize {
+        if self
+            .cache
+            .as_ref()
+            .is_none_or(|(cached, _)| cached != filter)
+        {
+            let matching = self
+                .entries
+                .iter()
+                .enumerate()
+                .filter(|(_, entry)| filter.matches(entry))
+                .map(|(index, _)| self.evicted + index)
+                .collect();
+            self.cache = Some((filter.clone(), matching));
+        }
+        self.cache
+            .as_ref()
+            .map_or(0, |(_, matching)| matching.len())
+    }
+
+    /// The `row`-th entry matching the last filter applied
+    #[must_use]
+    pub fn matching_entry(&self, row: usize) -> Option<&LogEntry> {
+        let (_, matching) = self.cache.as_ref()?;
+        self.entries.get(matching.get(row)? - self.evicted)
+    }
+
+    /// Indexes of the entries matching `filter`
+    #[cfg(test)]
+    pub fn filtered(&mut self, filter: &LogFilter) -> Vec<usize> {
+        self.apply_filter(filter);
+        self.cache.as_ref().map_or_else(Vec::new, |(_, matching)| {
+            matching
+                .iter()
+                .map(|position| position - self.evicted)
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn entry(time: f64, event: &ForwardedEvent) -> LogEntry {
+        LogEntry::from_event(time, event).expect("The event should be logged")
+    }
+
+    fn log() -> EventLog {
+        let mut log = EventLog::default();
+        log.push(entry(1.0, &ForwardedEvent::PDRSet(1, 0.5)));
+        log.push(entry(2.0, &ForwardedEvent::DroneCrashed(2)));
+        log.push(entry(3.0, &ForwardedEvent::ConnectionAdded(1, 3)));
+        log.push(LogEntry::from_response(
+            4.0,
+            &UIResponse::Falure("Drone 4 does not exist in the network".to_string()),
+        ));
+        log
+    }
+
+    #[test]
+    fn test_filter_by_node() {
+        let filter = LogFilter {
+            node: Some(1),
+            ..LogFilter::default()
+        };
+        assert_eq!(log().filtered(&filter), vec![0, 2]);
+    }
+
+    #[test]
+    fn test_filter_by_type_and_severity() {
+        let filter = LogFilter {
+            event_type: Some(LogEventType::Crash),
+            ..LogFilter::default()
+        };
+        assert_eq!(log().filtered(&filter), vec![1]);
+
+        let filter = LogFilter {
+            min_severity: Severity::Warning,
+            ..LogFilter::default()
+        };
+        assert_eq!(log().filtered(&filter), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_filter_by_text() {
+        let filter = LogFilter {
+            search: "DOES NOT EXIST".to_string(),
+            ..LogFilter::default()
+        };
+        assert_eq!(log().filtered(&filter), vec![3]);
+    }
+
+    #[test]
+    fn test_oldest_entries_dropped() {
+        let mut log = EventLog::with_capacity(3);
+        let filter = LogFilter {
+            node: Some(1),
+            ..LogFilter::default()
+        };
+        for node_id in [1, 2, 1] {
+            log.push(entry(0.0, &ForwardedEvent::DroneCrashed(node_id)));
+        }
+        assert_eq!(log.filtered(&filter), vec![0, 2]);
+
+        // The cached result follows the log without checking it again
+        log.push(entry(1.0, &ForwardedEvent::DroneCrashed(1)));
+        log.push(entry(2.0, &ForwardedEvent::DroneCrashed(3)));
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.apply_filter(&filter), 2);
+        assert_eq!(log.filtered(&filter), vec![0, 1]);
+        assert_eq!(log.matching_entry(1).map(|entry| entry.time), Some(1.0));
+    }
+
+    #[test]
+    fn test_suppressed_events_summarised() {
+        let mut log = EventLog::default();
+        for second in 0..3 {
+            let time = f64::from(second);
+            log.log_event(
+                time,
+                &ForwardedEvent::NodesMoved(HashMap::from([(1, [0.0, 0.0]), (2, [1.0, 1.0])])),
+            );
+            log.log_event(time, &ForwardedEvent::NodesMoved(HashMap::from([(3, [0.0, 0.0])])));
+            log.log_summaries(time);
+        }
+        assert!(log.is_empty());
+
+        log.log_summaries(SUMMARY_INTERVAL);
+        let filter = LogFilter {
+            event_type: Some(LogEventType::Movement),
+            node: Some(3),
+            ..LogFilter::default()
+        };
+        assert_eq!(log.filtered(&filter), vec![0]);
+        assert_eq!(
+            log.matching_entry(0).map(|entry| entry.text.as_str()),
+            Some("6 position updates in the last 5.0s")
+        );
+
+        // Nothing new to summarise
+        log.log_summaries(2.0 * SUMMARY_INTERVAL);
+        assert_eq!(log.len(), 1);
+    }
+
+    #[test]
+    fn test_eviction_note() {
+        let mut log = EventLog::with_capacity(2);
+        log.push(entry(0.0, &ForwardedEvent::DroneCrashed(1)));
+        log.push(entry(1.0, &ForwardedEvent::DroneCrashed(2)));
+        assert_eq!(log.eviction_note(), None);
+
+        log.push(entry(2.0, &ForwardedEvent::DroneCrashed(3)));
+        assert_eq!(
+            log.eviction_note().as_deref(),
+            Some("1 older entries dropped, only the last 2 are kept")
+        );
+    }
+}
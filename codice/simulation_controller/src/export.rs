@@ -0,0 +1,21 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Writes one line per entry to `path`, replacing the file if it exists
+///
+/// # Errors
+/// Returns the reason the file couldn't be written
+pub fn export_lines<I>(path: &str, lines: I) -> Result<(), String>
+where
+    I: IntoIterator,
+    I::Item: std::fmt::Display,
+{
+    let file = File::create(path).map_err(|e| format!("Failed to create {path}: {e}"))?;
+    let mut writer = BufWriter::new(file);
+    for line in lines {
+        writeln!(writer, "{line}").map_err(|e| format!("Failed to write {path}: {e}"))?;
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write {path}: {e}"))
+}
@@ -1,12 +1,55 @@
+use crate::battery::Battery;
+use crate::mobility::Point;
+use crate::pdr_profile::PdrProfile;
+use crate::topology_analysis::{ImpactPreview, TopologyAnalysis};
+use crate::traffic::TrafficCounters;
+use crate::view_diff::ViewDiff;
+use common::introspection::DiscoveredView;
+use std::collections::HashMap;
+use std::time::Instant;
 use wg_2024::network::NodeId;
 use wg_2024::packet::Packet;
 
+/// Most events waiting for the UI. When the channel is full the packet events
+/// are skipped, the snapshots wait to be sent with their latest value and the
+/// other events queue up in the controller, which never waits for the UI.
+pub const FORWARDED_EVENT_CAPACITY: usize = 10_000;
+
 #[derive(Debug, Clone)]
 pub enum ForwardedEvent {
-    PacketSent(Packet),
-    PacketDropped(Packet),
+    // Packet events carry the time the controller received them. They feed the
+    // animations, the alerts and the packet history, the counters come with
+    // `TrafficCounted`.
+    PacketSent(Packet, Instant),
+    PacketDropped(Packet, Instant),
+    // Packets counted since the previous counters
+    TrafficCounted(TrafficCounters),
+    ShortcutDelivered(Packet, Instant),
     PDRSet(NodeId, f32),
+    PDRProfileSet(NodeId, Option<PdrProfile>),
     DroneCrashed(NodeId),
+    // Charge of every drone with a battery
+    BatteryLevels(HashMap<NodeId, Battery>),
+    // A drone ran out of battery, with the reason it could not be crashed
+    BatteryDepleted(NodeId, Result<(), String>),
     ConnectionAdded(NodeId, NodeId),
     ConnectionRemoved(NodeId, NodeId),
-} 
\ No newline at end of file
+    // Simulated positions of the mobile nodes
+    NodesMoved(HashMap<NodeId, Point>),
+    TopologyAnalysed(TopologyAnalysis),
+    ImpactPreviewed(ImpactPreview),
+    ViewDiscovered(DiscoveredView, ViewDiff),
+}
+
+impl ForwardedEvent {
+    /// Whether the event can be skipped when the UI falls behind
+    #[must_use]
+    pub fn is_droppable(&self) -> bool {
+        matches!(
+            self,
+            ForwardedEvent::PacketSent(..)
+                | ForwardedEvent::PacketDropped(..)
+                | ForwardedEvent::ShortcutDelivered(..)
+        )
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Packet, PacketType};
+
+/// Upper bounds of the histogram buckets, the last bucket has no upper bound
+pub const HISTOGRAM_BOUNDS: [Duration; 6] = [
+    Duration::from_micros(100),
+    Duration::from_micros(500),
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+];
+/// Packets not seen again for this long are forgotten
+const PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Distribution of a set of latencies
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    pub buckets: [u32; HISTOGRAM_BOUNDS.len() + 1],
+    pub count: u32,
+    pub total: Duration,
+    pub max: Duration,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency: Duration) {
+        let bucket = HISTOGRAM_BOUNDS
+            .iter()
+            .position(|bound| latency < *bound)
+            .unwrap_or(HISTOGRAM_BOUNDS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.total += latency;
+        self.max = self.max.max(latency);
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        for (bucket, count) in self.buckets.iter_mut().zip(other.buckets) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.total += other.total;
+        self.max = self.max.max(other.max);
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.total / self.count)
+    }
+
+    /// Range of latencies counted by the bucket at `index`
+    #[must_use]
+    pub fn bucket_label(index: usize) -> String {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        match index {
+            0 => format!("< {} ms", ms(HISTOGRAM_BOUNDS[0])),
+            index if index < HISTOGRAM_BOUNDS.len() => format!(
+                "{}-{} ms",
+                ms(HISTOGRAM_BOUNDS[index - 1]),
+                ms(HISTOGRAM_BOUNDS[index])
+            ),
+            _ => format!(">= {} ms", ms(HISTOGRAM_BOUNDS[HISTOGRAM_BOUNDS.len() - 1])),
+        }
+    }
+}
+
+// A packet handed to the node at a hop index: source, session, fragment index,
+// packet kind and hop index
+type HopKey = (NodeId, u64, u64, u8, usize);
+// A fragment: source, session and fragment index
+type FragmentKey = (NodeId, u64, u64);
+
+/// Latencies measured on the times the controller received the events of each
+/// packet. The forwarding latency of a drone is the time between the event of the
+/// node handing it a packet and its own event for the same packet, so it can only
+/// be measured when that node is a drone too. The end-to-end latency of a fragment
+/// goes from its first drone forwarding it to its last drone delivering it.
+#[derive(Debug, Default)]
+pub struct LatencyTracker {
+    arrivals: HashMap<HopKey, Instant>,
+    fragment_starts: HashMap<FragmentKey, Instant>,
+    per_drone: HashMap<NodeId, LatencyHistogram>,
+    // End-to-end fragment latency by source and session
+    per_session: BTreeMap<(NodeId, u64), LatencyHistogram>,
+    last_prune: Option<Instant>,
+}
+
+impl LatencyTracker {
+    /// Records a drone forwarding the packet to the node at its hop index
+    pub fn packet_sent(&mut self, packet: &Packet, received_at: Instant) {
+        let Some(key) = hop_key(packet) else {
+            return;
+        };
+        let hops = &packet.routing_header.hops;
+        let hop_index = key.4;
+
+        let previous = (key.0, key.1, key.2, key.3, hop_index - 1);
+        if let Some(arrival) = self.arrivals.remove(&previous) {
+            self.per_drone
+                .entry(hops[hop_index - 1])
+                .or_default()
+                .record(received_at.saturating_duration_since(arrival));
+        }
+        self.arrivals.insert(key, received_at);
+
+        if let PacketType::MsgFragment(ref fragment) = packet.pack_type {
+            let fragment_key = (key.0, packet.session_id, fragment.fragment_index);
+            if hop_index == 2 {
+                self.fragment_starts.insert(fragment_key, received_at);
+            }
+            if hop_index + 1 == hops.len() {
+                self.arrivals.remove(&key);
+                if let Some(start) = self.fragment_starts.remove(&fragment_key) {
+                    self.per_session
+                        .entry((key.0, packet.session_id))
+                        .or_default()
+                        .record(received_at.saturating_duration_since(start));
+                }
+            }
+        }
+
+        self.prune(received_at);
+    }
+
+    /// Forgets a packet dropped by the drone at its hop index
+    pub fn packet_dropped(&mut self, packet: &Packet) {
+        let Some(key) = hop_key(packet) else {
+            return;
+        };
+        self.arrivals.remove(&key);
+        if let PacketType::MsgFragment(ref fragment) = packet.pack_type {
+            self.fragment_starts
+                .remove(&(key.0, packet.session_id, fragment.fragment_index));
+        }
+    }
+
+    /// Forwarding latency of every drone
+    #[must_use]
+    pub fn per_drone(&self) -> &HashMap<NodeId, LatencyHistogram> {
+        &self.per_drone
+    }
+
+    /// End-to-end fragment latency of every session, by source and session id
+    #[must_use]
+    pub fn per_session(&self) -> &BTreeMap<(NodeId, u64), LatencyHistogram> {
+        &self.per_session
+    }
+
+    fn prune(&mut self, now: Instant) {
+        if self
+            .last_prune
+            .is_some_and(|last_prune| now.saturating_duration_since(last_prune) < PRUNE_INTERVAL)
+        {
+            return;
+        }
+        self.last_prune = Some(now);
+        let fresh = |time: &Instant| now.saturating_duration_since(*time) < PENDING_TIMEOUT;
+        self.arrivals.retain(|_, time| fresh(time));
+        self.fragment_starts.retain(|_, time| fresh(time));
+    }
+}
+
+/// The hop of a source routed packet, `None` for flood requests and invalid headers
+fn hop_key(packet: &Packet) -> Option<HopKey> {
+    let kind = match packet.pack_type {
+        PacketType::MsgFragment(_) => 0,
+        PacketType::Ack(_) => 1,
+        PacketType::Nack(_) => 2,
+        PacketType::FloodResponse(_) => 3,
+        PacketType::FloodRequest(_) => return None,
+    };
+    let hops = &packet.routing_header.hops;
+    let hop_index = packet.routing_header.hop_index;
+    if hop_index == 0 || hop_index >= hops.len() {
+        return None;
+    }
+    Some((
+        hops[0],
+        packet.session_id,
+        packet.get_fragment_index(),
+        kind,
+        hop_index,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::{Fragment, FRAGMENT_DSIZE};
+
+    fn fragment(hop_index: usize) -> Packet {
+        Packet::new_fragment(
+            SourceRoutingHeader::new(vec![5, 1, 2, 3, 7], hop_index),
+            4,
+            Fragment::new(0, 1, [0; FRAGMENT_DSIZE]),
+        )
+    }
+
+    #[test]
+    fn test_hop_and_end_to_end_latency() {
+        let start = Instant::now();
+        let mut tracker = LatencyTracker::default();
+        tracker.packet_sent(&fragment(2), start);
+        tracker.packet_sent(&fragment(3), start + Duration::from_millis(2));
+        tracker.packet_sent(&fragment(4), start + Duration::from_millis(9));
+
+        // Drone 1 gets the packet from the client, which does not notify the controller
+        assert!(!tracker.per_drone().contains_key(&1));
+        assert_eq!(tracker.per_drone()[&2].max, Duration::from_millis(2));
+        assert_eq!(tracker.per_drone()[&3].max, Duration::from_millis(7));
+        assert_eq!(tracker.per_drone()[&3].buckets[4], 1);
+        assert_eq!(
+            tracker.per_session()[&(5, 4)].mean(),
+            Some(Duration::from_millis(9))
+        );
+    }
+
+    #[test]
+    fn test_dropped_packets_are_not_measured() {
+        let start = Instant::now();
+        let mut tracker = LatencyTracker::default();
+        tracker.packet_sent(&fragment(2), start);
+        tracker.packet_dropped(&fragment(2));
+        tracker.packet_sent(&fragment(3), start + Duration::from_millis(2));
+        tracker.packet_sent(&fragment(4), start + Duration::from_millis(3));
+
+        assert!(!tracker.per_drone().contains_key(&2));
+        assert_eq!(tracker.per_drone()[&3].count, 1);
+        assert!(tracker.per_session().is_empty());
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = LatencyHistogram::default();
+        assert_eq!(histogram.mean(), None);
+        histogram.record(Duration::from_micros(50));
+        histogram.record(Duration::from_millis(100));
+        assert_eq!(histogram.buckets, [1, 0, 0, 0, 0, 0, 1]);
+
+        let mut merged = LatencyHistogram::default();
+        merged.merge(&histogram);
+        merged.merge(&histogram);
+        assert_eq!(merged.count, 4);
+        assert_eq!(merged.max, Duration::from_millis(100));
+        assert_eq!(LatencyHistogram::bucket_label(0), "< 0.1 ms");
+    }
+}
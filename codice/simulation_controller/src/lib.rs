@@ -1,10 +1,22 @@
+pub mod alerts;
+pub mod battery;
 pub mod colored_data;
+pub mod event_batch;
+pub mod event_log;
+pub mod export;
 pub mod forwarded_event;
+pub mod latency;
+pub mod mobility;
 pub mod network_graph;
 pub mod node_stats;
 pub mod packet_animation;
+pub mod packet_crafting;
+pub mod pdr_profile;
 pub mod simulation_controller;
 pub mod simulation_controller_ui;
+pub mod topology_analysis;
+pub mod traffic;
 pub mod ui_commands;
+pub mod view_diff;
 pub use simulation_controller::SimulationController;
 pub use simulation_controller_ui::SimulationControllerUI;
@@ -0,0 +1,297 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+use wg_2024::network::NodeId;
+
+/// How often the controller moves the nodes and updates the links
+pub const MOBILITY_TICK: Duration = Duration::from_millis(200);
+
+/// Position or velocity on the simulated plane
+pub type Point = [f32; 2];
+// Two nodes, the lower id first
+type Link = (NodeId, NodeId);
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct MobilitySettings {
+    /// Nodes closer than this can be linked, nodes further apart can't
+    pub range: f32,
+    /// Width and height of the area the nodes move in
+    pub area: Point,
+}
+
+impl MobilitySettings {
+    /// Checks that the range and the area are positive
+    ///
+    /// # Errors
+    /// Returns a description of the first invalid parameter
+    pub fn validate(&self) -> Result<(), String> {
+        if self.range <= 0.0 {
+            return Err("Radio range must be positive".to_string());
+        }
+        if self.area.iter().any(|side| *side <= 0.0) {
+            return Err("Mobility area must have a positive size".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// How a node picks where to go. Nodes without a model keep their velocity,
+/// bouncing off the borders of the area.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MobilityModel {
+    /// Moves to random points of the area, waiting `pause` seconds at each
+    RandomWaypoint { speed: f32, pause: f32 },
+    /// Goes through the waypoints in order, starting over after the last
+    Path { waypoints: Vec<Point>, speed: f32 },
+}
+
+impl MobilityModel {
+    /// Checks that the speed is positive and a path has waypoints
+    ///
+    /// # Errors
+    /// Returns a description of the first invalid parameter
+    pub fn validate(&self) -> Result<(), String> {
+        let speed = match self {
+            MobilityModel::RandomWaypoint { speed, pause } => {
+                if *pause < 0.0 {
+                    return Err("Random waypoint pause can't be negative".to_string());
+                }
+                speed
+            }
+            MobilityModel::Path { waypoints, speed } => {
+                if waypoints.is_empty() {
+                    return Err("Path needs at least one waypoint".to_string());
+                }
+                speed
+            }
+        };
+        if *speed <= 0.0 {
+            return Err("Mobility speed must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A node with a simulated position
+#[derive(Debug, Clone, PartialEq)]
+pub struct MovingNode {
+    pub position: Point,
+    pub velocity: Point,
+    model: Option<MobilityModel>,
+    // Point the model is moving towards
+    target: Option<Point>,
+    next_waypoint: usize,
+    // Seconds left to wait at the last random waypoint
+    pause_left: f32,
+}
+
+impl MovingNode {
+    #[must_use]
+    pub fn new(position: Point, velocity: Point, model: Option<MobilityModel>) -> Self {
+        Self {
+            position,
+            velocity,
+            model,
+            target: None,
+            next_waypoint: 0,
+            pause_left: 0.0,
+        }
+    }
+
+    fn step(&mut self, elapsed: f32, area: Point, rng: &mut impl Rng) {
+        let speed = match &self.model {
+            None => {
+                for ((position, velocity), side) in
+                    self.position.iter_mut().zip(&mut self.velocity).zip(area)
+                {
+                    *position += *velocity * elapsed;
+                    if *position < 0.0 || *position > side {
+                        *velocity = -*velocity;
+                        *position = position.clamp(0.0, side);
+                    }
+                }
+                return;
+            }
+            Some(MobilityModel::RandomWaypoint { speed, .. }) => {
+                if self.pause_left > 0.0 {
+                    self.pause_left -= elapsed;
+                    self.velocity = [0.0, 0.0];
+                    return;
+                }
+                if self.target.is_none() {
+                    self.target =
+                        Some([rng.gen_range(0.0..=area[0]), rng.gen_range(0.0..=area[1])]);
+                }
+                *speed
+            }
+            Some(MobilityModel::Path { waypoints, speed }) => {
+                if self.target.is_none() {
+                    self.target = Some(waypoints[self.next_waypoint % waypoints.len()]);
+                }
+                *speed
+            }
+        };
+        let Some(target) = self.target else {
+            return;
+        };
+
+        let offset = [target[0] - self.position[0], target[1] - self.position[1]];
+        let distance = offset[0].hypot(offset[1]);
+        if distance <= speed * elapsed {
+            self.position = target;
+            self.velocity = [0.0, 0.0];
+            self.target = None;
+            match &self.model {
+                Some(MobilityModel::RandomWaypoint { pause, .. }) => self.pause_left = *pause,
+                Some(MobilityModel::Path { .. }) => self.next_waypoint += 1,
+                None => {}
+            }
+        } else {
+            self.velocity = [offset[0] / distance * speed, offset[1] / distance * speed];
+            self.position[0] += self.velocity[0] * elapsed;
+            self.position[1] += self.velocity[1] * elapsed;
+        }
+    }
+}
+
+/// Moves the nodes with a position and finds the links that radio range adds or breaks
+#[derive(Debug, Clone)]
+pub struct Mobility {
+    settings: MobilitySettings,
+    nodes: HashMap<NodeId, MovingNode>,
+}
+
+impl Mobility {
+    #[must_use]
+    pub fn new(settings: MobilitySettings, nodes: HashMap<NodeId, MovingNode>) -> Self {
+        Self { settings, nodes }
+    }
+
+    /// Moves every node by the time elapsed since the last step
+    pub fn step(&mut self, elapsed: Duration, rng: &mut impl Rng) {
+        for node in self.nodes.values_mut() {
+            node.step(elapsed.as_secs_f32(), self.settings.area, rng);
+        }
+    }
+
+    /// Stops simulating a node, for example a crashed drone
+    pub fn remove_node(&mut self, node_id: NodeId) {
+        self.nodes.remove(&node_id);
+    }
+
+    #[must_use]
+    pub fn positions(&self) -> HashMap<NodeId, Point> {
+        self.nodes
+            .iter()
+            .map(|(node_id, node)| (*node_id, node.position))
+            .collect()
+    }
+
+    /// Links between nodes that came within range and links between nodes that left it.
+    /// Nodes without a position are never linked or unlinked by range.
+    #[must_use]
+    pub fn link_changes(
+        &self,
+        topology: &HashMap<NodeId, HashSet<NodeId>>,
+    ) -> (Vec<Link>, Vec<Link>) {
+        let mut node_ids = self.nodes.keys().copied().collect::<Vec<_>>();
+        node_ids.sort_unstable();
+
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        for (i, node1) in node_ids.iter().enumerate() {
+            for node2 in &node_ids[i + 1..] {
+                let [x1, y1] = self.nodes[node1].position;
+                let [x2, y2] = self.nodes[node2].position;
+                let in_range = (x1 - x2).hypot(y1 - y2) <= self.settings.range;
+                let linked = topology
+                    .get(node1)
+                    .is_some_and(|neighbours| neighbours.contains(node2));
+                if in_range && !linked {
+                    added.push((*node1, *node2));
+                } else if !in_range && linked {
+                    removed.push((*node1, *node2));
+                }
+            }
+        }
+        (added, removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SETTINGS: MobilitySettings = MobilitySettings {
+        range: 10.0,
+        area: [100.0, 100.0],
+    };
+
+    #[test]
+    fn test_velocity_bounces_off_borders() {
+        let mut node = MovingNode::new([95.0, 50.0], [10.0, 0.0], None);
+        node.step(1.0, SETTINGS.area, &mut rand::thread_rng());
+        assert_eq!(node.position, [100.0, 50.0]);
+        assert_eq!(node.velocity, [-10.0, 0.0]);
+        node.step(1.0, SETTINGS.area, &mut rand::thread_rng());
+        assert_eq!(node.position, [90.0, 50.0]);
+    }
+
+    #[test]
+    fn test_path_loops_through_waypoints() {
+        let model = MobilityModel::Path {
+            waypoints: vec![[10.0, 0.0], [0.0, 0.0]],
+            speed: 5.0,
+        };
+        let mut node = MovingNode::new([0.0, 0.0], [0.0, 0.0], Some(model));
+        let mut rng = rand::thread_rng();
+        node.step(1.0, SETTINGS.area, &mut rng);
+        assert_eq!(node.position, [5.0, 0.0]);
+        node.step(1.0, SETTINGS.area, &mut rng);
+        assert_eq!(node.position, [10.0, 0.0]);
+        node.step(2.0, SETTINGS.area, &mut rng);
+        assert_eq!(node.position, [0.0, 0.0]);
+        node.step(1.0, SETTINGS.area, &mut rng);
+        assert_eq!(node.position, [5.0, 0.0]);
+    }
+
+    #[test]
+    fn test_random_waypoint_stays_in_area() {
+        let model = MobilityModel::RandomWaypoint {
+            speed: 30.0,
+            pause: 0.5,
+        };
+        assert!(model.validate().is_ok());
+        let mut node = MovingNode::new([50.0, 50.0], [0.0, 0.0], Some(model));
+        let mut rng = rand::thread_rng();
+        for _ in 0..200 {
+            node.step(0.2, SETTINGS.area, &mut rng);
+            assert!(node
+                .position
+                .iter()
+                .all(|axis| (0.0..=100.0).contains(axis)));
+        }
+    }
+
+    #[test]
+    fn test_link_changes() {
+        let nodes = HashMap::from([
+            (1, MovingNode::new([0.0, 0.0], [0.0, 0.0], None)),
+            (2, MovingNode::new([5.0, 0.0], [0.0, 0.0], None)),
+            (3, MovingNode::new([50.0, 0.0], [0.0, 0.0], None)),
+        ]);
+        let mobility = Mobility::new(SETTINGS, nodes);
+        let topology = HashMap::from([
+            (1, HashSet::from([3, 4])),
+            (2, HashSet::new()),
+            (3, HashSet::from([1])),
+            (4, HashSet::from([1])),
+        ]);
+        let (added, removed) = mobility.link_changes(&topology);
+        assert_eq!(added, vec![(1, 2)]);
+        // Node 4 has no position, so its link is left alone
+        assert_eq!(removed, vec![(1, 3)]);
+    }
+}
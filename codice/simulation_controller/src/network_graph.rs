@@ -1,4 +1,4 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use egui_graphs::{
     DefaultEdgeShape, Graph, Metadata, SettingsInteraction, SettingsNavigation, SettingsStyle,
@@ -13,21 +13,49 @@ use wg_2024::network::NodeId;
 
 use crate::{
     colored_data::{self, ColoredNode, NodeData},
+    mobility::Point,
+    node_stats::DroneStats,
     packet_animation::{AnimationType, PacketAnimation},
+    topology_analysis::ImpactAction,
+    ui_commands::UICommand,
 };
 
+/// Animation time after its last wavefront when a flood is considered over
+const FLOOD_FRONT_MAX_AGE: f64 = 10.0;
+
+/// What the context menu was opened on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContextTarget {
+    Node(NodeId),
+    Edge(NodeId, NodeId),
+}
+
 pub struct NetworkGraph {
     graph: Graph<NodeData, String, Undirected, DefaultIx, ColoredNode, DefaultEdgeShape>,
     node_indexes: HashMap<NodeId, NodeIndex>,
     edge_indexes: HashMap<(NodeId, NodeId), EdgeIndex>,
     packet_animations: HashMap<(u64, u64), VecDeque<PacketAnimation>>,
     active_animations: HashMap<(u64, u64), PacketAnimation>,
+    // Node a connection is being dragged from
+    link_start: Option<NodeId>,
+    context_target: Option<ContextTarget>,
+    context_pdr: f32,
+    // Hops of the flood request wavefronts, started as soon as they are added
+    flood_animations: Vec<PacketAnimation>,
+    // Time of the last wavefront and hops already animated, by flood id and initiator
+    flood_fronts: HashMap<(u64, NodeId), (f64, HashSet<(NodeId, NodeId)>)>,
+    hidden_animations: HashSet<AnimationType>,
+    // Animations follow their own clock so they can be slowed down or paused
+    animation_time: f64,
+    last_frame: Option<f64>,
+    animation_speed: f64,
+    animations_paused: bool,
 }
 
 impl NetworkGraph {
     #[must_use]
     pub fn new(
-        drones: Vec<NodeId>,
+        drones: Vec<(NodeId, String)>,
         clients: Vec<NodeId>,
         servers: Vec<NodeId>,
         edges: Vec<(NodeId, NodeId)>,
@@ -37,10 +65,12 @@ impl NetworkGraph {
         let mut node_indexes = HashMap::new();
         let mut edge_indexes = HashMap::new();
 
-        for drone_id in drones {
+        for (drone_id, implementation) in drones {
             let drone = NodeData {
-                label: drone_id.to_string(),
+                label: format!("{drone_id}\n{implementation}"),
                 node_type: colored_data::NodeType::Drone,
+                critical: false,
+                battery: None,
             };
             let a = g.add_node(drone);
             node_indexes.insert(drone_id, a);
@@ -49,6 +79,8 @@ impl NetworkGraph {
             let client = NodeData {
                 label: client_id.to_string(),
                 node_type: colored_data::NodeType::Client,
+                critical: false,
+                battery: None,
             };
             let b = g.add_node(client);
             node_indexes.insert(client_id, b);
@@ -57,13 +89,15 @@ impl NetworkGraph {
             let server = NodeData {
                 label: server_id.to_string(),
                 node_type: colored_data::NodeType::Server,
+                critical: false,
+                battery: None,
             };
             let c = g.add_node(server);
             node_indexes.insert(server_id, c);
         }
         for (a, b) in edges {
             let e = g.add_edge(node_indexes[&a], node_indexes[&b], 0.to_string());
-            edge_indexes.insert((a, b), e);
+            edge_indexes.insert((a.min(b), a.max(b)), e);
         }
 
         let graph = Graph::from(&g);
@@ -74,6 +108,16 @@ impl NetworkGraph {
             edge_indexes,
             packet_animations: HashMap::new(),
             active_animations: HashMap::new(),
+            link_start: None,
+            context_target: None,
+            context_pdr: 0.0,
+            flood_animations: Vec::new(),
+            flood_fronts: HashMap::new(),
+            hidden_animations: HashSet::new(),
+            animation_time: 0.0,
+            last_frame: None,
+            animation_speed: 1.0,
+            animations_paused: false,
         }
     }
 
@@ -93,10 +137,101 @@ impl NetworkGraph {
         });
     }
 
+    /// Animates the hops a flood request went through, as recorded in its path
+    /// trace. Each hop is animated once per flood.
+    pub fn add_flood_wave(&mut self, flood_id: u64, initiator: NodeId, path_trace: &[NodeId]) {
+        let (last_front, hops) = self.flood_fronts.entry((flood_id, initiator)).or_default();
+        // The initiator does not notify the controller, so its hop comes with the first drone's
+        for hop in path_trace.windows(2) {
+            if !hops.insert((hop[0], hop[1])) {
+                continue;
+            }
+            *last_front = self.animation_time;
+            self.flood_animations.push(PacketAnimation {
+                source: hop[0],
+                dest: hop[1],
+                start_time: self.animation_time,
+                anim_type: AnimationType::FloodRequest,
+            });
+        }
+    }
+
+    /// Duration of an animation, proportional to the length of the link
+    fn animation_duration(&self, animation: &PacketAnimation) -> Option<f64> {
+        let source = self
+            .graph
+            .node(*self.node_indexes.get(&animation.source)?)?;
+        let dest = self.graph.node(*self.node_indexes.get(&animation.dest)?)?;
+        Some(f64::from(source.location().distance(dest.location())) / 100.0)
+    }
+
+    fn animation_controls_ui(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            for animation_type in AnimationType::ALL {
+                let mut visible = !self.hidden_animations.contains(&animation_type);
+                let text =
+                    egui::RichText::new(animation_type.to_string()).color(animation_type.color());
+                if ui.checkbox(&mut visible, text).changed() {
+                    if visible {
+                        self.hidden_animations.remove(&animation_type);
+                    } else {
+                        self.hidden_animations.insert(animation_type);
+                    }
+                }
+            }
+            ui.separator();
+            ui.add(egui::Slider::new(&mut self.animation_speed, 0.1..=5.0).text("Speed"));
+            let label = if self.animations_paused {
+                "Resume"
+            } else {
+                "Pause"
+            };
+            if ui.button(label).clicked() {
+                self.animations_paused = !self.animations_paused;
+            }
+        });
+    }
+
     pub fn crash_drone(&mut self, drone_id: NodeId) {
         let drone_index = self.node_indexes[&drone_id];
         self.graph.remove_node(drone_index);
         self.node_indexes.remove(&drone_id);
+        // The edges went with the node, and their indexes will be reused by new edges
+        self.edge_indexes
+            .retain(|(node1, node2), _| *node1 != drone_id && *node2 != drone_id);
+    }
+
+    /// Highlights the given nodes as critical, clearing the others
+    pub fn set_critical_nodes(&mut self, critical_nodes: &[NodeId]) {
+        for (node_id, node_index) in &self.node_indexes {
+            if let Some(node) = self.graph.node_mut(*node_index) {
+                node.payload_mut().critical = critical_nodes.contains(node_id);
+            }
+        }
+    }
+
+    /// Shades a drone by the share of battery it has left
+    pub fn set_battery_level(&mut self, drone_id: NodeId, level: f32) {
+        if let Some(node) = self
+            .node_indexes
+            .get(&drone_id)
+            .and_then(|node_index| self.graph.node_mut(*node_index))
+        {
+            node.payload_mut().battery = Some(level);
+        }
+    }
+
+    /// Places the nodes at their simulated positions
+    pub fn set_positions(&mut self, positions: &HashMap<NodeId, Point>) {
+        for (node_id, [x, y]) in positions {
+            if let Some(node) = self
+                .node_indexes
+                .get(node_id)
+                .and_then(|node_index| self.graph.node_mut(*node_index))
+            {
+                node.set_location(egui::Pos2::new(*x, *y));
+            }
+        }
     }
 
     pub fn add_connection(&mut self, drone_id: NodeId, neighbour_id: NodeId) {
@@ -106,44 +241,79 @@ impl NetworkGraph {
         let edge = self
             .graph
             .add_edge(drone_index, neighbour_index, 0.to_string());
-        self.edge_indexes.insert((drone_id, neighbour_id), edge);
+        self.edge_indexes.insert(
+            (drone_id.min(neighbour_id), drone_id.max(neighbour_id)),
+            edge,
+        );
     }
 
     pub fn remove_connection(&mut self, drone_id: NodeId, neighbour_id: NodeId) {
         let min = drone_id.min(neighbour_id);
         let max = drone_id.max(neighbour_id);
 
-        let edge_index = self.edge_indexes[&(min, max)];
+        if let Some(edge_index) = self.edge_indexes.remove(&(min, max)) {
+            self.graph.remove_edge(edge_index);
+        }
+    }
 
-        self.graph.remove_edge(edge_index);
-        self.edge_indexes.remove(&(min, max));
+    fn node_at(&self, meta: &Metadata, screen_pos: egui::Pos2) -> Option<NodeId> {
+        let index = self.graph.node_by_screen_pos(meta, screen_pos)?;
+        self.node_indexes
+            .iter()
+            .find(|(_, node_index)| **node_index == index)
+            .map(|(node_id, _)| *node_id)
     }
 
-    pub fn show_ui(&mut self, ui: &mut egui::Ui, now: f64) {
-        let interaction_settings = &SettingsInteraction::new().with_dragging_enabled(true);
+    fn edge_at(&self, meta: &Metadata, screen_pos: egui::Pos2) -> Option<(NodeId, NodeId)> {
+        let index = self.graph.edge_by_screen_pos(meta, screen_pos)?;
+        self.edge_indexes
+            .iter()
+            .find(|(_, edge_index)| **edge_index == index)
+            .map(|(nodes, _)| *nodes)
+    }
 
-        self.active_animations.retain(|_, animation| {
-            if let (Some(&source_idx), Some(&dest_idx)) = (
-                self.node_indexes.get(&animation.source),
-                self.node_indexes.get(&animation.dest),
-            ) {
-                if let (Some(source_node), Some(dest_node)) =
-                    (self.graph.node(source_idx), self.graph.node(dest_idx))
-                {
-                    let source_pos = source_node.location();
-                    let dest_pos = dest_node.location();
-                    let distance = source_pos.distance(dest_pos) / 100.0;
-                    return now - animation.start_time < distance.into();
-                }
-            }
-            false
+    /// Shows the graph and returns the command requested by interacting with it:
+    /// shift + drag from a node to another to connect them, right click on a
+    /// link to remove it or on a drone to crash it or set its PDR
+    pub fn show_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        now: f64,
+        drone_stats: &HashMap<NodeId, DroneStats>,
+    ) -> Option<UICommand> {
+        // While linking, dragging must not move the nodes or pan the view
+        let linking = ui.input(|i| i.modifiers.shift) || self.link_start.is_some();
+        let interaction_settings = &SettingsInteraction::new().with_dragging_enabled(!linking);
+
+        self.animation_controls_ui(ui);
+
+        if !self.animations_paused {
+            let elapsed = now - self.last_frame.unwrap_or(now);
+            self.animation_time += elapsed * self.animation_speed;
+        }
+        self.last_frame = Some(now);
+        let time = self.animation_time;
+
+        let mut active_animations = std::mem::take(&mut self.active_animations);
+        active_animations.retain(|_, animation| {
+            self.animation_duration(animation)
+                .is_some_and(|duration| time - animation.start_time < duration)
+        });
+        self.active_animations = active_animations;
+        let mut flood_animations = std::mem::take(&mut self.flood_animations);
+        flood_animations.retain(|animation| {
+            self.animation_duration(animation)
+                .is_some_and(|duration| time - animation.start_time < duration)
         });
+        self.flood_animations = flood_animations;
+        self.flood_fronts
+            .retain(|_, (last_front, _)| time - *last_front < FLOOD_FRONT_MAX_AGE);
 
         for (key, animation_queue) in &mut self.packet_animations {
             if !self.active_animations.contains_key(key) {
                 let next_animation = animation_queue.pop_front();
                 if let Some(mut animation) = next_animation {
-                    animation.start_time = now;
+                    animation.start_time = time;
                     self.active_animations.insert(*key, animation);
                 }
             }
@@ -152,7 +322,7 @@ impl NetworkGraph {
         let style_settings = &SettingsStyle::new().with_labels_always(false);
         let navigation_settings = &SettingsNavigation::new()
             .with_fit_to_screen_enabled(false)
-            .with_zoom_and_pan_enabled(true);
+            .with_zoom_and_pan_enabled(!linking);
 
         let graph_view = &mut egui_graphs::GraphView::<
             NodeData,
@@ -166,16 +336,91 @@ impl NetworkGraph {
         .with_interactions(interaction_settings)
         .with_navigations(navigation_settings);
 
-        ui.add(graph_view);
+        let response = ui.add(graph_view);
 
         let painter = ui.painter();
 
         let meta = Metadata::load(ui);
 
+        let mut command = None;
+
+        if linking && response.drag_started() {
+            self.link_start = response
+                .interact_pointer_pos()
+                .and_then(|pos| self.node_at(&meta, pos));
+        }
+        if let Some(start) = self.link_start {
+            let pointer = ui.input(|i| i.pointer.latest_pos());
+            if let (Some(&start_index), Some(pointer)) = (self.node_indexes.get(&start), pointer) {
+                if let Some(start_node) = self.graph.node(start_index) {
+                    painter.line_segment(
+                        [meta.canvas_to_screen_pos(start_node.location()), pointer],
+                        egui::Stroke::new(2.0, egui::Color32::WHITE),
+                    );
+                }
+            }
+            if response.drag_stopped() {
+                let end = pointer.and_then(|pos| self.node_at(&meta, pos));
+                if let Some(end) = end.filter(|end| *end != start) {
+                    command = Some(UICommand::AddConnection(start, end));
+                }
+                self.link_start = None;
+            }
+        }
+
+        if response.secondary_clicked() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                self.context_target = match self.node_at(&meta, pos) {
+                    Some(node_id) => Some(ContextTarget::Node(node_id)),
+                    None => self
+                        .edge_at(&meta, pos)
+                        .map(|(node1, node2)| ContextTarget::Edge(node1, node2)),
+                };
+            }
+            if let Some(ContextTarget::Node(node_id)) = self.context_target {
+                self.context_pdr = drone_stats.get(&node_id).map_or(0.0, |stats| stats.pdr);
+            }
+        }
+
+        if let Some(target) = self.context_target {
+            let context_pdr = &mut self.context_pdr;
+            response.context_menu(|ui| match target {
+                ContextTarget::Node(node_id) if drone_stats.contains_key(&node_id) => {
+                    ui.label(format!("Drone {node_id}"));
+                    if ui.button("Crash").clicked() {
+                        command = Some(UICommand::PreviewImpact(ImpactAction::Crash(node_id)));
+                        ui.close_menu();
+                    }
+                    ui.add(egui::Slider::new(context_pdr, 0.0..=1.0).text("PDR"));
+                    if ui.button("Set PDR").clicked() {
+                        command = Some(UICommand::SetPDR(node_id, *context_pdr));
+                        ui.close_menu();
+                    }
+                }
+                ContextTarget::Node(node_id) => {
+                    ui.label(format!("Node {node_id} is not a drone"));
+                }
+                ContextTarget::Edge(node1, node2) => {
+                    ui.label(format!("Link {node1} - {node2}"));
+                    if ui.button("Remove").clicked() {
+                        command = Some(UICommand::PreviewImpact(ImpactAction::RemoveConnection(
+                            node1, node2,
+                        )));
+                        ui.close_menu();
+                    }
+                }
+            });
+        }
+
         let to_screen =
             |canvas_pos: egui::Pos2| (canvas_pos.to_vec2() * meta.zoom + meta.pan).to_pos2();
 
-        for anim in self.active_animations.values() {
+        for anim in self
+            .active_animations
+            .values()
+            .chain(&self.flood_animations)
+            .filter(|anim| !self.hidden_animations.contains(&anim.anim_type))
+        {
             if let (Some(&source_idx), Some(&dest_idx)) = (
                 self.node_indexes.get(&anim.source),
                 self.node_indexes.get(&anim.dest),
@@ -186,7 +431,7 @@ impl NetworkGraph {
                     let source_pos = source_node.location();
                     let dest_pos = dest_node.location();
 
-                    let progress = ((now - anim.start_time)
+                    let progress = ((time - anim.start_time)
                         / (f64::from(source_pos.distance(dest_pos)) / 100.0))
                         as f32;
                     let source_screen_pos = to_screen(source_pos);
@@ -194,17 +439,78 @@ impl NetworkGraph {
 
                     let packet_pos = source_screen_pos.lerp(dest_screen_pos, progress);
 
-                    // NEW: Select color based on the animation type
-                    let color = match anim.anim_type {
-                        AnimationType::Fragment => egui::Color32::YELLOW,
-                        AnimationType::Ack => egui::Color32::GREEN,
-                        AnimationType::Nack => egui::Color32::RED,
-                    };
-
-                    // Draw the packet with the selected color
-                    painter.circle_filled(packet_pos, 5.0 * meta.zoom, color);
+                    if anim.anim_type == AnimationType::FloodRequest {
+                        // Flood requests are drawn as the arc of a wave leaving the source
+                        let radius = source_screen_pos.distance(packet_pos);
+                        let direction = (dest_screen_pos - source_screen_pos).normalized();
+                        let normal = direction.rot90();
+                        let arc = (-4_i8..=4)
+                            .map(|step| {
+                                let angle = f32::from(step) * 0.1;
+                                source_screen_pos
+                                    + (direction * angle.cos() + normal * angle.sin()) * radius
+                            })
+                            .collect::<Vec<_>>();
+                        painter.add(egui::Shape::line(
+                            arc,
+                            egui::Stroke::new(2.0 * meta.zoom, anim.anim_type.color()),
+                        ));
+                    } else {
+                        painter.circle_filled(packet_pos, 5.0 * meta.zoom, anim.anim_type.color());
+                    }
                 }
             }
         }
+
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> NetworkGraph {
+        NetworkGraph::new(
+            vec![(1, "a".to_string()), (2, "b".to_string()), (3, "c".to_string())],
+            vec![4],
+            vec![],
+            vec![(2, 1), (2, 3), (1, 4)],
+        )
+    }
+
+    #[test]
+    fn test_crash_drone_forgets_its_edges() {
+        let mut graph = graph();
+        graph.crash_drone(2);
+        let mut edges: Vec<_> = graph.edge_indexes.keys().copied().collect();
+        edges.sort_unstable();
+        assert_eq!(edges, vec![(1, 4)]);
+
+        // The new edge may reuse the index of a removed one
+        graph.add_connection(1, 3);
+        graph.remove_connection(3, 1);
+        assert_eq!(graph.edge_indexes.len(), 1);
+        assert_eq!(graph.graph.edge_count(), 1);
+
+        // Removing a connection that isn't there does nothing
+        graph.remove_connection(2, 3);
+        assert_eq!(graph.graph.edge_count(), 1);
+    }
+
+    #[test]
+    fn test_flood_wave_follows_path_trace() {
+        let mut graph = graph();
+        graph.add_flood_wave(1, 4, &[4, 1]);
+        graph.add_flood_wave(1, 4, &[4, 1, 2]);
+        // The same hops again, from another copy of the request
+        graph.add_flood_wave(1, 4, &[4, 1, 2]);
+        let hops: Vec<_> = graph
+            .flood_animations
+            .iter()
+            .map(|animation| (animation.source, animation.dest))
+            .collect();
+        // Not towards 3, which the request never reached
+        assert_eq!(hops, vec![(4, 1), (1, 2)]);
     }
 }
@@ -1,5 +1,16 @@
-use std::collections::HashSet;
-use wg_2024::{network::NodeId, packet::Packet};
+use crate::battery::Battery;
+use crate::pdr_profile::PdrProfile;
+use crate::traffic::{DroneTraffic, EndpointTraffic};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use wg_2024::{
+    network::NodeId,
+    packet::{Packet, PacketType},
+};
+
+/// How many seconds of PDR history are kept for the chart
+pub const PDR_HISTORY_WINDOW: f64 = 60.0;
+/// How many of the last packets of each drone are kept for the packet debug view
+pub const PACKET_HISTORY_LIMIT: usize = 1_000;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DroneStats {
@@ -13,12 +24,18 @@ pub struct DroneStats {
     pub nacks_forwarded: u32,
     pub crashed: bool,
     pub pdr: f32,
-    pub packets_sent: Vec<Packet>,
+    // Last `PACKET_HISTORY_LIMIT` packets sent or dropped
+    pub packets_sent: VecDeque<Packet>,
+    pub implementation: String,
+    pub pdr_profile: Option<PdrProfile>,
+    // PDR changes as (UI time, new PDR)
+    pub pdr_history: Vec<(f64, f32)>,
+    pub battery: Option<Battery>,
 }
 
 impl DroneStats {
     #[must_use]
-    pub fn new(neigbours: HashSet<NodeId>, pdr: f32) -> Self {
+    pub fn new(neigbours: HashSet<NodeId>, pdr: f32, implementation: String) -> Self {
         Self {
             neigbours,
             packets_forwarded: 0,
@@ -30,7 +47,188 @@ impl DroneStats {
             nacks_forwarded: 0,
             crashed: false,
             pdr,
-            packets_sent: vec![],
+            packets_sent: VecDeque::new(),
+            implementation,
+            pdr_profile: None,
+            pdr_history: vec![],
+            battery: None,
+        }
+    }
+
+    /// Adds the packets counted by the controller since the previous counters
+    pub fn add_traffic(&mut self, traffic: &DroneTraffic) {
+        self.packets_forwarded += traffic.forwarded.total();
+        self.fragments_forwarded += traffic.forwarded.fragments;
+        self.acks_forwarded += traffic.forwarded.acks;
+        self.nacks_forwarded += traffic.forwarded.nacks;
+        self.flood_requests_forwarded += traffic.forwarded.flood_requests;
+        self.flood_responses_forwarded += traffic.forwarded.flood_responses;
+        self.packets_dropped += traffic.dropped;
+    }
+
+    /// Keeps the packet for the packet debug view, forgetting the oldest one when full
+    pub fn record_packet(&mut self, packet: Packet) {
+        if self.packets_sent.len() == PACKET_HISTORY_LIMIT {
+            self.packets_sent.pop_front();
+        }
+        self.packets_sent.push_back(packet);
+    }
+
+    /// Sets the PDR and keeps it in the history, forgetting what is too old for the chart
+    pub fn record_pdr(&mut self, now: f64, pdr: f32) {
+        self.pdr = pdr;
+        self.pdr_history.push((now, pdr));
+        while self.pdr_history.len() > 1 && self.pdr_history[1].0 < now - PDR_HISTORY_WINDOW {
+            self.pdr_history.remove(0);
+        }
+    }
+}
+
+/// Traffic of a client or a server, as seen from the drones next to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct EndpointStats {
+    pub neighbours: HashSet<NodeId>,
+    // Server kind, or client implementation
+    pub kind: String,
+    pub fragments_sent: u32,
+    pub acks_sent: u32,
+    pub nacks_sent: u32,
+    pub flood_requests_sent: u32,
+    pub flood_responses_sent: u32,
+    pub fragments_received: u32,
+    pub acks_received: u32,
+    pub nacks_received: u32,
+    pub flood_responses_received: u32,
+    // Packets sent by this node that a drone dropped
+    pub packets_lost: u32,
+    pub shortcuts_received: u32,
+}
+
+impl EndpointStats {
+    #[must_use]
+    pub fn new(neighbours: HashSet<NodeId>, kind: String) -> Self {
+        Self {
+            neighbours,
+            kind,
+            fragments_sent: 0,
+            acks_sent: 0,
+            nacks_sent: 0,
+            flood_requests_sent: 0,
+            flood_responses_sent: 0,
+            fragments_received: 0,
+            acks_received: 0,
+            nacks_received: 0,
+            flood_responses_received: 0,
+            packets_lost: 0,
+            shortcuts_received: 0,
         }
     }
+
+    /// Adds the packets counted by the controller since the previous counters. No
+    /// flood request is ever delivered to an endpoint, they are not counted.
+    pub fn add_traffic(&mut self, traffic: &EndpointTraffic) {
+        self.fragments_sent += traffic.sent.fragments;
+        self.acks_sent += traffic.sent.acks;
+        self.nacks_sent += traffic.sent.nacks;
+        self.flood_requests_sent += traffic.sent.flood_requests;
+        self.flood_responses_sent += traffic.sent.flood_responses;
+        self.fragments_received += traffic.received.fragments;
+        self.acks_received += traffic.received.acks;
+        self.nacks_received += traffic.received.nacks;
+        self.flood_responses_received += traffic.received.flood_responses;
+        self.packets_lost += traffic.lost;
+    }
+
+    #[must_use]
+    pub fn packets_sent(&self) -> u32 {
+        self.fragments_sent
+            + self.acks_sent
+            + self.nacks_sent
+            + self.flood_requests_sent
+            + self.flood_responses_sent
+    }
+
+    #[must_use]
+    pub fn packets_received(&self) -> u32 {
+        self.fragments_received
+            + self.acks_received
+            + self.nacks_received
+            + self.flood_responses_received
+            + self.shortcuts_received
+    }
+}
+
+/// Finds the nodes at the ends of the hop a drone just forwarded a packet on:
+/// the node it left from when this was its first hop, paired with `true`, and
+/// the node it is delivered to when this is its last hop, paired with `false`
+#[must_use]
+pub fn hop_endpoints(packet: &Packet) -> Vec<(NodeId, bool)> {
+    let mut endpoints = vec![];
+    if let PacketType::FloodRequest(flood_request) = &packet.pack_type {
+        if flood_request.path_trace.len() == 2 {
+            endpoints.push((flood_request.path_trace[0].0, true));
+        }
+        return endpoints;
+    }
+
+    let hops = &packet.routing_header.hops;
+    let hop_index = packet.routing_header.hop_index;
+    if hop_index == 2 {
+        endpoints.push((hops[0], true));
+    }
+    if hop_index > 0 && hop_index + 1 == hops.len() {
+        endpoints.push((hops[hop_index], false));
+    }
+    endpoints
+}
+
+/// Counters of all the drones running the same implementation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImplementationStats {
+    pub drones: u32,
+    pub packets_forwarded: u32,
+    pub fragments_forwarded: u32,
+    pub packets_dropped: u32,
+    pub nacks_forwarded: u32,
+    pub pdr_sum: f32,
+}
+
+impl ImplementationStats {
+    /// Share of the fragments reaching these drones that were dropped
+    #[must_use]
+    pub fn observed_drop_rate(&self) -> f32 {
+        let received = self.fragments_forwarded + self.packets_dropped;
+        if received == 0 {
+            return 0.0;
+        }
+        self.packets_dropped as f32 / received as f32
+    }
+
+    #[must_use]
+    pub fn mean_pdr(&self) -> f32 {
+        if self.drones == 0 {
+            return 0.0;
+        }
+        self.pdr_sum / self.drones as f32
+    }
+}
+
+/// Aggregates the drone statistics by implementation name
+#[must_use]
+pub fn stats_by_implementation(
+    drone_stats: &HashMap<NodeId, DroneStats>,
+) -> BTreeMap<String, ImplementationStats> {
+    let mut by_implementation: BTreeMap<String, ImplementationStats> = BTreeMap::new();
+    for stats in drone_stats.values() {
+        let entry = by_implementation
+            .entry(stats.implementation.clone())
+            .or_default();
+        entry.drones += 1;
+        entry.packets_forwarded += stats.packets_forwarded;
+        entry.fragments_forwarded += stats.fragments_forwarded;
+        entry.packets_dropped += stats.packets_dropped;
+        entry.nacks_forwarded += stats.nacks_forwarded;
+        entry.pdr_sum += stats.pdr;
+    }
+    by_implementation
 }
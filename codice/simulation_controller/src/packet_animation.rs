@@ -1,10 +1,47 @@
+use std::fmt;
 use wg_2024::network::NodeId;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum AnimationType {
     Fragment,
     Ack,
     Nack,
+    FloodRequest,
+    FloodResponse,
+}
+
+impl AnimationType {
+    pub const ALL: [AnimationType; 5] = [
+        AnimationType::Fragment,
+        AnimationType::Ack,
+        AnimationType::Nack,
+        AnimationType::FloodRequest,
+        AnimationType::FloodResponse,
+    ];
+
+    #[must_use]
+    pub fn color(&self) -> egui::Color32 {
+        match self {
+            AnimationType::Fragment => egui::Color32::YELLOW,
+            AnimationType::Ack => egui::Color32::GREEN,
+            AnimationType::Nack => egui::Color32::RED,
+            AnimationType::FloodRequest => egui::Color32::LIGHT_BLUE,
+            AnimationType::FloodResponse => egui::Color32::from_rgb(200, 0, 200),
+        }
+    }
+}
+
+impl fmt::Display for AnimationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AnimationType::Fragment => "Fragments",
+            AnimationType::Ack => "ACKs",
+            AnimationType::Nack => "NACKs",
+            AnimationType::FloodRequest => "Flood requests",
+            AnimationType::FloodResponse => "Flood responses",
+        };
+        write!(f, "{name}")
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -0,0 +1,432 @@
+use crate::simulation_controller::NodeType;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{
+    Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, Packet, PacketType, FRAGMENT_DSIZE,
+};
+
+/// The kind of packet being crafted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DraftKind {
+    Fragment,
+    Ack,
+    Nack,
+    FloodRequest,
+    FloodResponse,
+}
+
+impl DraftKind {
+    pub const ALL: [DraftKind; 5] = [
+        DraftKind::Fragment,
+        DraftKind::Ack,
+        DraftKind::Nack,
+        DraftKind::FloodRequest,
+        DraftKind::FloodResponse,
+    ];
+}
+
+impl fmt::Display for DraftKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DraftKind::Fragment => "Fragment",
+            DraftKind::Ack => "Ack",
+            DraftKind::Nack => "Nack",
+            DraftKind::FloodRequest => "Flood request",
+            DraftKind::FloodResponse => "Flood response",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The kind of NACK being crafted, the node is taken from the draft
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DraftNackType {
+    ErrorInRouting,
+    DestinationIsDrone,
+    Dropped,
+    UnexpectedRecipient,
+}
+
+impl DraftNackType {
+    pub const ALL: [DraftNackType; 4] = [
+        DraftNackType::ErrorInRouting,
+        DraftNackType::DestinationIsDrone,
+        DraftNackType::Dropped,
+        DraftNackType::UnexpectedRecipient,
+    ];
+}
+
+impl fmt::Display for DraftNackType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DraftNackType::ErrorInRouting => "Error in routing",
+            DraftNackType::DestinationIsDrone => "Destination is drone",
+            DraftNackType::Dropped => "Dropped",
+            DraftNackType::UnexpectedRecipient => "Unexpected recipient",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// Common malformed packets, built on the current topology
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MalformedPreset {
+    // The second hop is not a neighbour of the first drone
+    WrongNextHop,
+    // The route ends on a drone
+    DroneAsDestination,
+    // The routing header has no hops
+    EmptyHeader,
+    // A fragment whose index is not lower than the number of fragments
+    FragmentIndexOutOfRange,
+}
+
+impl MalformedPreset {
+    pub const ALL: [MalformedPreset; 4] = [
+        MalformedPreset::WrongNextHop,
+        MalformedPreset::DroneAsDestination,
+        MalformedPreset::EmptyHeader,
+        MalformedPreset::FragmentIndexOutOfRange,
+    ];
+}
+
+impl fmt::Display for MalformedPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MalformedPreset::WrongNextHop => "Wrong next hop",
+            MalformedPreset::DroneAsDestination => "Drone as destination",
+            MalformedPreset::EmptyHeader => "Empty header",
+            MalformedPreset::FragmentIndexOutOfRange => "Fragment index out of range",
+        };
+        write!(f, "{name}")
+    }
+}
+
+/// The fields of a packet being built by hand in the UI. Node lists are kept as
+/// text, so that any list can be typed, and parsed when the packet is built.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PacketDraft {
+    // Node whose channel receives the packet
+    pub target: NodeId,
+    pub kind: DraftKind,
+    pub hops: String,
+    pub hop_index: usize,
+    pub session_id: u64,
+    pub fragment_index: u64,
+    pub total_n_fragments: u64,
+    pub data: String,
+    pub nack_type: DraftNackType,
+    // Node reported by `ErrorInRouting` and `UnexpectedRecipient`
+    pub nack_node: NodeId,
+    pub flood_id: u64,
+    pub initiator_id: NodeId,
+    pub path_trace: String,
+}
+
+impl Default for PacketDraft {
+    fn default() -> Self {
+        Self {
+            target: 0,
+            kind: DraftKind::Fragment,
+            hops: String::new(),
+            hop_index: 1,
+            session_id: 0,
+            fragment_index: 0,
+            total_n_fragments: 1,
+            data: String::new(),
+            nack_type: DraftNackType::Dropped,
+            nack_node: 0,
+            flood_id: 0,
+            initiator_id: 0,
+            path_trace: String::new(),
+        }
+    }
+}
+
+impl PacketDraft {
+    /// Builds the packet, the nodes of the path trace get the type in `node_types`
+    /// and are drones when unknown
+    ///
+    /// # Errors
+    /// Returns an error if a node list can't be parsed or the data does not fit in a fragment
+    pub fn build(&self, node_types: &HashMap<NodeId, NodeType>) -> Result<Packet, String> {
+        let routing_header = SourceRoutingHeader {
+            hop_index: self.hop_index,
+            hops: parse_node_ids(&self.hops)?,
+        };
+        let path_trace = || -> Result<Vec<_>, String> {
+            Ok(parse_node_ids(&self.path_trace)?
+                .into_iter()
+                .map(|node_id| (node_id, packet_node_type(node_types.get(&node_id))))
+                .collect())
+        };
+
+        let pack_type = match self.kind {
+            DraftKind::Fragment => {
+                let bytes = self.data.as_bytes();
+                if bytes.len() > FRAGMENT_DSIZE {
+                    return Err(format!(
+                        "The data is {} bytes, a fragment holds {FRAGMENT_DSIZE}",
+                        bytes.len()
+                    ));
+                }
+                let mut data = [0; FRAGMENT_DSIZE];
+                data[..bytes.len()].copy_from_slice(bytes);
+                PacketType::MsgFragment(Fragment {
+                    fragment_index: self.fragment_index,
+                    total_n_fragments: self.total_n_fragments,
+                    length: u8::try_from(bytes.len()).expect("Should always be able to convert"),
+                    data,
+                })
+            }
+            DraftKind::Ack => PacketType::Ack(Ack {
+                fragment_index: self.fragment_index,
+            }),
+            DraftKind::Nack => PacketType::Nack(Nack {
+                fragment_index: self.fragment_index,
+                nack_type: match self.nack_type {
+                    DraftNackType::ErrorInRouting => NackType::ErrorInRouting(self.nack_node),
+                    DraftNackType::DestinationIsDrone => NackType::DestinationIsDrone,
+                    DraftNackType::Dropped => NackType::Dropped,
+                    DraftNackType::UnexpectedRecipient => {
+                        NackType::UnexpectedRecipient(self.nack_node)
+                    }
+                },
+            }),
+            DraftKind::FloodRequest => PacketType::FloodRequest(FloodRequest {
+                flood_id: self.flood_id,
+                initiator_id: self.initiator_id,
+                path_trace: path_trace()?,
+            }),
+            DraftKind::FloodResponse => PacketType::FloodResponse(FloodResponse {
+                flood_id: self.flood_id,
+                path_trace: path_trace()?,
+            }),
+        };
+
+        Ok(Packet {
+            routing_header,
+            session_id: self.session_id,
+            pack_type,
+        })
+    }
+
+    /// A fragment from a client showing the malformation of `preset`, injected
+    /// into the first drone of its route
+    ///
+    /// # Errors
+    /// Returns an error if the topology has no nodes to build the preset with
+    pub fn preset(
+        preset: MalformedPreset,
+        topology: &HashMap<NodeId, HashSet<NodeId>>,
+        node_types: &HashMap<NodeId, NodeType>,
+    ) -> Result<Self, String> {
+        let of_type = |node_type: NodeType| {
+            let mut nodes = node_types
+                .iter()
+                .filter(|(_, t)| **t == node_type)
+                .map(|(node_id, _)| *node_id)
+                .collect::<Vec<_>>();
+            nodes.sort_unstable();
+            nodes
+        };
+        let neighbours = |node_id: NodeId| {
+            let mut nodes = topology
+                .get(&node_id)
+                .map(|neighbours| neighbours.iter().copied().collect::<Vec<_>>())
+                .unwrap_or_default();
+            nodes.sort_unstable();
+            nodes
+        };
+        let drones = of_type(NodeType::Drone);
+        let client = *of_type(NodeType::Client)
+            .first()
+            .ok_or("There is no client in the network")?;
+        let server = *of_type(NodeType::Server)
+            .first()
+            .ok_or("There is no server in the network")?;
+        let first_drone = *neighbours(client)
+            .first()
+            .ok_or(format!("Client {client} has no neighbours"))?;
+
+        let hops = match preset {
+            MalformedPreset::WrongNextHop => {
+                let not_neighbour = drones
+                    .iter()
+                    .find(|drone| {
+                        **drone != first_drone && !neighbours(first_drone).contains(drone)
+                    })
+                    .ok_or(format!("Drone {first_drone} is a neighbour of every drone"))?;
+                vec![client, first_drone, *not_neighbour, server]
+            }
+            MalformedPreset::DroneAsDestination => {
+                let mut hops = vec![client, first_drone];
+                if let Some(next) = neighbours(first_drone)
+                    .into_iter()
+                    .find(|node_id| node_types.get(node_id) == Some(&NodeType::Drone))
+                {
+                    hops.push(next);
+                }
+                hops
+            }
+            MalformedPreset::EmptyHeader => vec![],
+            MalformedPreset::FragmentIndexOutOfRange => route(topology, node_types, client, server)
+                .ok_or(format!("There is no route from {client} to {server}"))?,
+        };
+
+        let mut draft = Self {
+            target: first_drone,
+            hops: format_node_ids(&hops),
+            hop_index: usize::from(!hops.is_empty()),
+            initiator_id: client,
+            ..Self::default()
+        };
+        if preset == MalformedPreset::FragmentIndexOutOfRange {
+            draft.fragment_index = 3;
+            draft.total_n_fragments = 2;
+        }
+        Ok(draft)
+    }
+}
+
+/// Parses a list of node ids separated by commas or spaces
+///
+/// # Errors
+/// Returns an error naming the first item that is not a node id
+pub fn parse_node_ids(text: &str) -> Result<Vec<NodeId>, String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            item.parse::<NodeId>()
+                .map_err(|_| format!("\"{item}\" is not a node id"))
+        })
+        .collect()
+}
+
+#[must_use]
+pub fn format_node_ids(node_ids: &[NodeId]) -> String {
+    node_ids
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn packet_node_type(node_type: Option<&NodeType>) -> wg_2024::packet::NodeType {
+    match node_type {
+        Some(NodeType::Client) => wg_2024::packet::NodeType::Client,
+        Some(NodeType::Server) => wg_2024::packet::NodeType::Server,
+        Some(NodeType::Drone) | None => wg_2024::packet::NodeType::Drone,
+    }
+}
+
+/// Shortest route from `from` to `to` forwarding only through drones
+fn route(
+    topology: &HashMap<NodeId, HashSet<NodeId>>,
+    node_types: &HashMap<NodeId, NodeType>,
+    from: NodeId,
+    to: NodeId,
+) -> Option<Vec<NodeId>> {
+    let mut previous = HashMap::from([(from, from)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(current) = queue.pop_front() {
+        if current == to {
+            let mut hops = vec![to];
+            while hops[hops.len() - 1] != from {
+                hops.push(previous[&hops[hops.len() - 1]]);
+            }
+            hops.reverse();
+            return Some(hops);
+        }
+        if current != from && node_types.get(&current) != Some(&NodeType::Drone) {
+            continue;
+        }
+        for neighbour in topology.get(&current).into_iter().flatten() {
+            if !previous.contains_key(neighbour) {
+                previous.insert(*neighbour, current);
+                queue.push_back(*neighbour);
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 5 - 1 - 2 - 7
+    //     |
+    //     3 - 4
+    fn network() -> (HashMap<NodeId, HashSet<NodeId>>, HashMap<NodeId, NodeType>) {
+        let topology = HashMap::from([
+            (1, HashSet::from([2, 3, 5])),
+            (2, HashSet::from([1, 7])),
+            (3, HashSet::from([1, 4])),
+            (4, HashSet::from([3])),
+            (5, HashSet::from([1])),
+            (7, HashSet::from([2])),
+        ]);
+        let node_types = HashMap::from([
+            (1, NodeType::Drone),
+            (2, NodeType::Drone),
+            (3, NodeType::Drone),
+            (4, NodeType::Drone),
+            (5, NodeType::Client),
+            (7, NodeType::Server),
+        ]);
+        (topology, node_types)
+    }
+
+    #[test]
+    fn test_build_packet() {
+        let (_, node_types) = network();
+        let draft = PacketDraft {
+            kind: DraftKind::FloodResponse,
+            hops: "2, 1,5".to_string(),
+            path_trace: "5 1 2".to_string(),
+            flood_id: 9,
+            ..PacketDraft::default()
+        };
+        let packet = draft.build(&node_types).unwrap();
+        assert_eq!(packet.routing_header.hops, vec![2, 1, 5]);
+        assert_eq!(packet.routing_header.hop_index, 1);
+        assert_eq!(
+            packet.pack_type,
+            PacketType::FloodResponse(FloodResponse {
+                flood_id: 9,
+                path_trace: vec![
+                    (5, wg_2024::packet::NodeType::Client),
+                    (1, wg_2024::packet::NodeType::Drone),
+                    (2, wg_2024::packet::NodeType::Drone),
+                ],
+            })
+        );
+
+        let draft = PacketDraft {
+            hops: "5, one".to_string(),
+            ..PacketDraft::default()
+        };
+        assert!(draft.build(&node_types).is_err());
+    }
+
+    #[test]
+    fn test_malformed_presets() {
+        let (topology, node_types) = network();
+        let preset = |preset| PacketDraft::preset(preset, &topology, &node_types).unwrap();
+
+        let draft = preset(MalformedPreset::WrongNextHop);
+        assert_eq!((draft.target, draft.hops.as_str()), (1, "5, 1, 4, 7"));
+
+        let draft = preset(MalformedPreset::DroneAsDestination);
+        assert_eq!(draft.hops, "5, 1, 2");
+
+        let draft = preset(MalformedPreset::EmptyHeader);
+        assert_eq!((draft.hops.as_str(), draft.hop_index), ("", 0));
+
+        let draft = preset(MalformedPreset::FragmentIndexOutOfRange);
+        assert_eq!(draft.hops, "5, 1, 2, 7");
+        assert!(draft.fragment_index >= draft.total_n_fragments);
+    }
+}
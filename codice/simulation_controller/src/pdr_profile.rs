@@ -0,0 +1,260 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::f64::consts::TAU;
+use std::time::Duration;
+
+/// How often the controller recomputes the PDR of the drones with a profile
+pub const PDR_PROFILE_TICK: Duration = Duration::from_millis(500);
+
+/// A packet drop rate that changes over time. Times are in seconds since the
+/// profile was set.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PdrProfile {
+    /// `before` until `at`, `after` from then on
+    Step { before: f32, after: f32, at: f64 },
+    /// Linear change from `from` to `to` over `duration`, then stays at `to`
+    Ramp { from: f32, to: f32, duration: f64 },
+    /// Oscillates around `mean`
+    Sine {
+        mean: f32,
+        amplitude: f32,
+        period: f64,
+    },
+    /// Starts at `start` and moves by at most `step` every tick
+    RandomWalk { start: f32, step: f32 },
+    /// Two state burst model, switching state every tick with the given probabilities
+    GilbertElliott {
+        good_pdr: f32,
+        bad_pdr: f32,
+        p_good_to_bad: f32,
+        p_bad_to_good: f32,
+    },
+}
+
+impl PdrProfile {
+    /// One profile of each kind, with sensible parameters
+    pub const DEFAULTS: [PdrProfile; 5] = [
+        PdrProfile::Step {
+            before: 0.0,
+            after: 0.5,
+            at: 10.0,
+        },
+        PdrProfile::Ramp {
+            from: 0.0,
+            to: 0.5,
+            duration: 30.0,
+        },
+        PdrProfile::Sine {
+            mean: 0.25,
+            amplitude: 0.2,
+            period: 20.0,
+        },
+        PdrProfile::RandomWalk {
+            start: 0.1,
+            step: 0.05,
+        },
+        PdrProfile::GilbertElliott {
+            good_pdr: 0.01,
+            bad_pdr: 0.6,
+            p_good_to_bad: 0.05,
+            p_bad_to_good: 0.3,
+        },
+    ];
+
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        match self {
+            PdrProfile::Step { .. } => "Step",
+            PdrProfile::Ramp { .. } => "Ramp",
+            PdrProfile::Sine { .. } => "Sine",
+            PdrProfile::RandomWalk { .. } => "Random walk",
+            PdrProfile::GilbertElliott { .. } => "Gilbert-Elliott",
+        }
+    }
+
+    /// Checks that every rate and probability is in 0..=1 and every time is positive
+    ///
+    /// # Errors
+    /// Returns a description of the first invalid parameter
+    pub fn validate(&self) -> Result<(), String> {
+        let (rates, times): (Vec<f32>, Vec<f64>) = match *self {
+            PdrProfile::Step { before, after, at } => (vec![before, after], vec![at]),
+            PdrProfile::Ramp { from, to, duration } => (vec![from, to], vec![duration]),
+            PdrProfile::Sine {
+                mean,
+                amplitude,
+                period,
+            } => (vec![mean, amplitude], vec![period]),
+            PdrProfile::RandomWalk { start, step } => (vec![start, step], vec![]),
+            PdrProfile::GilbertElliott {
+                good_pdr,
+                bad_pdr,
+                p_good_to_bad,
+                p_bad_to_good,
+            } => (
+                vec![good_pdr, bad_pdr, p_good_to_bad, p_bad_to_good],
+                vec![],
+            ),
+        };
+
+        if rates.iter().any(|rate| !(0.0..=1.0).contains(rate)) {
+            return Err(format!(
+                "{} profile values must be between 0 and 1",
+                self.name()
+            ));
+        }
+        if times.iter().any(|time| *time <= 0.0) {
+            return Err(format!("{} profile times must be positive", self.name()));
+        }
+        Ok(())
+    }
+}
+
+/// A profile applied to a drone, along with the state of the random profiles
+#[derive(Debug, Clone)]
+pub struct PdrProfileState {
+    pub profile: PdrProfile,
+    current: f32,
+    bad: bool,
+    /// Last PDR sent to the drone
+    applied: Option<f32>,
+}
+
+impl PdrProfileState {
+    #[must_use]
+    pub fn new(profile: PdrProfile) -> Self {
+        let current = match profile {
+            PdrProfile::RandomWalk { start, .. } => start,
+            _ => 0.0,
+        };
+        Self {
+            profile,
+            current,
+            bad: false,
+            applied: None,
+        }
+    }
+
+    /// Returns the PDR for `elapsed` seconds since the profile was set. The
+    /// random profiles move one step every call.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn next_pdr(&mut self, elapsed: f64, rng: &mut impl Rng) -> f32 {
+        let pdr = match self.profile {
+            PdrProfile::Step { before, after, at } => {
+                if elapsed < at {
+                    before
+                } else {
+                    after
+                }
+            }
+            PdrProfile::Ramp { from, to, duration } => {
+                let progress = (elapsed / duration).min(1.0) as f32;
+                from + (to - from) * progress
+            }
+            PdrProfile::Sine {
+                mean,
+                amplitude,
+                period,
+            } => mean + amplitude * (TAU * elapsed / period).sin() as f32,
+            PdrProfile::RandomWalk { step, .. } => {
+                self.current += rng.gen_range(-step..=step);
+                self.current
+            }
+            PdrProfile::GilbertElliott {
+                good_pdr,
+                bad_pdr,
+                p_good_to_bad,
+                p_bad_to_good,
+            } => {
+                let switch = if self.bad {
+                    p_bad_to_good
+                } else {
+                    p_good_to_bad
+                };
+                if rng.gen::<f32>() < switch {
+                    self.bad = !self.bad;
+                }
+                if self.bad {
+                    bad_pdr
+                } else {
+                    good_pdr
+                }
+            }
+        };
+
+        self.current = pdr.clamp(0.0, 1.0);
+        self.current
+    }
+
+    /// Like `next_pdr`, but returns `None` when the drone already has that PDR
+    pub fn next_change(&mut self, elapsed: f64, rng: &mut impl Rng) -> Option<f32> {
+        let pdr = self.next_pdr(elapsed, rng);
+        if self.applied == Some(pdr) {
+            return None;
+        }
+        self.applied = Some(pdr);
+        Some(pdr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pdr_profile_sent_only_on_change() {
+        let mut rng = rand::thread_rng();
+        let mut state = PdrProfileState::new(PdrProfile::Step {
+            before: 0.1,
+            after: 0.5,
+            at: 10.0,
+        });
+        assert_eq!(state.next_change(0.0, &mut rng), Some(0.1));
+        assert_eq!(state.next_change(0.5, &mut rng), None);
+        assert_eq!(state.next_change(10.0, &mut rng), Some(0.5));
+        assert_eq!(state.next_change(10.5, &mut rng), None);
+    }
+
+    #[test]
+    fn test_pdr_profile_values() {
+        let mut rng = rand::thread_rng();
+
+        let mut step = PdrProfileState::new(PdrProfile::Step {
+            before: 0.1,
+            after: 0.5,
+            at: 10.0,
+        });
+        assert!((step.next_pdr(5.0, &mut rng) - 0.1).abs() < 1e-6);
+        assert!((step.next_pdr(15.0, &mut rng) - 0.5).abs() < 1e-6);
+
+        let mut ramp = PdrProfileState::new(PdrProfile::Ramp {
+            from: 0.0,
+            to: 0.4,
+            duration: 10.0,
+        });
+        assert!((ramp.next_pdr(5.0, &mut rng) - 0.2).abs() < 1e-6);
+        assert!((ramp.next_pdr(50.0, &mut rng) - 0.4).abs() < 1e-6);
+
+        let mut gilbert_elliott = PdrProfileState::new(PdrProfile::GilbertElliott {
+            good_pdr: 0.0,
+            bad_pdr: 1.0,
+            p_good_to_bad: 0.5,
+            p_bad_to_good: 0.5,
+        });
+        for i in 0..100 {
+            let pdr = gilbert_elliott.next_pdr(f64::from(i), &mut rng);
+            assert!(pdr == 0.0 || pdr == 1.0);
+        }
+    }
+
+    #[test]
+    fn test_invalid_pdr_profile() {
+        let profile = PdrProfile::Sine {
+            mean: 1.5,
+            amplitude: 0.1,
+            period: 10.0,
+        };
+        assert!(profile.validate().is_err());
+    }
+}
@@ -3,14 +3,22 @@
 #![allow(clippy::too_many_arguments)]
 
 use common::get_drone_impl;
+use common::introspection::{DiscoveredView, IntrospectionRequest};
 
+use crate::battery::{Battery, BatteryConfig, BATTERY_TICK};
 use crate::forwarded_event::ForwardedEvent;
-use crate::ui_commands::{UICommand, UIResponse};
+use crate::mobility::{Mobility, MOBILITY_TICK};
+use crate::pdr_profile::{PdrProfile, PdrProfileState, PDR_PROFILE_TICK};
+use crate::topology_analysis::{ImpactAction, ImpactPreview, TopologyAnalysis};
+use crate::traffic::{TrafficCounters, TRAFFIC_TICK};
+use crate::ui_commands::{CommandId, UICommand, UIResponse};
+use crate::view_diff::ViewDiff;
 
-use crossbeam_channel::{select_biased, Receiver, Sender};
-use std::collections::{HashMap, HashSet};
+use crossbeam_channel::{select_biased, tick, Receiver, Sender, TrySendError};
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
 
 use std::thread;
+use std::time::Instant;
 use wg_2024::controller::{DroneCommand, DroneEvent};
 use wg_2024::network::NodeId;
 use wg_2024::packet::Packet;
@@ -23,6 +31,18 @@ pub enum NodeType {
     Server,
 }
 
+// Neighbours of every node
+type Topology = HashMap<NodeId, HashSet<NodeId>>;
+
+/// Periodic views of the network state. They are built when there is room in the
+/// channel, so the UI only gets the latest one when it falls behind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Snapshot {
+    BatteryLevels,
+    NodePositions,
+    TopologyAnalysis,
+}
+
 /// Simulation Controller
 pub struct SimulationController {
     // Map of node IDs to their respective senders for commands
@@ -40,12 +60,39 @@ pub struct SimulationController {
     // Index of the next drone implementation to use
     _next_drone_impl_index: u8,
 
-    ui_command_receiver: Receiver<UICommand>,
+    ui_command_receiver: Receiver<(CommandId, UICommand)>,
 
-    ui_response_sender: Sender<UIResponse>,
+    ui_response_sender: Sender<(CommandId, UIResponse)>,
+    // Command being handled, the responses are sent with its id
+    current_command: CommandId,
     forwarded_event_sender: Sender<ForwardedEvent>,
+    // Events the channel had no room for, sent in order before any new one
+    unsent_events: VecDeque<ForwardedEvent>,
+    // Snapshots that changed since they were last sent
+    stale_snapshots: BTreeSet<Snapshot>,
 
     crash_event_senders: Vec<Sender<NodeId>>,
+    // Channels to ask clients and servers for their discovered view
+    introspection_senders: HashMap<NodeId, Sender<IntrospectionRequest>>,
+    // Where the clients and servers answer
+    view_sender: Sender<DiscoveredView>,
+    view_receiver: Receiver<DiscoveredView>,
+
+    // PDR profiles of the drones, with the time they were set
+    pdr_profiles: HashMap<NodeId, (PdrProfileState, Instant)>,
+    pdr_profile_ticker: Receiver<Instant>,
+
+    // Batteries of the drones that have one
+    batteries: HashMap<NodeId, Battery>,
+    battery_ticker: Receiver<Instant>,
+
+    // Positions of the nodes, when the network is mobile
+    mobility: Option<Mobility>,
+    mobility_ticker: Receiver<Instant>,
+
+    // Packets counted since the counters were last sent to the UI
+    traffic: TrafficCounters,
+    traffic_ticker: Receiver<Instant>,
 }
 
 impl SimulationController {
@@ -62,11 +109,15 @@ impl SimulationController {
         server_nodes: Vec<NodeId>,
         next_drone_impl_index: u8,
 
-        ui_command_receiver: Receiver<UICommand>,
-        ui_response_sender: Sender<UIResponse>,
+        ui_command_receiver: Receiver<(CommandId, UICommand)>,
+        ui_response_sender: Sender<(CommandId, UIResponse)>,
         forwarded_event_sender: Sender<ForwardedEvent>,
 
         crash_event_senders: Vec<Sender<NodeId>>,
+        introspection_senders: HashMap<NodeId, Sender<IntrospectionRequest>>,
+        pdr_profiles: HashMap<NodeId, PdrProfile>,
+        batteries: HashMap<NodeId, BatteryConfig>,
+        mobility: Option<Mobility>,
     ) -> Self {
         let mut node_types = HashMap::new();
 
@@ -82,6 +133,8 @@ impl SimulationController {
             node_types.insert(server_id, NodeType::Server);
         }
 
+        let (view_sender, view_receiver) = crossbeam_channel::unbounded();
+
         Self {
             node_command_senders,
             node_packet_senders,
@@ -92,25 +145,84 @@ impl SimulationController {
             _next_drone_impl_index: next_drone_impl_index,
             ui_command_receiver,
             ui_response_sender,
+            current_command: 0,
             forwarded_event_sender,
+            unsent_events: VecDeque::new(),
+            stale_snapshots: BTreeSet::new(),
             crash_event_senders,
+            introspection_senders,
+            view_sender,
+            view_receiver,
+            pdr_profiles: pdr_profiles
+                .into_iter()
+                .map(|(drone_id, profile)| {
+                    (drone_id, (PdrProfileState::new(profile), Instant::now()))
+                })
+                .collect(),
+            pdr_profile_ticker: tick(PDR_PROFILE_TICK),
+            batteries: batteries
+                .into_iter()
+                .map(|(drone_id, config)| (drone_id, Battery::new(config)))
+                .collect(),
+            battery_ticker: tick(BATTERY_TICK),
+            mobility,
+            mobility_ticker: tick(MOBILITY_TICK),
+            traffic: TrafficCounters::default(),
+            traffic_ticker: tick(TRAFFIC_TICK),
         }
     }
 
     /// Runs the simulation controller
     pub fn run(&mut self) {
+        let profiles: Vec<_> = self
+            .pdr_profiles
+            .iter()
+            .map(|(drone_id, (state, _))| (*drone_id, state.profile))
+            .collect();
+        for (drone_id, profile) in profiles {
+            self.forward_event(ForwardedEvent::PDRProfileSet(drone_id, Some(profile)));
+        }
+        self.forward_snapshot(Snapshot::BatteryLevels);
+        self.forward_snapshot(Snapshot::TopologyAnalysis);
+
         loop {
             select_biased! {
                 recv(self.event_receiver) -> event => {
                    if let Ok (event) = event {
-                        self.handle_event(event);
+                        self.handle_event(event, Instant::now());
                    }
                 }
                 recv(self.ui_command_receiver) -> ui_command => {
-                    if let Ok (ui_command) = ui_command {
-                    self.handle_ui_command(ui_command);
+                    match ui_command {
+                        Ok((command_id, ui_command)) => {
+                            self.current_command = command_id;
+                            self.handle_ui_command(ui_command);
+                        }
+                        // Nothing can drive the simulation without the UI
+                        Err(_) => {
+                            self.shutdown();
+                            return;
+                        }
+                    }
+                }
+                recv(self.view_receiver) -> view => {
+                    if let Ok(view) = view {
+                        self.forward_discovered_view(view);
                     }
                 }
+                recv(self.pdr_profile_ticker) -> _ => {
+                    self.apply_pdr_profiles();
+                }
+                recv(self.battery_ticker) -> _ => {
+                    self.drain_idle_batteries();
+                }
+                recv(self.mobility_ticker) -> _ => {
+                    self.move_nodes();
+                }
+                recv(self.traffic_ticker) -> _ => {
+                    self.flush_events();
+                    self.send_traffic();
+                }
             }
         }
     }
@@ -119,8 +231,15 @@ impl SimulationController {
     fn handle_ui_command(&mut self, ui_command: UICommand) {
         match ui_command {
             UICommand::SetPDR(node_id, pdr) => {
+                // A fixed PDR replaces the profile
+                if self.pdr_profiles.remove(&node_id).is_some() {
+                    self.forward_event(ForwardedEvent::PDRProfileSet(node_id, None));
+                }
                 self.set_packet_drop_rate(node_id, pdr);
             }
+            UICommand::SetPDRProfile(node_id, profile) => {
+                self.set_pdr_profile(node_id, profile);
+            }
             UICommand::CrashDrone(node_id) => {
                 self.crash_drone(node_id);
             }
@@ -130,15 +249,29 @@ impl SimulationController {
             UICommand::RemoveConnection(node1, node2) => {
                 self.remove_connection(node1, node2);
             }
+            UICommand::PreviewImpact(action) => {
+                self.preview_impact(action);
+            }
+            UICommand::InjectPacket(node_id, packet) => {
+                self.inject_packet(node_id, packet);
+            }
+            UICommand::InspectEndpoint(node_id) => {
+                self.inspect_endpoint(node_id);
+            }
         }
     }
 
     /// Handles events received from drones
-    fn handle_event(&mut self, event: DroneEvent) {
+    fn handle_event(&mut self, event: DroneEvent, received_at: Instant) {
         match event {
             DroneEvent::PacketSent(packet) => {
+                // Flood responses are source routed, the last hop of their path
+                // trace is the responder and not the drone forwarding them
                 let node_id = match &packet.pack_type {
-                    PacketType::MsgFragment(_) | PacketType::Ack(_) | PacketType::Nack(_) => packet
+                    PacketType::MsgFragment(_)
+                    | PacketType::Ack(_)
+                    | PacketType::Nack(_)
+                    | PacketType::FloodResponse(_) => packet
                         .routing_header
                         .previous_hop()
                         .expect("there should always be a previous hop"),
@@ -149,13 +282,6 @@ impl SimulationController {
                             .expect("Flood requests should have a last hop")
                             .0
                     }
-                    PacketType::FloodResponse(flood_response) => {
-                        flood_response
-                            .path_trace
-                            .last()
-                            .expect("Flood requests should have a last hop")
-                            .0
-                    }
                 };
 
                 if *self
@@ -164,9 +290,15 @@ impl SimulationController {
                     .expect("Node id should be valid")
                     == NodeType::Drone
                 {
-                    self.forwarded_event_sender
-                        .send(ForwardedEvent::PacketSent(packet))
-                        .expect("Should be able to send event");
+                    let depleted = self
+                        .batteries
+                        .get_mut(&node_id)
+                        .is_some_and(|battery| battery.packet_sent(&packet.pack_type));
+                    self.traffic.packet_sent(node_id, &packet);
+                    self.forward_packet_event(ForwardedEvent::PacketSent(packet, received_at));
+                    if depleted {
+                        self.crash_depleted_drone(node_id, true);
+                    }
                 }
             }
             DroneEvent::PacketDropped(packet) => {
@@ -175,17 +307,93 @@ impl SimulationController {
                     .current_hop()
                     .expect("Previous hop should always be valid");
                 if self.node_types.get(&node_id) == Some(&NodeType::Drone) {
-                    self.forwarded_event_sender
-                        .send(ForwardedEvent::PacketDropped(packet))
-                        .expect("Should be able to send event");
+                    self.traffic.packet_dropped(node_id, &packet);
+                    self.forward_packet_event(ForwardedEvent::PacketDropped(packet, received_at));
                 }
             }
             DroneEvent::ControllerShortcut(packet) => {
+                self.forward_packet_event(ForwardedEvent::ShortcutDelivered(
+                    packet.clone(),
+                    received_at,
+                ));
                 self.send_packet_directly(packet);
             }
         }
     }
 
+    /// Forwards a packet event, unless the UI is so far behind that the channel is
+    /// full: the packet is in the counters anyway, and so is the skipped event
+    fn forward_packet_event(&mut self, event: ForwardedEvent) {
+        if self.forwarded_event_sender.try_send(event).is_err() {
+            self.traffic.skipped_events += 1;
+        }
+    }
+
+    /// Sends the UI the packets counted since the last time. When the channel is
+    /// full they keep adding up until a later tick.
+    fn send_traffic(&mut self) {
+        // The controller is the only sender, a channel with room takes the event
+        if self.traffic.is_empty() || self.forwarded_event_sender.is_full() {
+            return;
+        }
+        let _ = self
+            .forwarded_event_sender
+            .try_send(ForwardedEvent::TrafficCounted(std::mem::take(
+                &mut self.traffic,
+            )));
+    }
+
+    /// Sends the UI an event that changes its state. When the channel is full the
+    /// event waits for a later flush instead of stalling the controller.
+    fn forward_event(&mut self, event: ForwardedEvent) {
+        self.unsent_events.push_back(event);
+        self.flush_events();
+    }
+
+    /// Marks a snapshot as changed, it is built and sent once there is room
+    fn forward_snapshot(&mut self, snapshot: Snapshot) {
+        self.stale_snapshots.insert(snapshot);
+        self.flush_events();
+    }
+
+    /// Sends what the channel takes: the waiting events in order, then the
+    /// snapshots that changed
+    fn flush_events(&mut self) {
+        while let Some(event) = self.unsent_events.pop_front() {
+            match self.forwarded_event_sender.try_send(event) {
+                Ok(()) => {}
+                Err(TrySendError::Full(event)) => {
+                    self.unsent_events.push_front(event);
+                    return;
+                }
+                // Nobody is left to show them
+                Err(TrySendError::Disconnected(_)) => {
+                    self.unsent_events.clear();
+                    return;
+                }
+            }
+        }
+        while !self.forwarded_event_sender.is_full() {
+            let Some(snapshot) = self.stale_snapshots.pop_first() else {
+                return;
+            };
+            let event = match snapshot {
+                Snapshot::BatteryLevels if !self.batteries.is_empty() => {
+                    ForwardedEvent::BatteryLevels(self.batteries.clone())
+                }
+                Snapshot::BatteryLevels => continue,
+                Snapshot::NodePositions => match &self.mobility {
+                    Some(mobility) => ForwardedEvent::NodesMoved(mobility.positions()),
+                    None => continue,
+                },
+                Snapshot::TopologyAnalysis => ForwardedEvent::TopologyAnalysed(
+                    TopologyAnalysis::new(&self.network_topology, &self.node_types),
+                ),
+            };
+            let _ = self.forwarded_event_sender.try_send(event);
+        }
+    }
+
     /// Sends a packet directly to the destination using the simulation controller
     fn send_packet_directly(&self, packet: Packet) {
         if let Some(destination) = packet.routing_header.destination() {
@@ -197,6 +405,54 @@ impl SimulationController {
         }
     }
 
+    /// Puts a packet in the channel of a node, as if one of its neighbours sent it
+    fn inject_packet(&self, node_id: NodeId, packet: Packet) {
+        let response = match self.node_packet_senders.get(&node_id) {
+            Some(sender) => match sender.send(packet) {
+                Ok(()) => UIResponse::Success(format!("Packet injected into node {node_id}")),
+                Err(_) => UIResponse::Falure(format!("Node {node_id} is not receiving packets")),
+            },
+            None => UIResponse::Falure(format!("Node {node_id} does not exist in the network")),
+        };
+        self.respond(response);
+    }
+
+    /// Asks a client or server for its discovered view, which arrives on `view_receiver`
+    fn inspect_endpoint(&self, node_id: NodeId) {
+        let result = match self.introspection_senders.get(&node_id) {
+            Some(sender) => sender
+                .send(IntrospectionRequest {
+                    reply: self.view_sender.clone(),
+                })
+                .map_err(|_| format!("Node {node_id} is not answering")),
+            None => Err(format!("Node {node_id} is not a client or server")),
+        };
+        if let Err(e) = result {
+            self.respond(UIResponse::Falure(e));
+        }
+    }
+
+    /// Sends the UI the view of an endpoint along with how it differs from the network
+    fn forward_discovered_view(&mut self, view: DiscoveredView) {
+        let crashed = self
+            .node_types
+            .iter()
+            .filter(|(node_id, node_type)| {
+                **node_type == NodeType::Drone && !self.node_packet_senders.contains_key(node_id)
+            })
+            .map(|(node_id, _)| *node_id)
+            .collect();
+        let diff = ViewDiff::new(&view, &self.network_topology, &crashed);
+        self.forward_event(ForwardedEvent::ViewDiscovered(view, diff));
+    }
+
+    /// Answers the UI command being handled
+    fn respond(&self, response: UIResponse) {
+        self.ui_response_sender
+            .send((self.current_command, response))
+            .expect("Should be able to send");
+    }
+
     /// Sends a command to a specific drone
     fn send_command(&self, drone_id: NodeId, command: &DroneCommand) -> Result<(), String> {
         self.is_command_allowed(drone_id, command)?;
@@ -290,139 +546,264 @@ impl SimulationController {
         //self.node_types.remove(&drone_id);
     }
 
+    /// Sends the new PDR to a drone and forwards it to the UI
+    fn send_packet_drop_rate(&mut self, drone_id: NodeId, pdr: f32) -> Result<(), String> {
+        self.send_command(drone_id, &DroneCommand::SetPacketDropRate(pdr))?;
+        self.forward_event(ForwardedEvent::PDRSet(drone_id, pdr));
+        Ok(())
+    }
+
     /// Updates the Packet Drop Rate (PDR) of a drone
-    fn set_packet_drop_rate(&self, drone_id: NodeId, pdr: f32) {
-        match self.send_command(drone_id, &DroneCommand::SetPacketDropRate(pdr)) {
+    fn set_packet_drop_rate(&mut self, drone_id: NodeId, pdr: f32) {
+        match self.send_packet_drop_rate(drone_id, pdr) {
             Ok(()) => {
-                self.forwarded_event_sender
-                    .send(ForwardedEvent::PDRSet(drone_id, pdr))
-                    .expect("Should be able to send event");
+                self.respond(UIResponse::Success(
+                    "Packet drop rate successfully updated".to_string(),
+                ));
+            }
+            Err(e) => {
+                self.respond(UIResponse::Falure(e));
+            }
+        }
+    }
+
+    /// Sets or removes the PDR profile of a drone
+    fn set_pdr_profile(&mut self, drone_id: NodeId, profile: Option<PdrProfile>) {
+        let result = if self.node_types.get(&drone_id) == Some(&NodeType::Drone) {
+            match profile {
+                Some(profile) => profile.validate().map(|()| {
+                    self.pdr_profiles
+                        .insert(drone_id, (PdrProfileState::new(profile), Instant::now()));
+                    format!("{} profile set", profile.name())
+                }),
+                None => {
+                    self.pdr_profiles.remove(&drone_id);
+                    Ok("PDR profile removed".to_string())
+                }
+            }
+        } else {
+            Err(format!("Drone {drone_id} does not exist in the network"))
+        };
 
-                self.ui_response_sender
-                    .send(UIResponse::Success(
-                        "Packet drop rate successfully updated".to_string(),
-                    ))
-                    .expect("Should be able to send");
+        match result {
+            Ok(message) => {
+                self.forward_event(ForwardedEvent::PDRProfileSet(drone_id, profile));
+                self.apply_pdr_profiles();
+                self.respond(UIResponse::Success(message));
             }
             Err(e) => {
-                self.ui_response_sender
-                    .send(UIResponse::Falure(e))
-                    .expect("Should be able to send");
+                self.respond(UIResponse::Falure(e));
+            }
+        }
+    }
+
+    /// Sends every drone with a profile its current PDR, if it changed
+    fn apply_pdr_profiles(&mut self) {
+        let mut rng = rand::thread_rng();
+        let mut updates = Vec::new();
+        for (drone_id, (state, started)) in &mut self.pdr_profiles {
+            if let Some(pdr) = state.next_change(started.elapsed().as_secs_f64(), &mut rng) {
+                updates.push((*drone_id, pdr));
+            }
+        }
+
+        for (drone_id, pdr) in updates {
+            // The drone crashed in the meantime, its profile is dropped with it
+            if self.send_packet_drop_rate(drone_id, pdr).is_err() {
+                self.pdr_profiles.remove(&drone_id);
             }
         }
     }
 
     /// Crashes a drone
     fn crash_drone(&mut self, drone_id: NodeId) {
-        match self.send_command(drone_id, &DroneCommand::Crash) {
+        match self.crash(drone_id) {
             Ok(()) => {
-                self.remove_drone(drone_id);
-                self.forwarded_event_sender
-                    .send(ForwardedEvent::DroneCrashed(drone_id))
-                    .expect("Should be able to send event");
-                for sender in &self.crash_event_senders {
-                    sender
-                        .send(drone_id)
-                        .expect("Should be able to send crash event");
-                }
-                self.ui_response_sender
-                    .send(UIResponse::Success(
-                        "Drone successfully crashed".to_string(),
-                    ))
-                    .expect("Should be able to send");
+                self.respond(UIResponse::Success(
+                    "Drone successfully crashed".to_string(),
+                ));
             }
             Err(e) => {
-                self.ui_response_sender
-                    .send(UIResponse::Falure(format!(
-                        "If this drone crashed, this would happen: {e}"
-                    )))
-                    .expect("Should be able to send");
+                self.respond(UIResponse::Falure(format!(
+                    "If this drone crashed, this would happen: {e}"
+                )));
             }
         }
     }
 
-    /// Adds a connection between two nodes
-    fn add_connection(&mut self, node1: NodeId, node2: NodeId) {
-        match self.is_adding_connection_valid(node1, node2) {
-            Ok(()) => {
-                self.network_topology
-                    .entry(node1)
-                    .or_default()
-                    .insert(node2);
-                self.network_topology
-                    .entry(node2)
-                    .or_default()
-                    .insert(node1);
-
-                // Notify both nodes to add the connection
-                if let Some(sender1) = self.node_command_senders.get(&node1) {
-                    sender1
-                        .send(DroneCommand::AddSender(
-                            node2,
-                            self.node_packet_senders[&node2].clone(),
-                        ))
-                        .expect("Neighbor sender should be valid");
-                }
-                if let Some(sender2) = self.node_command_senders.get(&node2) {
-                    sender2
-                        .send(DroneCommand::AddSender(
-                            node1,
-                            self.node_packet_senders[&node1].clone(),
-                        ))
-                        .expect("Neighbor sender should be valid");
+    /// Crashes a drone if the network stays valid without it and notifies everyone
+    fn crash(&mut self, drone_id: NodeId) -> Result<(), String> {
+        self.send_command(drone_id, &DroneCommand::Crash)?;
+        self.pdr_profiles.remove(&drone_id);
+        self.batteries.remove(&drone_id);
+        if let Some(mobility) = &mut self.mobility {
+            mobility.remove_node(drone_id);
+        }
+        self.remove_drone(drone_id);
+        self.forward_event(ForwardedEvent::DroneCrashed(drone_id));
+        self.forward_snapshot(Snapshot::TopologyAnalysis);
+        for sender in &self.crash_event_senders {
+            sender
+                .send(drone_id)
+                .expect("Should be able to send crash event");
+        }
+        Ok(())
+    }
+
+    /// Crashes every drone without checking the network, so that all the node
+    /// threads can end: the drones stop once nobody holds their channel anymore,
+    /// the clients and servers once every drone is gone
+    fn shutdown(&mut self) {
+        for (drone_id, sender) in &self.node_command_senders {
+            if let Some(neighbors) = self.network_topology.get(drone_id) {
+                for neighbor in neighbors {
+                    // The drone may have crashed already
+                    let _ = sender.send(DroneCommand::RemoveSender(*neighbor));
                 }
+            }
+            let _ = sender.send(DroneCommand::Crash);
+            for crash_event_sender in &self.crash_event_senders {
+                // The endpoint may have stopped already
+                let _ = crash_event_sender.send(*drone_id);
+            }
+        }
+        self.node_command_senders.clear();
+        self.node_packet_senders.clear();
+        self.crash_event_senders.clear();
+        self.introspection_senders.clear();
+    }
+
+    /// Crashes a drone with an empty battery. When the crash would break the network
+    /// the drone keeps running, and the UI is warned only if `warn` is set.
+    fn crash_depleted_drone(&mut self, drone_id: NodeId, warn: bool) {
+        let result = self.crash(drone_id);
+        if result.is_ok() || warn {
+            self.forward_event(ForwardedEvent::BatteryDepleted(drone_id, result));
+        }
+    }
+
+    /// Charges the idle cost to every battery, then retries crashing the empty ones
+    fn drain_idle_batteries(&mut self) {
+        let mut depleted = Vec::new();
+        for (drone_id, battery) in &mut self.batteries {
+            let just_emptied = battery.idle(BATTERY_TICK);
+            if battery.is_empty() {
+                depleted.push((*drone_id, just_emptied));
+            }
+        }
+        self.forward_snapshot(Snapshot::BatteryLevels);
 
-                self.forwarded_event_sender
-                    .send(ForwardedEvent::ConnectionAdded(node1, node2))
-                    .expect("Should be able to send event");
+        for (drone_id, just_emptied) in depleted {
+            self.crash_depleted_drone(drone_id, just_emptied);
+        }
+    }
 
+    /// Adds a connection between two nodes
+    fn add_connection(&mut self, node1: NodeId, node2: NodeId) {
+        match self.connect(node1, node2) {
+            Ok(()) => {
                 //send response to the UI
-                self.ui_response_sender
-                    .send(UIResponse::Success(
-                        "Connection successfully added".to_string(),
-                    ))
-                    .expect("Should be able to send");
+                self.respond(UIResponse::Success(
+                    "Connection successfully added".to_string(),
+                ));
             }
             Err(e) => {
-                self.ui_response_sender
-                    .send(UIResponse::Falure(e))
-                    .expect("Should be able to send");
+                self.respond(UIResponse::Falure(e));
             }
         }
     }
 
+    /// Links two nodes if the network stays valid and notifies the UI
+    fn connect(&mut self, node1: NodeId, node2: NodeId) -> Result<(), String> {
+        self.is_adding_connection_valid(node1, node2)?;
+        self.network_topology
+            .entry(node1)
+            .or_default()
+            .insert(node2);
+        self.network_topology
+            .entry(node2)
+            .or_default()
+            .insert(node1);
+
+        // Notify both nodes to add the connection
+        if let Some(sender1) = self.node_command_senders.get(&node1) {
+            sender1
+                .send(DroneCommand::AddSender(
+                    node2,
+                    self.node_packet_senders[&node2].clone(),
+                ))
+                .expect("Neighbor sender should be valid");
+        }
+        if let Some(sender2) = self.node_command_senders.get(&node2) {
+            sender2
+                .send(DroneCommand::AddSender(
+                    node1,
+                    self.node_packet_senders[&node1].clone(),
+                ))
+                .expect("Neighbor sender should be valid");
+        }
+
+        self.forward_event(ForwardedEvent::ConnectionAdded(node1, node2));
+        self.forward_snapshot(Snapshot::TopologyAnalysis);
+        Ok(())
+    }
+
     /// Removes a connection between two nodes
     fn remove_connection(&mut self, node1: NodeId, node2: NodeId) {
-        match self.is_connection_removal_valid(node1, node2) {
+        match self.disconnect(node1, node2) {
             Ok(()) => {
-                if let Some(neighbors) = self.network_topology.get_mut(&node1) {
-                    neighbors.remove(&node2);
-                }
-                if let Some(neighbors) = self.network_topology.get_mut(&node2) {
-                    neighbors.remove(&node1);
-                }
+                self.respond(UIResponse::Success(
+                    "Connection successfully removed".to_string(),
+                ));
+            }
+            Err(e) => {
+                self.respond(UIResponse::Falure(e));
+            }
+        }
+    }
 
-                // Notify both nodes to remove the connection
-                // We ignore errors here since the nodes might already be disconnected
-                let _ = self.send_command(node1, &DroneCommand::RemoveSender(node2));
-                let _ = self.send_command(node2, &DroneCommand::RemoveSender(node1));
+    /// Unlinks two nodes if the network stays valid and notifies the UI
+    fn disconnect(&mut self, node1: NodeId, node2: NodeId) -> Result<(), String> {
+        self.is_connection_removal_valid(node1, node2)?;
+        if let Some(neighbors) = self.network_topology.get_mut(&node1) {
+            neighbors.remove(&node2);
+        }
+        if let Some(neighbors) = self.network_topology.get_mut(&node2) {
+            neighbors.remove(&node1);
+        }
 
-                self.forwarded_event_sender
-                    .send(ForwardedEvent::ConnectionRemoved(node1, node2))
-                    .expect("Should be able to send event");
+        // Notify both nodes to remove the connection
+        // We ignore errors here since the nodes might already be disconnected
+        let _ = self.send_command(node1, &DroneCommand::RemoveSender(node2));
+        let _ = self.send_command(node2, &DroneCommand::RemoveSender(node1));
 
-                self.ui_response_sender
-                    .send(UIResponse::Success(
-                        "Connection successfully removed".to_string(),
-                    ))
-                    .expect("Should be able to send");
-            }
-            Err(e) => {
-                self.ui_response_sender
-                    .send(UIResponse::Falure(e))
-                    .expect("Should be able to send");
+        self.forward_event(ForwardedEvent::ConnectionRemoved(node1, node2));
+        self.forward_snapshot(Snapshot::TopologyAnalysis);
+        Ok(())
+    }
+
+    /// Moves the nodes with a position, then links the nodes that came within range
+    /// and unlinks the ones that left it. Changes that would break the network are
+    /// skipped and retried on the next tick.
+    fn move_nodes(&mut self) {
+        let Some(mobility) = &mut self.mobility else {
+            return;
+        };
+        mobility.step(MOBILITY_TICK, &mut rand::thread_rng());
+        let (added, removed) = mobility.link_changes(&self.network_topology);
+        self.forward_snapshot(Snapshot::NodePositions);
+
+        // Links are added first, so that they can take over the ones being removed
+        for (node1, node2) in added {
+            if self.node_types.get(&node1) == Some(&NodeType::Drone)
+                || self.node_types.get(&node2) == Some(&NodeType::Drone)
+            {
+                let _ = self.connect(node1, node2);
             }
         }
+        for (node1, node2) in removed {
+            let _ = self.disconnect(node1, node2);
+        }
     }
 
     /// Checks if executing a command is allowed based on network requirements
@@ -457,6 +838,18 @@ impl SimulationController {
         destination: NodeId,
         node_id: NodeId,
     ) -> Result<(), String> {
+        let topology = self.topology_after_removal(destination, node_id)?;
+
+        // Check if the network is still connected
+        Self::check_topology(&topology, &self.node_types)
+    }
+
+    /// Simulates removing an edge on a copy of the topology
+    fn topology_after_removal(
+        &self,
+        destination: NodeId,
+        node_id: NodeId,
+    ) -> Result<Topology, String> {
         let mut topology = self.network_topology.clone();
 
         if let Some(neighbors) = topology.get_mut(&destination) {
@@ -470,13 +863,21 @@ impl SimulationController {
             return Err(format!("Node {node_id} does not exist in the network"));
         }
 
-        // Check if the network is still connected
-        Self::check_topology(&topology, &self.node_types)
+        Ok(topology)
     }
 
     /// Checks if the network remains connected after crashing a drone
     fn is_network_connected_after_crash(&self, node_id: NodeId) -> Result<(), String> {
-        // Simulate the crash by removing the drone and checking connectivity
+        let (topology, node_types) = self.topology_after_crash(node_id)?;
+        // Check if the network is still connected
+        Self::check_topology(&topology, &node_types)
+    }
+
+    /// Simulates a crash by removing the drone from a copy of the topology
+    fn topology_after_crash(
+        &self,
+        node_id: NodeId,
+    ) -> Result<(Topology, HashMap<NodeId, NodeType>), String> {
         let mut topology = self.network_topology.clone();
 
         let neighbors = topology
@@ -491,8 +892,32 @@ impl SimulationController {
         topology.remove(&node_id);
         let mut node_types = self.node_types.clone();
         node_types.remove(&node_id);
-        // Check if the network is still connected
-        Self::check_topology(&topology, &node_types)
+        Ok((topology, node_types))
+    }
+
+    /// Sends the UI what an action would change, without doing it
+    fn preview_impact(&mut self, action: ImpactAction) {
+        let after = match action {
+            ImpactAction::Crash(drone_id) => self.topology_after_crash(drone_id),
+            ImpactAction::RemoveConnection(node1, node2) => self
+                .topology_after_removal(node1, node2)
+                .map(|topology| (topology, self.node_types.clone())),
+        };
+
+        match after {
+            Ok((topology, node_types)) => {
+                let preview = ImpactPreview::new(
+                    action,
+                    (&self.network_topology, &self.node_types),
+                    (&topology, &node_types),
+                    Self::check_topology(&topology, &node_types).err(),
+                );
+                self.forward_event(ForwardedEvent::ImpactPreviewed(preview));
+            }
+            Err(e) => {
+                self.respond(UIResponse::Falure(e));
+            }
+        }
     }
 
     fn check_topology(
@@ -649,6 +1074,7 @@ impl SimulationController {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use wg_2024::network::SourceRoutingHeader;
     fn _initialize_mock_network() -> SimulationController {
         use crossbeam_channel::unbounded;
         use std::collections::{HashMap, HashSet};
@@ -660,7 +1086,7 @@ mod tests {
         let mut drone_command_recievers = HashMap::new();
 
         let (event_sender, event_receiver) = unbounded();
-        let (forwarded_event_sender, _) = unbounded();
+        let (forwarded_event_sender, forwarded_event_receiver) = unbounded();
 
         let (ui_command_sender, ui_command_receiver) = unbounded();
         let (ui_response_sender, ui_response_receiver) = unbounded();
@@ -736,8 +1162,9 @@ mod tests {
             thread::spawn(move || drone.run());
 
             struct MockUi {
-                _receiver: Receiver<UIResponse>,
-                _sender: Sender<UICommand>,
+                _receiver: Receiver<(CommandId, UIResponse)>,
+                _sender: Sender<(CommandId, UICommand)>,
+                _events: Receiver<ForwardedEvent>,
             }
 
             impl MockUi {
@@ -749,6 +1176,7 @@ mod tests {
             let mock_ui = MockUi {
                 _receiver: ui_response_receiver.clone(),
                 _sender: ui_command_sender.clone(),
+                _events: forwarded_event_receiver.clone(),
             };
 
             thread::spawn(move || mock_ui._run());
@@ -767,6 +1195,11 @@ mod tests {
             ui_command_receiver,
             ui_response_sender,
             forwarded_event_sender,
+            vec![],
+            HashMap::new(),
+            HashMap::new(),
+            HashMap::new(),
+            None,
         )
     }
 
@@ -794,7 +1227,7 @@ mod tests {
 
     #[test]
     fn test_set_packet_drop_rate() {
-        let controller = _initialize_mock_network();
+        let mut controller = _initialize_mock_network();
         controller.set_packet_drop_rate(1, 0.5);
     }
 
@@ -921,4 +1354,132 @@ mod tests {
         controller.add_connection(5, 7); // Client 4 and server 6
         assert_eq!(controller.network_topology, initial_topology); // Connection should not be added
     }
+
+    #[test]
+    fn test_full_channel_does_not_stall_controller() {
+        let mut controller = _initialize_mock_network();
+        let (forwarded_event_sender, forwarded_event_receiver) = crossbeam_channel::bounded(1);
+        controller.forwarded_event_sender = forwarded_event_sender;
+
+        // The UI reads nothing, the commands are handled anyway
+        controller.set_packet_drop_rate(1, 0.5);
+        controller.set_packet_drop_rate(2, 0.3);
+        controller.remove_connection(1, 2);
+        assert!(!controller.network_topology[&1].contains(&2));
+        assert_eq!(controller.unsent_events.len(), 2);
+        assert!(controller.stale_snapshots.contains(&Snapshot::TopologyAnalysis));
+
+        // Once the UI catches up the events arrive in order, the analysis last
+        let mut received = Vec::new();
+        while let Ok(event) = forwarded_event_receiver.try_recv() {
+            received.push(event);
+            controller.flush_events();
+        }
+        assert_eq!(received.len(), 4);
+        assert!(matches!(received[0], ForwardedEvent::PDRSet(1, _)));
+        assert!(matches!(received[1], ForwardedEvent::PDRSet(2, _)));
+        assert!(matches!(received[2], ForwardedEvent::ConnectionRemoved(1, 2)));
+        assert!(matches!(received[3], ForwardedEvent::TopologyAnalysed(_)));
+    }
+
+    #[test]
+    fn test_skipped_packet_events_counted() {
+        let mut controller = _initialize_mock_network();
+        let (forwarded_event_sender, forwarded_event_receiver) = crossbeam_channel::bounded(1);
+        controller.forwarded_event_sender = forwarded_event_sender;
+
+        controller.handle_event(ack_from(4), Instant::now());
+        controller.handle_event(ack_from(4), Instant::now());
+        controller.handle_event(ack_from(4), Instant::now());
+        assert_eq!(controller.traffic.skipped_events, 2);
+
+        // The count reaches the UI with the traffic counters
+        assert!(forwarded_event_receiver.try_recv().is_ok());
+        controller.send_traffic();
+        match forwarded_event_receiver.try_recv() {
+            Ok(ForwardedEvent::TrafficCounted(traffic)) => assert_eq!(traffic.skipped_events, 2),
+            _ => panic!("The traffic counters should have been sent"),
+        }
+    }
+
+    #[test]
+    fn test_set_pdr_profile() {
+        let mut controller = _initialize_mock_network();
+        let profile = PdrProfile::Step {
+            before: 0.1,
+            after: 0.5,
+            at: 10.0,
+        };
+
+        controller.set_pdr_profile(1, Some(profile));
+        assert!(controller.pdr_profiles.contains_key(&1));
+
+        controller.set_pdr_profile(5, Some(profile)); // Client 5 has no PDR
+        assert!(!controller.pdr_profiles.contains_key(&5));
+
+        controller.set_pdr_profile(1, None);
+        assert!(controller.pdr_profiles.is_empty());
+    }
+
+    const TEST_BATTERY: BatteryConfig = BatteryConfig {
+        capacity: 2.0,
+        fragment_cost: 1.0,
+        flood_cost: 1.0,
+        idle_cost: 0.0,
+    };
+
+    fn ack_from(drone_id: NodeId) -> DroneEvent {
+        DroneEvent::PacketSent(Packet::new_ack(
+            SourceRoutingHeader::new(vec![drone_id, 1, 6], 1),
+            0,
+            0,
+        ))
+    }
+
+    #[test]
+    fn test_depleted_battery_crashes_drone() {
+        let mut controller = _initialize_mock_network();
+        controller.batteries.insert(4, Battery::new(TEST_BATTERY));
+        controller.handle_event(ack_from(4), Instant::now());
+        assert!(controller.network_topology[&1].contains(&4));
+        controller.handle_event(ack_from(4), Instant::now());
+        assert!(!controller.batteries.contains_key(&4));
+        assert!(!controller.network_topology[&1].contains(&4));
+    }
+
+    #[test]
+    fn test_flood_response_charged_to_forwarding_drone() {
+        use wg_2024::packet::{FloodResponse, NodeType as PacketNodeType};
+
+        let mut controller = _initialize_mock_network();
+        controller.batteries.insert(3, Battery::new(TEST_BATTERY));
+        controller.batteries.insert(2, Battery::new(TEST_BATTERY));
+        let flood_response = Packet::new_flood_response(
+            SourceRoutingHeader::new(vec![7, 3, 2, 6], 2),
+            0,
+            FloodResponse {
+                flood_id: 0,
+                path_trace: vec![
+                    (6, PacketNodeType::Client),
+                    (2, PacketNodeType::Drone),
+                    (3, PacketNodeType::Drone),
+                    (7, PacketNodeType::Server),
+                ],
+            },
+        );
+        controller.handle_event(DroneEvent::PacketSent(flood_response), Instant::now());
+        assert_eq!(controller.batteries[&3].charge, 1.0);
+        assert_eq!(controller.batteries[&2].charge, 2.0);
+    }
+
+    #[test]
+    fn test_depleted_battery_keeps_needed_drone() {
+        let mut controller = _initialize_mock_network();
+        controller.batteries.insert(1, Battery::new(TEST_BATTERY));
+        controller.handle_event(ack_from(1), Instant::now());
+        controller.handle_event(ack_from(1), Instant::now());
+        // Drone 4 would be cut off, so drone 1 keeps running on an empty battery
+        assert!(controller.batteries[&1].is_empty());
+        assert!(controller.network_topology[&4].contains(&1));
+    }
 }
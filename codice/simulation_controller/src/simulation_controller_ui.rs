@@ -1,31 +1,81 @@
 #![allow(clippy::too_many_lines)]
 
+use crate::alerts::AnomalyDetector;
+use crate::event_batch::{is_sampled, EventBatcher};
+use crate::event_log::{EventLog, LogEntry, LogEventType, LogFilter, Severity};
+use crate::export::export_lines;
 use crate::forwarded_event::ForwardedEvent;
+use crate::latency::{LatencyHistogram, LatencyTracker};
 use crate::network_graph::NetworkGraph;
-use crate::node_stats::DroneStats;
+use crate::node_stats::{stats_by_implementation, DroneStats, EndpointStats, PDR_HISTORY_WINDOW};
 use crate::packet_animation::AnimationType;
-use crate::ui_commands::{UICommand, UIResponse};
+use crate::packet_crafting::{DraftKind, DraftNackType, MalformedPreset, PacketDraft};
+use crate::pdr_profile::PdrProfile;
+use crate::simulation_controller::NodeType;
+use crate::topology_analysis::{ImpactAction, ImpactPreview, TopologyAnalysis};
+use crate::ui_commands::{CommandId, CommandSender, UICommand, UIResponse};
+use crate::view_diff::ViewDiff;
+use common::introspection::DiscoveredView;
 use crossbeam_channel::Receiver;
 use crossbeam_channel::Sender;
 use eframe::egui;
 use egui::{Id, ScrollArea};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use wg_2024::network::NodeId;
 use wg_2024::packet::{Packet, PacketType};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SelectedTab {
+    Visualization,
+    Implementations,
+    Analysis,
+    Alerts,
+    EventLog,
+    PacketCrafting,
+    Latency,
+    Drone(NodeId),
+    Client(NodeId),
+    Server(NodeId),
+}
+
 pub struct SimulationControllerUI {
     drone_stats: HashMap<NodeId, DroneStats>,
-    selected_tab: usize,
-    ui_command_sender: Sender<UICommand>,
-    ui_response_receiver: Receiver<UIResponse>,
-    forwarded_event_receiver: Receiver<ForwardedEvent>,
+    endpoint_stats: HashMap<NodeId, EndpointStats>,
+    clients: Vec<NodeId>,
+    servers: Vec<NodeId>,
+    selected_tab: SelectedTab,
+    ui_command_sender: CommandSender,
+    ui_response_receiver: Receiver<(CommandId, UIResponse)>,
+    event_batcher: EventBatcher,
+    // One packet out of this many is animated while there is a backlog
+    animation_stride: u64,
+    // Packet events the controller skipped because the channel was full
+    skipped_events: u64,
     new_pdr: HashMap<NodeId, f32>,
+    new_pdr_profile: HashMap<NodeId, PdrProfile>,
     selected_add_neighbour: HashMap<NodeId, NodeId>,
     selected_remove_neighbour: HashMap<NodeId, NodeId>,
     snackbar: Option<(String, f64)>,
     snackbar_duration: f64,
     network_graph: NetworkGraph,
     packet_debug: bool,
+    anomaly_detector: AnomalyDetector,
+    alerts_export_path: String,
+    event_log: EventLog,
+    log_filter: LogFilter,
+    log_export_path: String,
+    packet_draft: PacketDraft,
+    latency: LatencyTracker,
+    node_ids: Vec<NodeId>,
+    // Commands sent from the graph still waiting for a response
+    awaiting_graph_responses: HashSet<CommandId>,
+    // Last response to a graph command: message, whether it failed, expiration
+    graph_feedback: Option<(String, bool, f64)>,
+    topology_analysis: TopologyAnalysis,
+    // Preview of the last crash or link removal requested, waiting to be confirmed
+    impact_preview: Option<ImpactPreview>,
+    // Last view reported by each client and server, compared with the network
+    discovered_views: HashMap<NodeId, (DiscoveredView, ViewDiff)>,
 }
 
 impl SimulationControllerUI {
@@ -33,30 +83,39 @@ impl SimulationControllerUI {
     #[must_use]
     pub fn new(
         drone_stats: HashMap<NodeId, DroneStats>,
-        ui_command_sender: Sender<UICommand>,
-        ui_response_receiver: Receiver<UIResponse>,
+        endpoint_stats: HashMap<NodeId, EndpointStats>,
+        ui_command_sender: Sender<(CommandId, UICommand)>,
+        ui_response_receiver: Receiver<(CommandId, UIResponse)>,
         forwarded_event_receiver: Receiver<ForwardedEvent>,
         clients: Vec<NodeId>,
         servers: Vec<NodeId>,
     ) -> Self {
-        env_logger::init();
+        let _ = env_logger::try_init();
 
         let mut new_pdr = HashMap::new();
         for (drone_id, drone) in &drone_stats {
             new_pdr.insert(*drone_id, drone.pdr);
         }
 
-        let mut selected_add_neighbour = HashMap::new();
+        let mut new_pdr_profile = HashMap::new();
         for drone_id in drone_stats.keys() {
-            selected_add_neighbour.insert(*drone_id, 0);
+            new_pdr_profile.insert(*drone_id, PdrProfile::DEFAULTS[0]);
+        }
+
+        let mut selected_add_neighbour = HashMap::new();
+        for node_id in drone_stats.keys().chain(endpoint_stats.keys()) {
+            selected_add_neighbour.insert(*node_id, 0);
         }
 
         let mut selected_remove_neighbour = HashMap::new();
-        for drone_id in drone_stats.keys() {
-            selected_remove_neighbour.insert(*drone_id, 0);
+        for node_id in drone_stats.keys().chain(endpoint_stats.keys()) {
+            selected_remove_neighbour.insert(*node_id, 0);
         }
 
-        let drones = drone_stats.keys().copied().collect::<Vec<_>>();
+        let drones = drone_stats
+            .iter()
+            .map(|(drone_id, drone)| (*drone_id, drone.implementation.clone()))
+            .collect::<Vec<_>>();
 
         let mut canonical_edges = HashSet::new();
 
@@ -71,87 +130,113 @@ impl SimulationControllerUI {
 
         let edges = canonical_edges.into_iter().collect();
 
+        let mut node_ids = drone_stats.keys().copied().collect::<Vec<_>>();
+        node_ids.extend(&clients);
+        node_ids.extend(&servers);
+        node_ids.sort_unstable();
+
+        let mut sorted_clients = clients.clone();
+        sorted_clients.sort_unstable();
+        let mut sorted_servers = servers.clone();
+        sorted_servers.sort_unstable();
+
         Self {
-            selected_tab: 0,
+            selected_tab: SelectedTab::Visualization,
             drone_stats,
-            ui_command_sender,
+            endpoint_stats,
+            clients: sorted_clients,
+            servers: sorted_servers,
+            ui_command_sender: CommandSender::new(ui_command_sender),
             ui_response_receiver,
-            forwarded_event_receiver,
+            event_batcher: EventBatcher::new(forwarded_event_receiver),
+            animation_stride: 1,
+            skipped_events: 0,
             new_pdr,
+            new_pdr_profile,
             selected_add_neighbour,
             selected_remove_neighbour,
             snackbar: None,
             snackbar_duration: 2.0,
             network_graph: NetworkGraph::new(drones, clients, servers, edges),
             packet_debug: false,
+            anomaly_detector: AnomalyDetector::default(),
+            alerts_export_path: "alerts.log".to_string(),
+            event_log: EventLog::default(),
+            log_filter: LogFilter::default(),
+            log_export_path: "events.log".to_string(),
+            packet_draft: PacketDraft::default(),
+            latency: LatencyTracker::default(),
+            node_ids,
+            awaiting_graph_responses: HashSet::new(),
+            graph_feedback: None,
+            topology_analysis: TopologyAnalysis::default(),
+            impact_preview: None,
+            discovered_views: HashMap::new(),
+        }
+    }
+
+    /// Handles the forwarded events that fit in the budget of a frame, animating only
+    /// a sample of the packets when too many are waiting. Returns how many were handled.
+    pub fn process_forwarded_events(&mut self, now: f64) -> usize {
+        self.animation_stride = self.event_batcher.animation_stride();
+        let mut budget = self.event_batcher.start_frame();
+        while let Some(event) = self.event_batcher.next(&mut budget) {
+            self.handle_forwarded_event(event, now);
         }
+        self.event_log.log_summaries(now);
+        budget.handled()
     }
 
-    fn handle_forwarded_event(&mut self, event: ForwardedEvent) {
+    /// Events received from the controller and not handled yet
+    #[must_use]
+    pub fn backlog(&self) -> usize {
+        self.event_batcher.backlog()
+    }
+
+    fn handle_forwarded_event(&mut self, event: ForwardedEvent, now: f64) {
+        self.event_log.log_event(now, &event);
+
         match event {
-            ForwardedEvent::PacketSent(packet) => {
+            ForwardedEvent::PacketSent(packet, received_at) => {
+                self.latency.packet_sent(&packet, received_at);
                 let node_id = match packet.pack_type {
-                    PacketType::MsgFragment(_) | PacketType::Ack(_) | PacketType::Nack(_) => {
-                        let (start, dest) = Self::get_start_dest_from_packet(&packet);
-                        let packet_id = (packet.get_fragment_index(), packet.session_id);
-                        let animation_type = match packet.pack_type {
-                            PacketType::Ack(_) => AnimationType::Ack,
-                            PacketType::Nack(_) => AnimationType::Nack,
-                            _ => AnimationType::Fragment,
-                        };
-                        if packet.routing_header.hop_index == 2 {
-                            let start = packet.routing_header.hops[0];
-                            let end = packet.routing_header.hops[1];
-                            self.network_graph.add_packet_animation(
-                                packet_id,
-                                start,
-                                end,
-                                animation_type,
-                            );
-                        }
-                        self.network_graph.add_packet_animation(
-                            packet_id,
-                            start,
-                            dest,
-                            animation_type,
-                        );
+                    PacketType::MsgFragment(_)
+                    | PacketType::Ack(_)
+                    | PacketType::Nack(_)
+                    | PacketType::FloodResponse(_) => {
+                        self.animate_routed_packet(&packet);
                         packet
                             .routing_header
                             .previous_hop()
                             .expect("there should always be a previous hop")
                     }
                     PacketType::FloodRequest(ref flood_request) => {
+                        let path_trace = flood_request
+                            .path_trace
+                            .iter()
+                            .map(|(node_id, _)| *node_id)
+                            .collect::<Vec<_>>();
+                        self.network_graph.add_flood_wave(
+                            flood_request.flood_id,
+                            flood_request.initiator_id,
+                            &path_trace,
+                        );
                         flood_request
                             .path_trace
                             .last()
                             .expect("Flood request should always have a last hop")
                             .0
                     }
-                    PacketType::FloodResponse(ref flood_response) => {
-                        flood_response
-                            .path_trace
-                            .last()
-                            .expect("Flood response should always have a last hop")
-                            .0
-                    }
                 };
-                if let Some(stats) = self.drone_stats.get_mut(&node_id) {
-                    stats.packets_forwarded += 1;
-                    match packet.pack_type {
-                        PacketType::MsgFragment(_) => stats.fragments_forwarded += 1,
-                        PacketType::Ack(_) => stats.acks_forwarded += 1,
-                        PacketType::Nack(_) => stats.nacks_forwarded += 1,
-                        PacketType::FloodRequest(_) => stats.flood_requests_forwarded += 1,
-                        PacketType::FloodResponse(_) => stats.flood_responses_forwarded += 1,
-                    }
-                }
+                self.anomaly_detector
+                    .packet_sent(now, node_id, &packet, &self.drone_stats);
                 self.drone_stats
                     .get_mut(&node_id)
                     .expect(&format!("Drone should exist, fragment: {node_id}"))
-                    .packets_sent
-                    .push(packet);
+                    .record_packet(packet);
             }
-            ForwardedEvent::PacketDropped(packet) => {
+            ForwardedEvent::PacketDropped(packet, _) => {
+                self.latency.packet_dropped(&packet);
                 let packet_id = (packet.get_fragment_index(), packet.session_id);
                 let animation_type = match packet.pack_type {
                     PacketType::Ack(_) => AnimationType::Ack,
@@ -159,7 +244,9 @@ impl SimulationControllerUI {
                     _ => AnimationType::Fragment,
                 };
 
-                if packet.routing_header.hop_index == 1 {
+                if packet.routing_header.hop_index == 1
+                    && is_sampled(&packet, self.animation_stride)
+                {
                     let start = packet.routing_header.hops[0];
                     let end = packet.routing_header.hops[1];
                     self.network_graph
@@ -170,26 +257,79 @@ impl SimulationControllerUI {
                     .routing_header
                     .current_hop()
                     .expect("Previous hop should always be valid");
-                if let Some(stats) = self.drone_stats.get_mut(&node_id) {
-                    stats.packets_dropped += 1;
-                }
+                self.anomaly_detector
+                    .packet_dropped(now, node_id, &packet, &self.drone_stats);
                 self.drone_stats
                     .get_mut(&node_id)
                     .expect(format!("Drone should exist, dropped: {node_id}").as_str())
-                    .packets_sent
-                    .push(packet);
+                    .record_packet(packet);
+            }
+            ForwardedEvent::TrafficCounted(traffic) => {
+                self.skipped_events += u64::from(traffic.skipped_events);
+                for (node_id, drone_traffic) in &traffic.drones {
+                    if let Some(stats) = self.drone_stats.get_mut(node_id) {
+                        stats.add_traffic(drone_traffic);
+                    }
+                }
+                for (node_id, endpoint_traffic) in &traffic.endpoints {
+                    if let Some(stats) = self.endpoint_stats.get_mut(node_id) {
+                        stats.add_traffic(endpoint_traffic);
+                    }
+                }
+            }
+            ForwardedEvent::ShortcutDelivered(packet, _) => {
+                if let Some(stats) = packet
+                    .routing_header
+                    .hops
+                    .last()
+                    .and_then(|destination| self.endpoint_stats.get_mut(destination))
+                {
+                    stats.shortcuts_received += 1;
+                }
             }
             ForwardedEvent::PDRSet(node_id, pdr) => {
                 if let Some(stats) = self.drone_stats.get_mut(&node_id) {
-                    stats.pdr = pdr;
+                    stats.record_pdr(now, pdr);
+                }
+            }
+            ForwardedEvent::PDRProfileSet(node_id, profile) => {
+                if let Some(stats) = self.drone_stats.get_mut(&node_id) {
+                    stats.pdr_profile = profile;
                 }
             }
             ForwardedEvent::DroneCrashed(node_id) => {
                 if let Some(stats) = self.drone_stats.get_mut(&node_id) {
                     stats.crashed = true;
                 }
+                for stats in self.endpoint_stats.values_mut() {
+                    stats.neighbours.remove(&node_id);
+                }
+                self.anomaly_detector.forget_node(node_id);
                 self.network_graph.crash_drone(node_id);
             }
+            ForwardedEvent::BatteryLevels(batteries) => {
+                for (node_id, battery) in batteries {
+                    if let Some(stats) = self.drone_stats.get_mut(&node_id) {
+                        stats.battery = Some(battery);
+                    }
+                    self.network_graph
+                        .set_battery_level(node_id, battery.level());
+                }
+            }
+            ForwardedEvent::BatteryDepleted(node_id, result) => {
+                if let Some(stats) = self.drone_stats.get_mut(&node_id) {
+                    if let Some(battery) = &mut stats.battery {
+                        battery.charge = 0.0;
+                    }
+                }
+                self.network_graph.set_battery_level(node_id, 0.0);
+                if let Err(e) = result {
+                    self.snackbar = Some((
+                        format!("Drone {node_id} ran out of battery but can't crash: {e}"),
+                        self.snackbar_duration + now,
+                    ));
+                }
+            }
             ForwardedEvent::ConnectionAdded(node1, node2) => {
                 if let Some(stats) = self.drone_stats.get_mut(&node1) {
                     stats.neigbours.insert(node2);
@@ -197,6 +337,12 @@ impl SimulationControllerUI {
                 if let Some(stats) = self.drone_stats.get_mut(&node2) {
                     stats.neigbours.insert(node1);
                 }
+                if let Some(stats) = self.endpoint_stats.get_mut(&node1) {
+                    stats.neighbours.insert(node2);
+                }
+                if let Some(stats) = self.endpoint_stats.get_mut(&node2) {
+                    stats.neighbours.insert(node1);
+                }
 
                 self.network_graph.add_connection(node1, node2);
             }
@@ -207,9 +353,53 @@ impl SimulationControllerUI {
                 if let Some(stats) = self.drone_stats.get_mut(&node2) {
                     stats.neigbours.remove(&node1);
                 }
+                if let Some(stats) = self.endpoint_stats.get_mut(&node1) {
+                    stats.neighbours.remove(&node2);
+                }
+                if let Some(stats) = self.endpoint_stats.get_mut(&node2) {
+                    stats.neighbours.remove(&node1);
+                }
                 self.network_graph.remove_connection(node1, node2);
             }
+            ForwardedEvent::NodesMoved(positions) => {
+                self.network_graph.set_positions(&positions);
+            }
+            ForwardedEvent::TopologyAnalysed(analysis) => {
+                self.network_graph
+                    .set_critical_nodes(&analysis.articulation_points);
+                self.topology_analysis = analysis;
+            }
+            ForwardedEvent::ImpactPreviewed(preview) => {
+                self.impact_preview = Some(preview);
+            }
+            ForwardedEvent::ViewDiscovered(view, diff) => {
+                self.discovered_views.insert(view.node_id, (view, diff));
+            }
+        }
+    }
+
+    /// Animates the hop of a source routed packet, along with the first hop
+    /// when it comes from a client or server, which does not notify the controller
+    fn animate_routed_packet(&mut self, packet: &Packet) {
+        if !is_sampled(packet, self.animation_stride) {
+            return;
+        }
+        let (start, dest) = Self::get_start_dest_from_packet(packet);
+        let packet_id = (packet.get_fragment_index(), packet.session_id);
+        let animation_type = match packet.pack_type {
+            PacketType::Ack(_) => AnimationType::Ack,
+            PacketType::Nack(_) => AnimationType::Nack,
+            PacketType::FloodResponse(_) => AnimationType::FloodResponse,
+            _ => AnimationType::Fragment,
+        };
+        if packet.routing_header.hop_index == 2 {
+            let start = packet.routing_header.hops[0];
+            let end = packet.routing_header.hops[1];
+            self.network_graph
+                .add_packet_animation(packet_id, start, end, animation_type);
         }
+        self.network_graph
+            .add_packet_animation(packet_id, start, dest, animation_type);
     }
 
     fn drone_stats_ui(&mut self, ui: &mut egui::Ui, drone_id: NodeId, now: f64) {
@@ -221,6 +411,7 @@ impl SimulationControllerUI {
         ui.separator();
 
         ui.label(format!("Drone ID: {drone_id}",));
+        ui.label(format!("Implementation: {}", drone_stats.implementation));
         ui.label(format!("Neighbours: {:?}", drone_stats.neigbours));
         ui.label(format!(
             "Packets forwarded: {}",
@@ -239,6 +430,19 @@ impl SimulationControllerUI {
         ui.label(format!("NACKs forwarded: {}", drone_stats.nacks_forwarded));
         ui.label(format!("Crashed: {}", drone_stats.crashed));
         ui.label(format!("PDR: {}", drone_stats.pdr));
+        ui.label(format!(
+            "PDR profile: {}",
+            drone_stats
+                .pdr_profile
+                .map_or("None", |profile| profile.name())
+        ));
+        if let Some(battery) = drone_stats.battery {
+            ui.add(egui::ProgressBar::new(battery.level()).text(format!(
+                "Battery: {:.1} / {:.1}",
+                battery.charge, battery.config.capacity
+            )));
+        }
+        Self::pdr_chart_ui(ui, drone_stats, now);
         ui.separator();
 
         if ui.button("Crash").clicked() {
@@ -249,7 +453,7 @@ impl SimulationControllerUI {
                 ));
             } else {
                 self.ui_command_sender
-                    .send(UICommand::CrashDrone(drone_id))
+                    .send(UICommand::PreviewImpact(ImpactAction::Crash(drone_id)))
                     .expect("Should be able to send the command");
             }
         }
@@ -285,6 +489,48 @@ impl SimulationControllerUI {
             }
         });
 
+        ui.horizontal(|ui| {
+            let profile = self
+                .new_pdr_profile
+                .get_mut(&drone_id)
+                .expect("Should be able to get the PDR profile");
+            ui.label("PDR profile: ");
+
+            egui::ComboBox::new(2, "")
+                .selected_text(profile.name())
+                .show_ui(ui, |ui| {
+                    for default in PdrProfile::DEFAULTS {
+                        if ui
+                            .selectable_label(profile.name() == default.name(), default.name())
+                            .clicked()
+                        {
+                            *profile = default;
+                        }
+                    }
+                });
+            Self::pdr_profile_editor_ui(ui, profile);
+        });
+
+        ui.horizontal(|ui| {
+            let profile = self
+                .new_pdr_profile
+                .get(&drone_id)
+                .expect("Should be able to get the PDR profile");
+            let set = ui.button("Set profile").clicked();
+            let remove = ui.button("Remove profile").clicked();
+
+            if (set || remove) && drone_stats.crashed {
+                self.snackbar = Some((
+                    "Can't send command to crashed drone".to_string(),
+                    self.snackbar_duration + now,
+                ));
+            } else if set || remove {
+                self.ui_command_sender
+                    .send(UICommand::SetPDRProfile(drone_id, set.then_some(*profile)))
+                    .expect("Should be able to send the command");
+            }
+        });
+
         ui.separator();
 
         ui.horizontal(|ui| {
@@ -344,12 +590,21 @@ impl SimulationControllerUI {
                     ));
                 } else {
                     self.ui_command_sender
-                        .send(UICommand::RemoveConnection(drone_id, *selected))
+                        .send(UICommand::PreviewImpact(ImpactAction::RemoveConnection(
+                            drone_id, *selected,
+                        )))
                         .expect("Should be able to send the command");
                     *selected = 0;
                 }
             }
         });
+
+        Self::impact_preview_ui(
+            ui,
+            &mut self.impact_preview,
+            &self.ui_command_sender,
+            Some(drone_id),
+        );
         ui.separator();
 
         ui.checkbox(&mut self.packet_debug, "Toggle packet debug");
@@ -365,36 +620,1009 @@ impl SimulationControllerUI {
         }
     }
 
+    /// Shows the neighbours and traffic of a client or a server, with the link actions
+    /// allowed by its degree limits: a client has 1 or 2 drones, a server at least 2
+    fn endpoint_stats_ui(&mut self, ui: &mut egui::Ui, node_id: NodeId, is_client: bool) {
+        let stats = self
+            .endpoint_stats
+            .get(&node_id)
+            .expect("Should be able to get the node");
+        let links = stats.neighbours.len();
+        let (role, can_add, can_remove, limits) = if is_client {
+            (
+                "Client",
+                links < 2,
+                links > 1,
+                "a client needs 1 or 2 drones",
+            )
+        } else {
+            (
+                "Server",
+                true,
+                links > 2,
+                "a server needs at least 2 drones",
+            )
+        };
+
+        let mut neighbours = stats.neighbours.iter().copied().collect::<Vec<_>>();
+        neighbours.sort_unstable();
+
+        ui.separator();
+
+        ui.label(format!("{role} ID: {node_id}"));
+        if is_client {
+            ui.label(format!("Implementation: {}", stats.kind));
+        } else {
+            ui.label(format!("Server kind: {}", stats.kind));
+        }
+        ui.label(format!("Neighbours: {neighbours:?}"));
+        ui.label(format!("Links: {links} ({limits})"));
+        ui.separator();
+
+        ui.label(format!("Packets sent: {}", stats.packets_sent()));
+        ui.label(format!(
+            "Fragments: {}, ACKs: {}, NACKs: {}, flood requests: {}, flood responses: {}",
+            stats.fragments_sent,
+            stats.acks_sent,
+            stats.nacks_sent,
+            stats.flood_requests_sent,
+            stats.flood_responses_sent
+        ));
+        ui.label(format!("Packets received: {}", stats.packets_received()));
+        ui.label(format!(
+            "Fragments: {}, ACKs: {}, NACKs: {}, flood responses: {}, through the controller: {}",
+            stats.fragments_received,
+            stats.acks_received,
+            stats.nacks_received,
+            stats.flood_responses_received,
+            stats.shortcuts_received
+        ));
+        ui.label(format!("Packets lost on the way: {}", stats.packets_lost));
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            let selected = self
+                .selected_add_neighbour
+                .get_mut(&node_id)
+                .expect("Should be able to get the selected neighbour");
+            ui.label("Add link to drone: ");
+
+            egui::ComboBox::new(0, "")
+                .selected_text(format!("{selected}"))
+                .show_ui(ui, |ui| {
+                    let mut drones = self
+                        .drone_stats
+                        .iter()
+                        .filter(|(drone_id, drone)| {
+                            !drone.crashed && !stats.neighbours.contains(drone_id)
+                        })
+                        .map(|(drone_id, _)| *drone_id)
+                        .collect::<Vec<_>>();
+                    drones.sort_unstable();
+                    for drone in drones {
+                        ui.selectable_value(selected, drone, drone.to_string());
+                    }
+                });
+            if ui
+                .add_enabled(can_add, egui::Button::new("Add"))
+                .on_disabled_hover_text(format!("{role} {node_id} has {links} links, {limits}"))
+                .clicked()
+                && *selected != 0
+            {
+                self.ui_command_sender
+                    .send(UICommand::AddConnection(node_id, *selected))
+                    .expect("Should be able to send the command");
+                *selected = 0;
+            }
+        });
+
+        ui.horizontal(|ui| {
+            let selected = self
+                .selected_remove_neighbour
+                .get_mut(&node_id)
+                .expect("Should be able to get the selected neighbour");
+            ui.label("Remove link to drone: ");
+
+            egui::ComboBox::new(1, "")
+                .selected_text(format!("{selected}"))
+                .show_ui(ui, |ui| {
+                    for drone in &neighbours {
+                        ui.selectable_value(selected, *drone, drone.to_string());
+                    }
+                });
+            if ui
+                .add_enabled(can_remove, egui::Button::new("Remove"))
+                .on_disabled_hover_text(format!("{role} {node_id} has {links} links, {limits}"))
+                .clicked()
+                && *selected != 0
+            {
+                self.ui_command_sender
+                    .send(UICommand::PreviewImpact(ImpactAction::RemoveConnection(
+                        node_id, *selected,
+                    )))
+                    .expect("Should be able to send the command");
+                *selected = 0;
+            }
+        });
+
+        Self::impact_preview_ui(
+            ui,
+            &mut self.impact_preview,
+            &self.ui_command_sender,
+            Some(node_id),
+        );
+
+        ui.separator();
+        if ui.button("Inspect discovered view").clicked() {
+            self.ui_command_sender
+                .send(UICommand::InspectEndpoint(node_id))
+                .expect("Should be able to send the command");
+        }
+        if let Some((view, diff)) = self.discovered_views.get(&node_id) {
+            Self::discovered_view_ui(ui, view, diff);
+        }
+    }
+
+    /// Shows what an endpoint knows about the network and where it is wrong
+    fn discovered_view_ui(ui: &mut egui::Ui, view: &DiscoveredView, diff: &ViewDiff) {
+        let links = view.graph.values().map(HashSet::len).sum::<usize>() / 2;
+        ui.label(format!(
+            "Discovered {} nodes and {links} links",
+            view.graph.len()
+        ));
+        ui.label(format!("Pending sessions: {:?}", view.pending_sessions));
+        ui.label(format!("Known servers: {:?}", view.known_servers));
+        ui.label(format!("Known clients: {:?}", view.known_clients));
+
+        if diff.is_empty() {
+            ui.colored_label(egui::Color32::GREEN, "The view matches the network");
+            return;
+        }
+        if !diff.missing_edges.is_empty() {
+            ui.colored_label(
+                egui::Color32::ORANGE,
+                format!("Missing links: {:?}", diff.missing_edges),
+            );
+        }
+        if !diff.stale_edges.is_empty() {
+            ui.colored_label(
+                egui::Color32::RED,
+                format!("Stale links: {:?}", diff.stale_edges),
+            );
+        }
+        if !diff.crashed_nodes.is_empty() {
+            ui.colored_label(
+                egui::Color32::RED,
+                format!("Crashed drones still known: {:?}", diff.crashed_nodes),
+            );
+        }
+        if !diff.unknown_nodes.is_empty() {
+            ui.colored_label(
+                egui::Color32::ORANGE,
+                format!("Unknown nodes: {:?}", diff.unknown_nodes),
+            );
+        }
+    }
+
+    /// Shows the preview of an action on the node, or of any action for `None`,
+    /// which is only sent once confirmed. Returns the id of the command confirmed
+    fn impact_preview_ui(
+        ui: &mut egui::Ui,
+        impact_preview: &mut Option<ImpactPreview>,
+        ui_command_sender: &CommandSender,
+        node_id: Option<NodeId>,
+    ) -> Option<CommandId> {
+        let Some(preview) = impact_preview else {
+            return None;
+        };
+        let (command, involves_node) = match preview.action {
+            ImpactAction::Crash(drone_id) => (
+                UICommand::CrashDrone(drone_id),
+                node_id.is_none_or(|node_id| drone_id == node_id),
+            ),
+            ImpactAction::RemoveConnection(node1, node2) => (
+                UICommand::RemoveConnection(node1, node2),
+                node_id.is_none_or(|node_id| node1 == node_id || node2 == node_id),
+            ),
+        };
+        if !involves_node {
+            return None;
+        }
+
+        ui.separator();
+        ui.strong(format!("Impact of {}", preview.action));
+
+        match &preview.failed_invariant {
+            Some(invariant) => {
+                ui.colored_label(egui::Color32::RED, format!("Not allowed: {invariant}"));
+            }
+            None => {
+                ui.label("No invariant is broken");
+            }
+        }
+
+        if preview.longer_paths.is_empty() {
+            ui.label("No client to server path gets longer");
+        }
+        for (client, server, hops_before, hops_after) in &preview.longer_paths {
+            match hops_after {
+                Some(hops_after) => ui.label(format!(
+                    "Client {client} to server {server}: {hops_before} -> {hops_after} hops (+{})",
+                    hops_after - hops_before
+                )),
+                None => ui.colored_label(
+                    egui::Color32::RED,
+                    format!("Client {client} to server {server}: no path left"),
+                ),
+            };
+        }
+
+        for (client, server, paths) in &preview.lost_alternatives {
+            ui.colored_label(
+                egui::Color32::ORANGE,
+                format!("Client {client} to server {server}: {paths} disjoint path(s) left"),
+            );
+        }
+
+        let allowed = preview.failed_invariant.is_none();
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(allowed, egui::Button::new("Confirm"))
+                .clicked()
+            {
+                *impact_preview = None;
+                Some(
+                    ui_command_sender
+                        .send(command)
+                        .expect("Should be able to send the command"),
+                )
+            } else {
+                if ui.button("Cancel").clicked() {
+                    *impact_preview = None;
+                }
+                None
+            }
+        })
+        .inner
+    }
+
+    /// Shows the fields of `profile` as editable values
+    fn pdr_profile_editor_ui(ui: &mut egui::Ui, profile: &mut PdrProfile) {
+        let rate = |ui: &mut egui::Ui, value: &mut f32, name: &str| {
+            ui.add(
+                egui::DragValue::new(value)
+                    .range(0.0..=1.0)
+                    .speed(0.01)
+                    .prefix(format!("{name}: ")),
+            );
+        };
+        let seconds = |ui: &mut egui::Ui, value: &mut f64, name: &str| {
+            ui.add(
+                egui::DragValue::new(value)
+                    .range(0.1..=600.0)
+                    .speed(0.5)
+                    .prefix(format!("{name}: "))
+                    .suffix(" s"),
+            );
+        };
+
+        match profile {
+            PdrProfile::Step { before, after, at } => {
+                rate(ui, before, "before");
+                rate(ui, after, "after");
+                seconds(ui, at, "at");
+            }
+            PdrProfile::Ramp { from, to, duration } => {
+                rate(ui, from, "from");
+                rate(ui, to, "to");
+                seconds(ui, duration, "duration");
+            }
+            PdrProfile::Sine {
+                mean,
+                amplitude,
+                period,
+            } => {
+                rate(ui, mean, "mean");
+                rate(ui, amplitude, "amplitude");
+                seconds(ui, period, "period");
+            }
+            PdrProfile::RandomWalk { start, step } => {
+                rate(ui, start, "start");
+                rate(ui, step, "step");
+            }
+            PdrProfile::GilbertElliott {
+                good_pdr,
+                bad_pdr,
+                p_good_to_bad,
+                p_bad_to_good,
+            } => {
+                rate(ui, good_pdr, "good");
+                rate(ui, bad_pdr, "bad");
+                rate(ui, p_good_to_bad, "good to bad");
+                rate(ui, p_bad_to_good, "bad to good");
+            }
+        }
+    }
+
+    /// Draws the PDR of the last `PDR_HISTORY_WINDOW` seconds as a step line
+    #[allow(clippy::cast_possible_truncation)]
+    fn pdr_chart_ui(ui: &mut egui::Ui, drone_stats: &DroneStats, now: f64) {
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width(), 100.0),
+            egui::Sense::hover(),
+        );
+        let painter = ui.painter_at(rect);
+        painter.rect_stroke(
+            rect,
+            0.0,
+            ui.visuals().widgets.noninteractive.bg_stroke,
+            egui::StrokeKind::Inside,
+        );
+
+        let start = now - PDR_HISTORY_WINDOW;
+        let to_screen = |time: f64, pdr: f32| {
+            let x = ((time.max(start) - start) / PDR_HISTORY_WINDOW) as f32;
+            egui::pos2(
+                rect.left() + x * rect.width(),
+                rect.bottom() - pdr * rect.height(),
+            )
+        };
+
+        let first_pdr = drone_stats
+            .pdr_history
+            .first()
+            .map_or(drone_stats.pdr, |(_, pdr)| *pdr);
+        let mut points = vec![to_screen(start, first_pdr)];
+        let mut last_pdr = first_pdr;
+        for &(time, pdr) in &drone_stats.pdr_history {
+            points.push(to_screen(time, last_pdr));
+            points.push(to_screen(time, pdr));
+            last_pdr = pdr;
+        }
+        points.push(to_screen(now, last_pdr));
+
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.5, egui::Color32::LIGHT_BLUE),
+        ));
+        painter.text(
+            rect.left_top(),
+            egui::Align2::LEFT_TOP,
+            "1.0",
+            egui::FontId::monospace(10.0),
+            ui.visuals().weak_text_color(),
+        );
+        painter.text(
+            rect.left_bottom(),
+            egui::Align2::LEFT_BOTTOM,
+            format!("0.0  (last {PDR_HISTORY_WINDOW} s)"),
+            egui::FontId::monospace(10.0),
+            ui.visuals().weak_text_color(),
+        );
+    }
+
+    /// Warns that a panel fed by the packet events may be missing some of them
+    fn skipped_events_warning(&self, ui: &mut egui::Ui) {
+        if self.skipped_events > 0 {
+            ui.colored_label(
+                egui::Color32::ORANGE,
+                format!(
+                    "{} packet events were skipped while the UI was behind, these results are partial",
+                    self.skipped_events
+                ),
+            );
+        }
+    }
+
+    /// Lists the alerts raised by the anomaly detector, newest first
+    fn alerts_ui(&mut self, ui: &mut egui::Ui, now: f64) {
+        ui.separator();
+        self.skipped_events_warning(ui);
+
+        ui.horizontal(|ui| {
+            if ui.button("Clear").clicked() {
+                self.anomaly_detector.clear();
+            }
+            ui.label("Export to: ");
+            ui.text_edit_singleline(&mut self.alerts_export_path);
+            if ui.button("Export").clicked() {
+                let message =
+                    match export_lines(&self.alerts_export_path, self.anomaly_detector.alerts()) {
+                        Ok(()) => format!("Alerts exported to {}", self.alerts_export_path),
+                        Err(e) => e,
+                    };
+                self.snackbar = Some((message, self.snackbar_duration + now));
+            }
+        });
+        ui.separator();
+
+        ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("alerts_grid").striped(true).show(ui, |ui| {
+                ui.strong("Time");
+                ui.strong("Alert");
+                ui.strong("Node");
+                ui.strong("Details");
+                ui.end_row();
+
+                for alert in self.anomaly_detector.alerts().iter().rev() {
+                    ui.label(format!("{:.1} s", alert.time));
+                    ui.colored_label(egui::Color32::ORANGE, alert.kind.to_string());
+                    ui.label(alert.node.to_string());
+                    ui.label(&alert.message);
+                    ui.end_row();
+                }
+            });
+        });
+    }
+
+    /// Neighbours and types of the nodes that are still running, as known by the UI.
+    /// Crashed drones are left out of the nodes and of every neighbour set.
+    fn known_topology(&self) -> (HashMap<NodeId, HashSet<NodeId>>, HashMap<NodeId, NodeType>) {
+        let running = |neighbours: &HashSet<NodeId>| {
+            neighbours
+                .iter()
+                .filter(|node_id| !self.drone_stats.get(node_id).is_some_and(|s| s.crashed))
+                .copied()
+                .collect::<HashSet<_>>()
+        };
+        let mut topology = HashMap::new();
+        let mut node_types = HashMap::new();
+        for (drone_id, stats) in self.drone_stats.iter().filter(|(_, s)| !s.crashed) {
+            topology.insert(*drone_id, running(&stats.neigbours));
+            node_types.insert(*drone_id, NodeType::Drone);
+        }
+        for (node_id, stats) in &self.endpoint_stats {
+            topology.insert(*node_id, running(&stats.neighbours));
+            let node_type = if self.clients.contains(node_id) {
+                NodeType::Client
+            } else {
+                NodeType::Server
+            };
+            node_types.insert(*node_id, node_type);
+        }
+        (topology, node_types)
+    }
+
+    /// Builds a packet field by field and injects it into the channel of any node
+    fn packet_crafting_ui(&mut self, ui: &mut egui::Ui, now: f64) {
+        let (topology, node_types) = self.known_topology();
+
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            ui.label("Presets: ");
+            for preset in MalformedPreset::ALL {
+                if ui.button(preset.to_string()).clicked() {
+                    match PacketDraft::preset(preset, &topology, &node_types) {
+                        Ok(draft) => self.packet_draft = draft,
+                        Err(e) => self.snackbar = Some((e, self.snackbar_duration + now)),
+                    }
+                }
+            }
+        });
+        ui.separator();
+
+        let draft = &mut self.packet_draft;
+
+        ui.horizontal(|ui| {
+            ui.label("Inject into: ");
+            egui::ComboBox::new("crafting_target", "")
+                .selected_text(draft.target.to_string())
+                .show_ui(ui, |ui| {
+                    for node in &self.node_ids {
+                        ui.selectable_value(&mut draft.target, *node, node.to_string());
+                    }
+                });
+
+            ui.label("Type: ");
+            egui::ComboBox::new("crafting_kind", "")
+                .selected_text(draft.kind.to_string())
+                .show_ui(ui, |ui| {
+                    for kind in DraftKind::ALL {
+                        ui.selectable_value(&mut draft.kind, kind, kind.to_string());
+                    }
+                });
+
+            ui.label("Session: ");
+            ui.add(egui::DragValue::new(&mut draft.session_id));
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("Hops: ");
+            ui.text_edit_singleline(&mut draft.hops);
+            ui.label("Hop index: ");
+            ui.add(egui::DragValue::new(&mut draft.hop_index));
+        });
+
+        ui.horizontal(|ui| match draft.kind {
+            DraftKind::Fragment => {
+                ui.label("Fragment index: ");
+                ui.add(egui::DragValue::new(&mut draft.fragment_index));
+                ui.label("Total fragments: ");
+                ui.add(egui::DragValue::new(&mut draft.total_n_fragments));
+                ui.label("Data: ");
+                ui.text_edit_singleline(&mut draft.data);
+            }
+            DraftKind::Ack => {
+                ui.label("Fragment index: ");
+                ui.add(egui::DragValue::new(&mut draft.fragment_index));
+            }
+            DraftKind::Nack => {
+                ui.label("Fragment index: ");
+                ui.add(egui::DragValue::new(&mut draft.fragment_index));
+                ui.label("NACK type: ");
+                egui::ComboBox::new("crafting_nack_type", "")
+                    .selected_text(draft.nack_type.to_string())
+                    .show_ui(ui, |ui| {
+                        for nack_type in DraftNackType::ALL {
+                            ui.selectable_value(
+                                &mut draft.nack_type,
+                                nack_type,
+                                nack_type.to_string(),
+                            );
+                        }
+                    });
+                if matches!(
+                    draft.nack_type,
+                    DraftNackType::ErrorInRouting | DraftNackType::UnexpectedRecipient
+                ) {
+                    ui.label("Node: ");
+                    ui.add(egui::DragValue::new(&mut draft.nack_node));
+                }
+            }
+            DraftKind::FloodRequest | DraftKind::FloodResponse => {
+                ui.label("Flood ID: ");
+                ui.add(egui::DragValue::new(&mut draft.flood_id));
+                if draft.kind == DraftKind::FloodRequest {
+                    ui.label("Initiator: ");
+                    ui.add(egui::DragValue::new(&mut draft.initiator_id));
+                }
+                ui.label("Path trace: ");
+                ui.text_edit_singleline(&mut draft.path_trace);
+            }
+        });
+        ui.separator();
+
+        match draft.build(&node_types) {
+            Ok(packet) => {
+                ui.label(format!("{packet:?}"));
+                if ui.button("Inject").clicked() {
+                    self.ui_command_sender
+                        .send(UICommand::InjectPacket(draft.target, packet))
+                        .expect("Should be able to send the command");
+                }
+            }
+            Err(e) => {
+                ui.colored_label(egui::Color32::RED, e);
+            }
+        }
+    }
+
+    /// Shows the event log entries matching the filter, oldest first
+    fn event_log_ui(&mut self, ui: &mut egui::Ui, now: f64) {
+        ui.separator();
+
+        ui.horizontal(|ui| {
+            let filter = &mut self.log_filter;
+
+            ui.label("Node: ");
+            egui::ComboBox::new("log_node_filter", "")
+                .selected_text(
+                    filter
+                        .node
+                        .map_or("All".to_string(), |node| node.to_string()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut filter.node, None, "All");
+                    for node in &self.node_ids {
+                        ui.selectable_value(&mut filter.node, Some(*node), node.to_string());
+                    }
+                });
+
+            ui.label("Type: ");
+            egui::ComboBox::new("log_type_filter", "")
+                .selected_text(
+                    filter
+                        .event_type
+                        .map_or("All".to_string(), |event_type| event_type.to_string()),
+                )
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut filter.event_type, None, "All");
+                    for event_type in LogEventType::ALL {
+                        ui.selectable_value(
+                            &mut filter.event_type,
+                            Some(event_type),
+                            event_type.to_string(),
+                        );
+                    }
+                });
+
+            ui.label("Min severity: ");
+            egui::ComboBox::new("log_severity_filter", "")
+                .selected_text(filter.min_severity.to_string())
+                .show_ui(ui, |ui| {
+                    for severity in Severity::ALL {
+                        ui.selectable_value(
+                            &mut filter.min_severity,
+                            severity,
+                            severity.to_string(),
+                        );
+                    }
+                });
+
+            ui.label("Search: ");
+            ui.text_edit_singleline(&mut filter.search);
+        });
+
+        let shown = self.event_log.apply_filter(&self.log_filter);
+
+        ui.horizontal(|ui| {
+            ui.label(format!("{shown} of {} entries", self.event_log.len()));
+            if let Some(note) = self.event_log.eviction_note() {
+                ui.colored_label(egui::Color32::ORANGE, note);
+            }
+            ui.label("Save to: ");
+            ui.text_edit_singleline(&mut self.log_export_path);
+            if ui.button("Save").clicked() {
+                let entries = self.event_log.eviction_note().into_iter().chain(
+                    (0..shown)
+                        .filter_map(|row| self.event_log.matching_entry(row))
+                        .map(ToString::to_string),
+                );
+                let message = match export_lines(&self.log_export_path, entries) {
+                    Ok(()) => format!("Event log saved to {}", self.log_export_path),
+                    Err(e) => e,
+                };
+                self.snackbar = Some((message, self.snackbar_duration + now));
+            }
+        });
+        ui.separator();
+
+        let row_height = ui.text_style_height(&egui::TextStyle::Body);
+        ScrollArea::vertical().stick_to_bottom(true).show_rows(
+            ui,
+            row_height,
+            shown,
+            |ui, rows| {
+                for entry in rows.filter_map(|row| self.event_log.matching_entry(row)) {
+                    let color = match entry.severity {
+                        Severity::Info => ui.visuals().text_color(),
+                        Severity::Warning => egui::Color32::ORANGE,
+                        Severity::Error => egui::Color32::RED,
+                    };
+                    ui.colored_label(color, entry.to_string());
+                }
+            },
+        );
+    }
+
+    /// Compares the drone implementations using the counters of their drones
+    fn implementations_ui(&self, ui: &mut egui::Ui) {
+        ui.separator();
+
+        egui::Grid::new("implementations_grid")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Implementation");
+                ui.strong("Drones");
+                ui.strong("Forwarded");
+                ui.strong("Dropped");
+                ui.strong("NACKs");
+                ui.strong("Observed drop rate");
+                ui.strong("Mean PDR");
+                ui.end_row();
+
+                for (implementation, stats) in stats_by_implementation(&self.drone_stats) {
+                    ui.label(implementation);
+                    ui.label(stats.drones.to_string());
+                    ui.label(stats.packets_forwarded.to_string());
+                    ui.label(stats.packets_dropped.to_string());
+                    ui.label(stats.nacks_forwarded.to_string());
+                    ui.label(format!("{:.2}", stats.observed_drop_rate()));
+                    ui.label(format!("{:.2}", stats.mean_pdr()));
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// Compares the forwarding latency of the drone implementations, then shows the
+    /// latency histogram of every drone and the end-to-end latency of every session
+    fn latency_ui(&self, ui: &mut egui::Ui) {
+        let ms = |duration: std::time::Duration| format!("{:.3}", duration.as_secs_f64() * 1000.0);
+        let mut by_implementation: BTreeMap<&str, LatencyHistogram> = BTreeMap::new();
+        for (drone_id, histogram) in self.latency.per_drone() {
+            if let Some(stats) = self.drone_stats.get(drone_id) {
+                by_implementation
+                    .entry(&stats.implementation)
+                    .or_default()
+                    .merge(histogram);
+            }
+        }
+
+        ui.separator();
+        self.skipped_events_warning(ui);
+        ui.label("Forwarding latency by implementation, in ms");
+        egui::Grid::new("latency_implementations")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Implementation");
+                ui.strong("Samples");
+                ui.strong("Mean");
+                ui.strong("Max");
+                ui.end_row();
+                for (implementation, histogram) in &by_implementation {
+                    ui.label(*implementation);
+                    ui.label(histogram.count.to_string());
+                    ui.label(histogram.mean().map_or("-".to_string(), ms));
+                    ui.label(ms(histogram.max));
+                    ui.end_row();
+                }
+            });
+        if by_implementation.is_empty() {
+            ui.label("No packet went through two drones yet");
+        }
+
+        ScrollArea::vertical().show(ui, |ui| {
+            ui.separator();
+            let mut drones = self.latency.per_drone().iter().collect::<Vec<_>>();
+            drones.sort_unstable_by_key(|(drone_id, _)| **drone_id);
+            for (drone_id, histogram) in drones {
+                ui.label(format!(
+                    "Drone {drone_id}: {} samples, mean {} ms, max {} ms",
+                    histogram.count,
+                    histogram.mean().map_or("-".to_string(), ms),
+                    ms(histogram.max)
+                ));
+                Self::latency_histogram_ui(ui, histogram);
+            }
+
+            ui.separator();
+            ui.label("End-to-end fragment latency by session, in ms");
+            egui::Grid::new("latency_sessions")
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.strong("Source");
+                    ui.strong("Session");
+                    ui.strong("Fragments");
+                    ui.strong("Mean");
+                    ui.strong("Max");
+                    ui.end_row();
+                    for ((source, session_id), histogram) in self.latency.per_session() {
+                        ui.label(source.to_string());
+                        ui.label(session_id.to_string());
+                        ui.label(histogram.count.to_string());
+                        ui.label(histogram.mean().map_or("-".to_string(), ms));
+                        ui.label(ms(histogram.max));
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    /// Draws one bar per bucket, scaled on the fullest bucket
+    fn latency_histogram_ui(ui: &mut egui::Ui, histogram: &LatencyHistogram) {
+        let (rect, _) =
+            ui.allocate_exact_size(egui::vec2(ui.available_width(), 60.0), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_stroke(
+            rect,
+            0.0,
+            ui.visuals().widgets.noninteractive.bg_stroke,
+            egui::StrokeKind::Inside,
+        );
+
+        let fullest = histogram.buckets.iter().copied().max().unwrap_or(0).max(1);
+        let width = rect.width() / histogram.buckets.len() as f32;
+        for (index, count) in histogram.buckets.iter().enumerate() {
+            let left = rect.left() + index as f32 * width;
+            let height = (rect.height() - 12.0) * *count as f32 / fullest as f32;
+            painter.rect_filled(
+                egui::Rect::from_min_max(
+                    egui::pos2(left + 2.0, rect.bottom() - 12.0 - height),
+                    egui::pos2(left + width - 2.0, rect.bottom() - 12.0),
+                ),
+                0.0,
+                egui::Color32::LIGHT_BLUE,
+            );
+            painter.text(
+                egui::pos2(left + width / 2.0, rect.bottom()),
+                egui::Align2::CENTER_BOTTOM,
+                format!("{} ({count})", LatencyHistogram::bucket_label(index)),
+                egui::FontId::monospace(10.0),
+                ui.visuals().weak_text_color(),
+            );
+        }
+    }
+
+    /// Shows the critical nodes and links of the current topology
+    fn analysis_ui(&self, ui: &mut egui::Ui) {
+        let analysis = &self.topology_analysis;
+        ui.separator();
+
+        egui::Grid::new("analysis_grid").show(ui, |ui| {
+            ui.label("Critical drones: ");
+            if analysis.articulation_points.is_empty() {
+                ui.label("None");
+            } else {
+                ui.colored_label(
+                    egui::Color32::ORANGE,
+                    format!("{:?}", analysis.articulation_points),
+                );
+            }
+            ui.end_row();
+
+            ui.label("Critical links: ");
+            if analysis.bridges.is_empty() {
+                ui.label("None");
+            } else {
+                let bridges = analysis
+                    .bridges
+                    .iter()
+                    .map(|(node1, node2)| format!("{node1} - {node2}"))
+                    .collect::<Vec<_>>();
+                ui.colored_label(egui::Color32::ORANGE, bridges.join(", "));
+            }
+            ui.end_row();
+
+            ui.label("Diameter: ");
+            match analysis.diameter {
+                Some(diameter) => ui.label(format!("{diameter} hops")),
+                None => ui.colored_label(egui::Color32::RED, "Network is partitioned"),
+            };
+            ui.end_row();
+        });
+
+        ui.separator();
+
+        egui::Grid::new("disjoint_paths_grid")
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Client");
+                ui.strong("Server");
+                ui.strong("Disjoint paths");
+                ui.end_row();
+
+                for (client, server, paths) in &analysis.disjoint_paths {
+                    ui.label(client.to_string());
+                    ui.label(server.to_string());
+                    let color = match paths {
+                        0 => egui::Color32::RED,
+                        1 => egui::Color32::ORANGE,
+                        _ => ui.visuals().text_color(),
+                    };
+                    ui.colored_label(color, paths.to_string());
+                    ui.end_row();
+                }
+            });
+    }
+
+    /// # Panics
+    fn visualization_ui(&mut self, ui: &mut egui::Ui, now: f64) {
+        ui.horizontal(|ui| {
+            ui.label("Shift + drag between two nodes to connect them, right click a node or link for actions");
+            if let Some((ref message, failed, expires)) = self.graph_feedback {
+                if now < expires {
+                    let color = if failed {
+                        egui::Color32::RED
+                    } else {
+                        egui::Color32::GREEN
+                    };
+                    ui.separator();
+                    ui.colored_label(color, message);
+                } else {
+                    self.graph_feedback = None;
+                }
+            }
+        });
+
+        if let Some(command) = self.network_graph.show_ui(ui, now, &self.drone_stats) {
+            // A preview gets no response, it comes back as an event
+            let is_preview = matches!(command, UICommand::PreviewImpact(_));
+            let command_id = self
+                .ui_command_sender
+                .send(command)
+                .expect("Should be able to send the command");
+            if !is_preview {
+                self.awaiting_graph_responses.insert(command_id);
+            }
+        }
+
+        // The crashes and removals of the context menu wait for the confirmation here
+        if let Some(command_id) = Self::impact_preview_ui(
+            ui,
+            &mut self.impact_preview,
+            &self.ui_command_sender,
+            None,
+        ) {
+            self.awaiting_graph_responses.insert(command_id);
+        }
+    }
+
     /// # Panics
     pub fn show_ui(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame, ui: &mut egui::Ui) {
-        // Handle any forwarded events
-        while let Ok(event) = self.forwarded_event_receiver.try_recv() {
-            self.handle_forwarded_event(event);
+        let now = ctx.input(|i| i.time);
+
+        // Handle the forwarded events, keeping the rest for the next frames
+        self.process_forwarded_events(now);
+        let backlog = self.backlog();
+        if backlog > 0 {
+            ctx.request_repaint();
+        }
+        self.anomaly_detector.check_timeouts(now);
+        for alert in self.anomaly_detector.take_unlogged() {
+            self.event_log.push(LogEntry::from_alert(&alert));
         }
 
         ui.separator();
 
         ui.horizontal(|ui| {
             if (ui.button("Visualization")).clicked() {
-                self.selected_tab = 0;
+                self.selected_tab = SelectedTab::Visualization;
+            }
+            if (ui.button("Implementations")).clicked() {
+                self.selected_tab = SelectedTab::Implementations;
+            }
+            if (ui.button("Analysis")).clicked() {
+                self.selected_tab = SelectedTab::Analysis;
+            }
+            let alerts = self.anomaly_detector.alerts().len();
+            if (ui.button(format!("Alerts ({alerts})"))).clicked() {
+                self.selected_tab = SelectedTab::Alerts;
+            }
+            if (ui.button("Event log")).clicked() {
+                self.selected_tab = SelectedTab::EventLog;
+            }
+            if (ui.button("Packet crafting")).clicked() {
+                self.selected_tab = SelectedTab::PacketCrafting;
+            }
+            if (ui.button("Latency")).clicked() {
+                self.selected_tab = SelectedTab::Latency;
+            }
+            if backlog > 0 {
+                let text = if self.animation_stride > 1 {
+                    format!(
+                        "Backlog: {backlog} events, animating 1 packet in {}",
+                        self.animation_stride
+                    )
+                } else {
+                    format!("Backlog: {backlog} events")
+                };
+                ui.colored_label(egui::Color32::ORANGE, text);
+            }
+            if self.skipped_events > 0 {
+                ui.colored_label(
+                    egui::Color32::ORANGE,
+                    format!("Skipped: {} packet events", self.skipped_events),
+                );
             }
             for drone in self.drone_stats.keys() {
                 if ui.button(format!("Drone {drone}")).clicked() {
-                    self.selected_tab = *drone as usize;
+                    self.selected_tab = SelectedTab::Drone(*drone);
+                }
+            }
+            for client in &self.clients {
+                if ui.button(format!("Client {client}")).clicked() {
+                    self.selected_tab = SelectedTab::Client(*client);
+                }
+            }
+            for server in &self.servers {
+                if ui.button(format!("Server {server}")).clicked() {
+                    self.selected_tab = SelectedTab::Server(*server);
                 }
             }
         });
 
-        let now = ctx.input(|i| i.time);
-
-        if self.selected_tab == 0 {
-            self.network_graph.show_ui(ui, now);
-        } else {
-            self.drone_stats_ui(
-                ui,
-                NodeId::try_from(self.selected_tab).expect("Should always be able to convert"),
-                now,
-            );
+        match self.selected_tab {
+            SelectedTab::Visualization => self.visualization_ui(ui, now),
+            SelectedTab::Implementations => self.implementations_ui(ui),
+            SelectedTab::Analysis => self.analysis_ui(ui),
+            SelectedTab::Alerts => self.alerts_ui(ui, now),
+            SelectedTab::EventLog => self.event_log_ui(ui, now),
+            SelectedTab::PacketCrafting => self.packet_crafting_ui(ui, now),
+            SelectedTab::Latency => self.latency_ui(ui),
+            SelectedTab::Drone(drone_id) => self.drone_stats_ui(ui, drone_id, now),
+            SelectedTab::Client(client_id) => self.endpoint_stats_ui(ui, client_id, true),
+            SelectedTab::Server(server_id) => self.endpoint_stats_ui(ui, server_id, false),
         }
 
         if let Some((ref message, expires)) = self.snackbar {
@@ -417,7 +1645,15 @@ impl SimulationControllerUI {
             }
         }
 
-        if let Ok(response) = self.ui_response_receiver.try_recv() {
+        while let Ok((command_id, response)) = self.ui_response_receiver.try_recv() {
+            self.event_log.push(LogEntry::from_response(now, &response));
+            if self.awaiting_graph_responses.remove(&command_id) {
+                let (message, failed) = match &response {
+                    UIResponse::Success(message) => (message.clone(), false),
+                    UIResponse::Falure(message) => (message.clone(), true),
+                };
+                self.graph_feedback = Some((message, failed, self.snackbar_duration + now));
+            }
             match response {
                 UIResponse::Success(message) | UIResponse::Falure(message) => {
                     self.snackbar = Some((message, self.snackbar_duration + now));
@@ -428,7 +1664,10 @@ impl SimulationControllerUI {
 
     fn get_start_dest_from_packet(packet: &Packet) -> (NodeId, NodeId) {
         match packet.pack_type {
-            PacketType::MsgFragment(_) | PacketType::Ack(_) | PacketType::Nack(_) => (
+            PacketType::MsgFragment(_)
+            | PacketType::Ack(_)
+            | PacketType::Nack(_)
+            | PacketType::FloodResponse(_) => (
                 packet
                     .routing_header
                     .previous_hop()
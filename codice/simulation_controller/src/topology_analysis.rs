@@ -0,0 +1,460 @@
+use crate::simulation_controller::NodeType;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use wg_2024::network::NodeId;
+
+// A node of the flow network: a node id and whether it is the exit side
+type FlowNode = (NodeId, bool);
+
+/// Critical nodes and links of the network, computed from the controller's
+/// topology. Only drones forward packets, so clients and servers are never
+/// used as intermediate hops.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TopologyAnalysis {
+    // Drones whose loss partitions the network
+    pub articulation_points: Vec<NodeId>,
+    // Links whose loss partitions the network
+    pub bridges: Vec<(NodeId, NodeId)>,
+    // Longest shortest path in hops, `None` if the network is partitioned
+    pub diameter: Option<usize>,
+    // Node-disjoint paths for every client and server pair
+    pub disjoint_paths: Vec<(NodeId, NodeId, usize)>,
+}
+
+impl TopologyAnalysis {
+    #[must_use]
+    pub fn new(
+        topology: &HashMap<NodeId, HashSet<NodeId>>,
+        node_types: &HashMap<NodeId, NodeType>,
+    ) -> Self {
+        let links = links(topology, node_types);
+
+        let mut articulation_points = links
+            .keys()
+            .filter(|node_id| node_types.get(node_id) == Some(&NodeType::Drone))
+            .filter(|node_id| !is_connected(&without_node(&links, **node_id), node_types))
+            .copied()
+            .collect::<Vec<_>>();
+        articulation_points.sort_unstable();
+
+        let mut bridges = Vec::new();
+        for (&node1, neighbours) in &links {
+            for &node2 in neighbours.iter().filter(|node2| node1 < **node2) {
+                if !is_connected(&without_link(&links, node1, node2), node_types) {
+                    bridges.push((node1, node2));
+                }
+            }
+        }
+        bridges.sort_unstable();
+
+        let mut diameter = Some(0);
+        for &node_id in links.keys() {
+            let distances = distances(&links, node_types, node_id);
+            if distances.len() < links.len() {
+                diameter = None;
+                break;
+            }
+            diameter = diameter.max(distances.values().max().copied());
+        }
+
+        let disjoint_paths = client_server_pairs(node_types)
+            .into_iter()
+            .map(|(client, server)| {
+                (
+                    client,
+                    server,
+                    disjoint_paths(topology, node_types, client, server),
+                )
+            })
+            .collect();
+
+        Self {
+            articulation_points,
+            bridges,
+            diameter,
+            disjoint_paths,
+        }
+    }
+}
+
+/// A topology change that can be previewed before doing it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImpactAction {
+    Crash(NodeId),
+    RemoveConnection(NodeId, NodeId),
+}
+
+impl fmt::Display for ImpactAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImpactAction::Crash(drone_id) => write!(f, "crashing drone {drone_id}"),
+            ImpactAction::RemoveConnection(node1, node2) => {
+                write!(f, "removing link {node1} - {node2}")
+            }
+        }
+    }
+}
+
+/// What an action would do to the client to server paths
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImpactPreview {
+    pub action: ImpactAction,
+    // Pairs whose shortest path grows: hops before and after, `None` if lost
+    pub longer_paths: Vec<(NodeId, NodeId, usize, Option<usize>)>,
+    // Pairs left with at most one disjoint path, with the paths left
+    pub lost_alternatives: Vec<(NodeId, NodeId, usize)>,
+    // The invariant the action breaks, if any
+    pub failed_invariant: Option<String>,
+}
+
+impl ImpactPreview {
+    /// Compares the topology before and after the action
+    #[must_use]
+    pub fn new(
+        action: ImpactAction,
+        before: (
+            &HashMap<NodeId, HashSet<NodeId>>,
+            &HashMap<NodeId, NodeType>,
+        ),
+        after: (
+            &HashMap<NodeId, HashSet<NodeId>>,
+            &HashMap<NodeId, NodeType>,
+        ),
+        failed_invariant: Option<String>,
+    ) -> Self {
+        let mut longer_paths = Vec::new();
+        let mut lost_alternatives = Vec::new();
+
+        for (client, server) in client_server_pairs(after.1) {
+            let hops_before = shortest_path(before.0, before.1, client, server);
+            let hops_after = shortest_path(after.0, after.1, client, server);
+            if let Some(hops_before) = hops_before {
+                if hops_after.is_none_or(|hops_after| hops_after > hops_before) {
+                    longer_paths.push((client, server, hops_before, hops_after));
+                }
+            }
+
+            let paths_after = disjoint_paths(after.0, after.1, client, server);
+            if disjoint_paths(before.0, before.1, client, server) > 1 && paths_after <= 1 {
+                lost_alternatives.push((client, server, paths_after));
+            }
+        }
+
+        Self {
+            action,
+            longer_paths,
+            lost_alternatives,
+            failed_invariant,
+        }
+    }
+}
+
+/// Every client and server pair, sorted
+#[must_use]
+pub fn client_server_pairs(node_types: &HashMap<NodeId, NodeType>) -> Vec<(NodeId, NodeId)> {
+    let mut pairs = Vec::new();
+    for (client, client_type) in node_types {
+        for (server, server_type) in node_types {
+            if *client_type == NodeType::Client && *server_type == NodeType::Server {
+                pairs.push((*client, *server));
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+/// Hops on the shortest path from `from` to `to`, if there is one
+#[must_use]
+pub fn shortest_path(
+    topology: &HashMap<NodeId, HashSet<NodeId>>,
+    node_types: &HashMap<NodeId, NodeType>,
+    from: NodeId,
+    to: NodeId,
+) -> Option<usize> {
+    distances(&links(topology, node_types), node_types, from)
+        .get(&to)
+        .copied()
+}
+
+/// Number of paths from `from` to `to` sharing no intermediate node
+#[must_use]
+pub fn disjoint_paths(
+    topology: &HashMap<NodeId, HashSet<NodeId>>,
+    node_types: &HashMap<NodeId, NodeType>,
+    from: NodeId,
+    to: NodeId,
+) -> usize {
+    let links = links(topology, node_types);
+    if !links.contains_key(&from) || !links.contains_key(&to) {
+        return 0;
+    }
+
+    // Max flow where every drone is split into an entry and an exit linked
+    // with capacity 1, so that each drone is used by at most one path
+    let entry = |node_id: NodeId| (node_id, false);
+    let exit = |node_id: NodeId| (node_id, true);
+    let mut capacity: HashMap<(FlowNode, FlowNode), usize> = HashMap::new();
+    for (&node_id, neighbours) in &links {
+        if node_types.get(&node_id) == Some(&NodeType::Drone) {
+            capacity.insert((entry(node_id), exit(node_id)), 1);
+        }
+        for &neighbour in neighbours {
+            capacity.insert((exit(node_id), entry(neighbour)), 1);
+        }
+    }
+
+    let source = exit(from);
+    let sink = entry(to);
+    let mut paths = 0;
+    loop {
+        // Breadth first search of an augmenting path in the residual graph
+        let mut parents = HashMap::from([(source, source)]);
+        let mut queue = VecDeque::from([source]);
+        while let Some(current) = queue.pop_front() {
+            if current == sink {
+                break;
+            }
+            let next = capacity
+                .iter()
+                .filter(|((start, end), left)| {
+                    *start == current && **left > 0 && !parents.contains_key(end)
+                })
+                .map(|((_, end), _)| *end)
+                .collect::<Vec<_>>();
+            for end in next {
+                parents.insert(end, current);
+                queue.push_back(end);
+            }
+        }
+
+        if !parents.contains_key(&sink) {
+            return paths;
+        }
+
+        let mut current = sink;
+        while current != source {
+            let parent = parents[&current];
+            *capacity
+                .get_mut(&(parent, current))
+                .expect("Should always be an edge of the path") -= 1;
+            *capacity.entry((current, parent)).or_default() += 1;
+            current = parent;
+        }
+        paths += 1;
+    }
+}
+
+/// The links of the topology. A link is only counted when both ends list each
+/// other, so crashed drones, which keep their own neighbour list, are left out.
+fn links(
+    topology: &HashMap<NodeId, HashSet<NodeId>>,
+    node_types: &HashMap<NodeId, NodeType>,
+) -> HashMap<NodeId, HashSet<NodeId>> {
+    let mut links = HashMap::new();
+    for (node_id, node_type) in node_types {
+        let neighbours = topology
+            .get(node_id)
+            .map(|neighbours| {
+                neighbours
+                    .iter()
+                    .filter(|neighbour| {
+                        topology
+                            .get(neighbour)
+                            .is_some_and(|neighbours| neighbours.contains(node_id))
+                    })
+                    .copied()
+                    .collect::<HashSet<_>>()
+            })
+            .unwrap_or_default();
+        if *node_type != NodeType::Drone || !neighbours.is_empty() {
+            links.insert(*node_id, neighbours);
+        }
+    }
+    links
+}
+
+fn without_node(
+    links: &HashMap<NodeId, HashSet<NodeId>>,
+    node_id: NodeId,
+) -> HashMap<NodeId, HashSet<NodeId>> {
+    let mut links = links.clone();
+    links.remove(&node_id);
+    for neighbours in links.values_mut() {
+        neighbours.remove(&node_id);
+    }
+    links
+}
+
+fn without_link(
+    links: &HashMap<NodeId, HashSet<NodeId>>,
+    node1: NodeId,
+    node2: NodeId,
+) -> HashMap<NodeId, HashSet<NodeId>> {
+    let mut links = links.clone();
+    if let Some(neighbours) = links.get_mut(&node1) {
+        neighbours.remove(&node2);
+    }
+    if let Some(neighbours) = links.get_mut(&node2) {
+        neighbours.remove(&node1);
+    }
+    links
+}
+
+/// Hops from `start` to every node it can reach, going only through drones
+fn distances(
+    links: &HashMap<NodeId, HashSet<NodeId>>,
+    node_types: &HashMap<NodeId, NodeType>,
+    start: NodeId,
+) -> HashMap<NodeId, usize> {
+    let mut distances = HashMap::new();
+    if !links.contains_key(&start) {
+        return distances;
+    }
+    distances.insert(start, 0);
+    let mut queue = VecDeque::from([start]);
+    while let Some(current) = queue.pop_front() {
+        if current != start && node_types.get(&current) != Some(&NodeType::Drone) {
+            continue;
+        }
+        let distance = distances[&current];
+        for neighbour in &links[&current] {
+            if !distances.contains_key(neighbour) {
+                distances.insert(*neighbour, distance + 1);
+                queue.push_back(*neighbour);
+            }
+        }
+    }
+    distances
+}
+
+fn is_connected(
+    links: &HashMap<NodeId, HashSet<NodeId>>,
+    node_types: &HashMap<NodeId, NodeType>,
+) -> bool {
+    // Starting from a drone every node is reachable iff the network is connected
+    let start = links
+        .keys()
+        .find(|node_id| node_types.get(node_id) == Some(&NodeType::Drone))
+        .or_else(|| links.keys().next());
+    start.is_none_or(|start| distances(links, node_types, *start).len() == links.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 5 - 1 - 2 - 7
+    //      \  |  /
+    //        3 - 4
+    fn network() -> (HashMap<NodeId, HashSet<NodeId>>, HashMap<NodeId, NodeType>) {
+        let topology = HashMap::from([
+            (1, HashSet::from([2, 3, 5])),
+            (2, HashSet::from([1, 3, 7])),
+            (3, HashSet::from([1, 2, 4, 7])),
+            (4, HashSet::from([3])),
+            (5, HashSet::from([1])),
+            (7, HashSet::from([2, 3])),
+        ]);
+        let node_types = HashMap::from([
+            (1, NodeType::Drone),
+            (2, NodeType::Drone),
+            (3, NodeType::Drone),
+            (4, NodeType::Drone),
+            (5, NodeType::Client),
+            (7, NodeType::Server),
+        ]);
+        (topology, node_types)
+    }
+
+    #[test]
+    fn test_critical_nodes_and_links() {
+        let (topology, node_types) = network();
+        let analysis = TopologyAnalysis::new(&topology, &node_types);
+        assert_eq!(analysis.articulation_points, vec![1, 3]);
+        assert_eq!(analysis.bridges, vec![(1, 5), (3, 4)]);
+        assert_eq!(analysis.diameter, Some(3));
+        assert_eq!(analysis.disjoint_paths, vec![(5, 7, 1)]);
+    }
+
+    #[test]
+    fn test_disjoint_paths() {
+        let (mut topology, node_types) = network();
+        assert_eq!(disjoint_paths(&topology, &node_types, 1, 7), 2);
+
+        // A second link from the client adds a path through drone 3
+        topology.get_mut(&5).unwrap().insert(3);
+        topology.get_mut(&3).unwrap().insert(5);
+        assert_eq!(disjoint_paths(&topology, &node_types, 5, 7), 2);
+    }
+
+    #[test]
+    fn test_paths_do_not_cross_servers() {
+        let (mut topology, mut node_types) = network();
+        // Drone 4 can only be reached through server 8
+        topology.get_mut(&3).unwrap().remove(&4);
+        topology.insert(4, HashSet::from([8]));
+        topology.insert(8, HashSet::from([2, 4]));
+        topology.get_mut(&2).unwrap().insert(8);
+        node_types.insert(8, NodeType::Server);
+
+        assert_eq!(shortest_path(&topology, &node_types, 5, 8), Some(3));
+        assert_eq!(shortest_path(&topology, &node_types, 5, 4), None);
+        assert_eq!(TopologyAnalysis::new(&topology, &node_types).diameter, None);
+    }
+
+    #[test]
+    fn test_impact_preview() {
+        let (before, node_types) = network();
+        let mut after = before.clone();
+        // Removing link 1 - 2 leaves 5 with a path of the same length through drone 3
+        after.get_mut(&1).unwrap().remove(&2);
+        after.get_mut(&2).unwrap().remove(&1);
+
+        let action = ImpactAction::RemoveConnection(1, 2);
+        let preview =
+            ImpactPreview::new(action, (&before, &node_types), (&after, &node_types), None);
+        assert_eq!(preview.longer_paths, vec![]);
+        assert_eq!(preview.lost_alternatives, vec![]);
+
+        // Without link 1 - 3 as well, 5 can't reach the server anymore
+        after.get_mut(&1).unwrap().remove(&3);
+        after.get_mut(&3).unwrap().remove(&1);
+        let preview = ImpactPreview::new(
+            action,
+            (&before, &node_types),
+            (&after, &node_types),
+            Some("Network is not fully connected".to_string()),
+        );
+        assert_eq!(preview.longer_paths, vec![(5, 7, 3, None)]);
+    }
+
+    #[test]
+    fn test_impact_preview_lost_alternatives() {
+        let (mut before, node_types) = network();
+        before.get_mut(&5).unwrap().insert(3);
+        before.get_mut(&3).unwrap().insert(5);
+        let mut after = before.clone();
+        after.get_mut(&5).unwrap().remove(&1);
+        after.get_mut(&1).unwrap().remove(&5);
+
+        let preview = ImpactPreview::new(
+            ImpactAction::RemoveConnection(1, 5),
+            (&before, &node_types),
+            (&after, &node_types),
+            None,
+        );
+        assert_eq!(preview.longer_paths, vec![]);
+        assert_eq!(preview.lost_alternatives, vec![(5, 7, 1)]);
+    }
+
+    #[test]
+    fn test_crashed_drone_is_ignored() {
+        let (mut topology, node_types) = network();
+        // After a crash the neighbours forget the drone, but its own entry stays
+        topology.get_mut(&3).unwrap().remove(&4);
+        let analysis = TopologyAnalysis::new(&topology, &node_types);
+        assert_eq!(analysis.articulation_points, vec![1]);
+        assert_eq!(analysis.diameter, Some(3));
+    }
+}
@@ -0,0 +1,138 @@
+use crate::node_stats::hop_endpoints;
+use std::collections::HashMap;
+use std::time::Duration;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Packet, PacketType};
+
+/// How often the controller sends the UI the packets it counted
+pub const TRAFFIC_TICK: Duration = Duration::from_millis(100);
+
+/// Packets of each type
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PacketCounts {
+    pub fragments: u32,
+    pub acks: u32,
+    pub nacks: u32,
+    pub flood_requests: u32,
+    pub flood_responses: u32,
+}
+
+impl PacketCounts {
+    pub fn count(&mut self, packet: &Packet) {
+        match packet.pack_type {
+            PacketType::MsgFragment(_) => self.fragments += 1,
+            PacketType::Ack(_) => self.acks += 1,
+            PacketType::Nack(_) => self.nacks += 1,
+            PacketType::FloodRequest(_) => self.flood_requests += 1,
+            PacketType::FloodResponse(_) => self.flood_responses += 1,
+        }
+    }
+
+    #[must_use]
+    pub fn total(&self) -> u32 {
+        self.fragments + self.acks + self.nacks + self.flood_requests + self.flood_responses
+    }
+}
+
+/// Packets a drone forwarded and dropped
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DroneTraffic {
+    pub forwarded: PacketCounts,
+    pub dropped: u32,
+}
+
+/// Packets that left or reached a client or server, as seen from the drones next to it
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EndpointTraffic {
+    pub sent: PacketCounts,
+    pub received: PacketCounts,
+    // Packets sent by this node that a drone dropped
+    pub lost: u32,
+}
+
+/// Packets the controller counted since it last sent the counters, so that the UI
+/// updates its statistics once per tick instead of once per packet
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrafficCounters {
+    pub drones: HashMap<NodeId, DroneTraffic>,
+    pub endpoints: HashMap<NodeId, EndpointTraffic>,
+    // Packet events skipped because the channel to the UI was full
+    pub skipped_events: u32,
+}
+
+impl TrafficCounters {
+    /// Counts a packet `drone_id` forwarded
+    pub fn packet_sent(&mut self, drone_id: NodeId, packet: &Packet) {
+        self.drones
+            .entry(drone_id)
+            .or_default()
+            .forwarded
+            .count(packet);
+        for (endpoint, sent) in hop_endpoints(packet) {
+            let traffic = self.endpoints.entry(endpoint).or_default();
+            if sent {
+                traffic.sent.count(packet);
+            } else {
+                traffic.received.count(packet);
+            }
+        }
+    }
+
+    /// Counts a packet `drone_id` dropped
+    pub fn packet_dropped(&mut self, drone_id: NodeId, packet: &Packet) {
+        self.drones.entry(drone_id).or_default().dropped += 1;
+        if let Some(source) = packet.routing_header.hops.first() {
+            let traffic = self.endpoints.entry(*source).or_default();
+            // Dropped on its first hop, the packet was never seen leaving the source
+            if packet.routing_header.hop_index == 1 {
+                traffic.sent.count(packet);
+            }
+            traffic.lost += 1;
+        }
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.drones.is_empty() && self.endpoints.is_empty() && self.skipped_events == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wg_2024::network::SourceRoutingHeader;
+    use wg_2024::packet::{Fragment, FRAGMENT_DSIZE};
+
+    fn fragment(hop_index: usize) -> Packet {
+        Packet::new_fragment(
+            SourceRoutingHeader::new(vec![10, 1, 2, 20], hop_index),
+            1,
+            Fragment::new(0, 1, [0; FRAGMENT_DSIZE]),
+        )
+    }
+
+    #[test]
+    fn test_transfer_counted_once_per_hop() {
+        let mut counters = TrafficCounters::default();
+        counters.packet_sent(1, &fragment(2));
+        counters.packet_sent(2, &fragment(3));
+
+        assert_eq!(counters.drones[&1].forwarded.fragments, 1);
+        assert_eq!(counters.drones[&2].forwarded.total(), 1);
+        assert_eq!(counters.endpoints[&10].sent.fragments, 1);
+        assert_eq!(counters.endpoints[&20].received.fragments, 1);
+    }
+
+    #[test]
+    fn test_drop_on_first_hop_counts_as_sent() {
+        let mut counters = TrafficCounters::default();
+        counters.packet_dropped(1, &fragment(1));
+        counters.packet_sent(1, &fragment(2));
+        counters.packet_dropped(2, &fragment(2));
+
+        assert_eq!(counters.drones[&1].dropped, 1);
+        assert_eq!(counters.drones[&2].dropped, 1);
+        assert_eq!(counters.endpoints[&10].sent.fragments, 2);
+        assert_eq!(counters.endpoints[&10].lost, 2);
+    }
+}
@@ -1,14 +1,54 @@
+use crate::pdr_profile::PdrProfile;
+use crate::topology_analysis::ImpactAction;
+use crossbeam_channel::Sender;
+use std::sync::atomic::{AtomicU64, Ordering};
 use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
 
-#[derive(Clone, Copy)]
+/// Copied by the controller from a command to its response
+pub type CommandId = u64;
+
+#[derive(Clone)]
 pub enum UICommand {
     CrashDrone(NodeId),
     SetPDR(NodeId, f32),
+    SetPDRProfile(NodeId, Option<PdrProfile>),
     AddConnection(NodeId, NodeId),
     RemoveConnection(NodeId, NodeId),
+    PreviewImpact(ImpactAction),
+    InjectPacket(NodeId, Packet),
+    InspectEndpoint(NodeId),
 }
 
 pub enum UIResponse {
     Success(String),
     Falure(String),
 }
+
+/// Sends the UI commands to the controller, each one with a new id
+pub struct CommandSender {
+    sender: Sender<(CommandId, UICommand)>,
+    next_id: AtomicU64,
+}
+
+impl CommandSender {
+    #[must_use]
+    pub fn new(sender: Sender<(CommandId, UICommand)>) -> Self {
+        Self {
+            sender,
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Sends `command` and returns the id its response will have
+    ///
+    /// # Errors
+    /// Returns an error if the controller stopped receiving commands
+    pub fn send(&self, command: UICommand) -> Result<CommandId, String> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.sender
+            .send((id, command))
+            .map(|()| id)
+            .map_err(|_| "The controller stopped receiving commands".to_string())
+    }
+}
@@ -0,0 +1,110 @@
+use common::introspection::DiscoveredView;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use wg_2024::network::NodeId;
+
+/// Differences between the view a client or server discovered and the real network
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ViewDiff {
+    /// Links of the network the endpoint does not know about
+    pub missing_edges: Vec<(NodeId, NodeId)>,
+    /// Links the endpoint knows about that are not in the network anymore
+    pub stale_edges: Vec<(NodeId, NodeId)>,
+    /// Crashed drones still in the view
+    pub crashed_nodes: Vec<NodeId>,
+    /// Nodes of the network the endpoint never discovered
+    pub unknown_nodes: Vec<NodeId>,
+}
+
+impl ViewDiff {
+    /// Compares the view with the topology, ignoring the links of the crashed drones
+    #[must_use]
+    pub fn new(
+        view: &DiscoveredView,
+        topology: &HashMap<NodeId, HashSet<NodeId>>,
+        crashed: &HashSet<NodeId>,
+    ) -> Self {
+        let real_edges = edges(topology, crashed);
+        let known_edges = edges(&view.graph, &HashSet::new());
+
+        let mut unknown_nodes = topology
+            .keys()
+            .filter(|node_id| !crashed.contains(node_id) && !view.graph.contains_key(node_id))
+            .copied()
+            .collect::<Vec<_>>();
+        unknown_nodes.sort_unstable();
+        let mut crashed_nodes = view
+            .graph
+            .keys()
+            .filter(|node_id| crashed.contains(node_id))
+            .copied()
+            .collect::<Vec<_>>();
+        crashed_nodes.sort_unstable();
+
+        Self {
+            missing_edges: real_edges.difference(&known_edges).copied().collect(),
+            stale_edges: known_edges.difference(&real_edges).copied().collect(),
+            crashed_nodes,
+            unknown_nodes,
+        }
+    }
+
+    /// Whether the view matches the network
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.missing_edges.is_empty()
+            && self.stale_edges.is_empty()
+            && self.crashed_nodes.is_empty()
+            && self.unknown_nodes.is_empty()
+    }
+}
+
+/// Every link between two nodes that are not excluded, lower id first
+fn edges(
+    graph: &HashMap<NodeId, HashSet<NodeId>>,
+    excluded: &HashSet<NodeId>,
+) -> BTreeSet<(NodeId, NodeId)> {
+    graph
+        .iter()
+        .filter(|(node_id, _)| !excluded.contains(node_id))
+        .flat_map(|(node_id, neighbours)| {
+            neighbours
+                .iter()
+                .filter(|neighbour| !excluded.contains(neighbour))
+                .map(|neighbour| (*node_id.min(neighbour), *node_id.max(neighbour)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_view_diff() {
+        // Client 5 - drone 1 - server 7, with drone 2 linked to 1 and 7 and drone 3 crashed
+        let topology = HashMap::from([
+            (1, HashSet::from([2, 5, 7])),
+            (2, HashSet::from([1, 7])),
+            (3, HashSet::from([5])),
+            (5, HashSet::from([1])),
+            (7, HashSet::from([1, 2])),
+        ]);
+        let crashed = HashSet::from([3]);
+        let discovered = HashMap::from([(5, vec![1, 3]), (1, vec![7]), (7, vec![4])]);
+        let view = DiscoveredView::from_graph(5, &discovered);
+
+        let diff = ViewDiff::new(&view, &topology, &crashed);
+        assert_eq!(diff.missing_edges, vec![(1, 2), (2, 7)]);
+        assert_eq!(diff.stale_edges, vec![(3, 5), (4, 7)]);
+        assert_eq!(diff.crashed_nodes, vec![3]);
+        assert_eq!(diff.unknown_nodes, vec![2]);
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn test_matching_view() {
+        let topology = HashMap::from([(1, HashSet::from([5])), (5, HashSet::from([1]))]);
+        let view = DiscoveredView::from_graph(5, &HashMap::from([(5, vec![1])]));
+        assert!(ViewDiff::new(&view, &topology, &HashSet::new()).is_empty());
+    }
+}